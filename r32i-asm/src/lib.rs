@@ -33,6 +33,17 @@ use std::str::FromStr;
 // import necessary mods ------------OK
 #[proc_macro]
 pub fn riscv_asm(input: TokenStream) -> TokenStream {
+    assemble(input, "crate::register::alias")
+}
+
+// riscv_asm! for RV32E, only the ilp32e register names are in scope, so
+// x16..x31 don't assemble
+#[proc_macro]
+pub fn rv32e_asm(input: TokenStream) -> TokenStream {
+    assemble(input, "crate::register::alias::ilp32e")
+}
+
+fn assemble(input: TokenStream, aliases: &str) -> TokenStream {
     // split to lines
     let mut vec = input.into_iter().collect::<Vec<_>>();
 
@@ -49,8 +60,25 @@ pub fn riscv_asm(input: TokenStream) -> TokenStream {
 
     process_data_directive(&mut instructions);
 
+    // lr.w -> lr_w, amoadd.w.aqrl -> amoadd_w_aqrl
+    instructions.iter_mut().for_each(merge_dotted_mnemonic);
+
+    // fadd.s ft0, ft1, ft2, rtz -> fadd_s_rm(..., rtz), dyn is the default
+    instructions.iter_mut().for_each(rounding_mode);
+
     // allow -4(a0)
     instructions.iter_mut().for_each(|tokens| {
+        // and (a0) on its own, for lr.w/sc.w/amo*.w
+        if let [.., TokenTree::Punct(p), TokenTree::Group(g)] = &tokens[..]
+            && p.as_char() == ','
+            && g.delimiter() == Delimiter::Parenthesis
+        {
+            let TokenTree::Group(g) = tokens.pop().unwrap() else {
+                unreachable!()
+            };
+            tokens.extend(g.stream());
+            return;
+        }
         if let [.., literal, group] = &tokens[..] {
             if let TokenTree::Literal(_) = literal {
                 if let TokenTree::Group(_) = group {
@@ -77,6 +105,16 @@ pub fn riscv_asm(input: TokenStream) -> TokenStream {
         }
     });
 
+    // the halfword each line starts at, compressed instructions take one
+    let positions: Vec<i32> = instructions
+        .iter()
+        .scan(0, |position, tokens| {
+            let here = *position;
+            *position += halfwords(tokens);
+            Some(here)
+        })
+        .collect();
+
     // record the labels
     let labels: HashMap<String, i32> = instructions
         .iter()
@@ -84,34 +122,26 @@ pub fn riscv_asm(input: TokenStream) -> TokenStream {
         .filter_map(|(i, token)| {
             if let [TokenTree::Ident(id), TokenTree::Punct(p), ..] = &token[..] {
                 if p.as_char() == ':' {
-                    return Some((id.to_string(), i as i32));
+                    return Some((id.to_string(), positions[i]));
                 }
             }
             None
         })
         .collect();
 
-    // replace the label offset
+    // replace the label offset, in halfwords
     instructions.iter_mut().enumerate().for_each(|(i, tokens)| {
         if let [.., token] = &mut tokens[..] {
             let key = token.to_string();
             if let Some(&target) = labels.get(&key) {
-                *token = TokenTree::Literal(Literal::i32_unsuffixed((target - i as i32) * 2));
+                *token = TokenTree::Literal(Literal::i32_unsuffixed(target - positions[i]));
             }
         }
     });
 
-    let mut output = TokenStream::new();
-    instructions
+    let lines = instructions
         .iter_mut()
-        .map(|tokens| {
-            if let [TokenTree::Ident(_), TokenTree::Punct(p), ..] = &tokens[..] {
-                if p.as_char() == ':' {
-                    return &mut tokens[2..];
-                }
-            }
-            tokens
-        })
+        .map(|tokens| strip_label(tokens))
         .filter(|tokens| !tokens.is_empty())
         .map(|tokens| {
             if let [TokenTree::Ident(i), ..] = tokens {
@@ -119,31 +149,32 @@ pub fn riscv_asm(input: TokenStream) -> TokenStream {
             }
             tokens
         })
-        .for_each(|tokens| {
+        .map(|tokens| {
+            let compressed = is_compressed(tokens);
             if let TokenTree::Literal(_) = &tokens[0] {
-                output.extend(Some(tokens[0].clone()));
-                output.extend(Some(TokenTree::Punct(Punct::new(',', Spacing::Alone))));
-                return;
+                return (TokenStream::from(tokens[0].clone()), compressed);
             }
 
-            let mut v = Vec::new();
-            v.push(tokens[0].clone());
-            v.push(TokenTree::Group(Group::new(
+            let mut v = TokenStream::new();
+            v.extend(Some(tokens[0].clone()));
+            v.extend(Some(TokenTree::Group(Group::new(
                 Delimiter::Parenthesis,
                 TokenStream::from_iter(tokens.iter().cloned().skip(1)),
-            )));
-            v.push(TokenTree::Punct(Punct::new(',', Spacing::Alone)));
-            output.extend(v);
-        });
+            ))));
+            (v, compressed)
+        })
+        .collect();
 
-    TokenStream::from(wrap_and_import(output))
+    let output = pack(lines);
+    TokenStream::from(wrap_and_import(output, aliases))
 }
 
-fn wrap_and_import(output: TokenStream) -> TokenTree {
+fn wrap_and_import(output: TokenStream, aliases: &str) -> TokenTree {
     // import mods
-    let mut import_mods = TokenStream::from_str(concat!(
-        "use crate::instruct_info::prelude::*;",
-        "use crate::register::alias::*;"
+    let mut import_mods = TokenStream::from_str(&format!(
+        "use crate::instruct_info::prelude::*;\
+         use {aliases}::*;\
+         use crate::register::csr::alias::*;"
     ))
     .expect("import mods");
     let borrow_code = [
@@ -156,6 +187,105 @@ fn wrap_and_import(output: TokenStream) -> TokenTree {
     TokenTree::Group(Group::new(Delimiter::Brace, import_mods))
 }
 
+fn strip_label(tokens: &mut [TokenTree]) -> &mut [TokenTree] {
+    if let [TokenTree::Ident(_), TokenTree::Punct(p), ..] = &tokens[..] {
+        if p.as_char() == ':' {
+            return &mut tokens[2..];
+        }
+    }
+    tokens
+}
+
+// c.addi and the like, merged into c_addi by now
+fn is_compressed(tokens: &[TokenTree]) -> bool {
+    matches!(
+        tokens.first(),
+        Some(TokenTree::Ident(id)) if id.to_string().to_ascii_lowercase().starts_with("c_")
+    )
+}
+
+fn halfwords(tokens: &[TokenTree]) -> i32 {
+    let mut tokens = tokens.to_vec();
+    match strip_label(&mut tokens) {
+        [] => 0,
+        tokens if is_compressed(tokens) => 1,
+        _ => 2,
+    }
+}
+
+// Puts the lines into words, compressed instructions two to a word. A 32-bit
+// instruction after an odd number of them straddles two words.
+fn pack(lines: Vec<(TokenStream, bool)>) -> TokenStream {
+    let mut output = TokenStream::new();
+    let mut push = |word: TokenStream| {
+        output.extend(word);
+        output.extend(Some(TokenTree::Punct(Punct::new(',', Spacing::Alone))));
+    };
+    let parse = |expression: String| TokenStream::from_str(&expression).expect("packed word");
+    // the low half of a word whose high half comes next
+    let mut low: Option<String> = None;
+    for (line, compressed) in lines {
+        match (compressed, low.take()) {
+            (false, None) => push(line),
+            (true, None) => low = Some(format!("({}) as u32", line)),
+            (true, Some(half)) => push(parse(format!("{} | (({}) as u32) << 16", half, line))),
+            (false, Some(half)) => {
+                push(parse(format!("{} | ({}) << 16", half, line)));
+                low = Some(format!("({}) >> 16", line));
+            }
+        }
+    }
+    // the high half is left zero
+    if let Some(half) = low {
+        push(parse(half));
+    }
+    output
+}
+
+fn merge_dotted_mnemonic(tokens: &mut Vec<TokenTree>) {
+    let mut i = 0;
+    while i + 2 < tokens.len() {
+        if let [TokenTree::Ident(head), TokenTree::Punct(p), TokenTree::Ident(tail)] =
+            &tokens[i..i + 3]
+            && p.as_char() == '.'
+        {
+            let merged = Ident::new(&format!("{}_{}", head, tail), head.span());
+            tokens.splice(i..i + 3, [TokenTree::Ident(merged)]);
+            continue;
+        }
+        i += 1;
+    }
+}
+
+fn rounding_mode(tokens: &mut Vec<TokenTree>) {
+    let [.., TokenTree::Punct(p), TokenTree::Ident(rm)] = &tokens[..] else {
+        return;
+    };
+    if p.as_char() != ',' {
+        return;
+    }
+    match rm.to_string().as_str() {
+        "dyn" => {
+            tokens.truncate(tokens.len() - 2);
+        }
+        "rne" | "rtz" | "rdn" | "rup" | "rmm" => {
+            // past the label, if there is one
+            let i = if let [TokenTree::Ident(_), TokenTree::Punct(p), ..] = &tokens[..]
+                && p.as_char() == ':'
+            {
+                2
+            } else {
+                0
+            };
+            if let Some(TokenTree::Ident(mnemonic)) = tokens.get(i) {
+                let renamed = Ident::new(&format!("{}_rm", mnemonic), mnemonic.span());
+                tokens[i] = TokenTree::Ident(renamed);
+            }
+        }
+        _ => {}
+    }
+}
+
 fn process_data_directive(instructions: &mut Vec<Vec<TokenTree>>) {
     let mut byte_address = 0;
     instructions.iter_mut().for_each(|tokens| {
@@ -6,8 +6,8 @@ pub fn register_aliases(_: TokenStream) -> TokenStream {
     (0..32)
         .map(|i| {
             format!(
-                "pub const x{}: u8 = {{ {} }};",
-                i, i
+                "pub const x{}: u8 = {{ {} }};\npub const f{}: u8 = {{ {} }};",
+                i, i, i, i
             )
         })
         .collect::<Vec<_>>()
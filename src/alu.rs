@@ -1,8 +1,11 @@
 #![allow(dead_code)]
 
-use crate::arch::Address;
-use crate::instruction_type::{BInstruction, IInstruction, ISInstruction, RInstruction};
-use crate::register::{Registers};
+use crate::arch::{Address, Byte, PC_STEP, Xlen};
+use crate::exception::Exception;
+use crate::float::{DOUBLE, Format, SINGLE};
+use crate::instruction_type::{BInstruction, IInstruction, Instruction, RInstruction};
+use crate::opcode::{MADD, MSUB, NMADD, NMSUB, OP_FP};
+use crate::register::{Register, Registers};
 
 pub struct ALU<'a>(&'a mut Registers);
 
@@ -11,179 +14,551 @@ impl<'a> ALU<'a> {
         Self(registers)
     }
 
-    const fn add(&mut self, r: RInstruction) {
-        *self.0.get_mut(r.rd()) = self.0.get(r.rs1()).overflowing_add(self.0.get(r.rs2())).0
-    }
-
-    const fn sub(&mut self, r: RInstruction) {
-        *self.0.get_mut(r.rd()) = self.0.get(r.rs1()).overflowing_sub(self.0.get(r.rs2())).0
-    }
-
-    const fn xor(&mut self, r: RInstruction) {
-        *self.0.get_mut(r.rd()) = self.0.get(r.rs1()) ^ self.0.get(r.rs2())
-    }
-
-    const fn and(&mut self, r: RInstruction) {
-        *self.0.get_mut(r.rd()) = self.0.get(r.rs1()) & self.0.get(r.rs2())
-    }
-
-    const fn or(&mut self, r: RInstruction) {
-        *self.0.get_mut(r.rd()) = self.0.get(r.rs1()) | self.0.get(r.rs2())
-    }
-
-    const fn sll(&mut self, r: RInstruction) {
-        *self.0.get_mut(r.rd()) = self.0.get(r.rs1()) << self.0.get(r.rs2())
-    }
-
-    const fn slt(&mut self, r: RInstruction) {
-        *self.0.get_mut(r.rd()) = if (self.0.get(r.rs1()) as i32) < (self.0.get(r.rs2()) as i32) {
-            1
-        } else {
-            0
-        }
-    }
-
-    const fn sltu(&mut self, r: RInstruction) {
-        *self.0.get_mut(r.rd()) = if self.0.get(r.rs1()) < self.0.get(r.rs2()) {
-            1
-        } else {
-            0
+    // TODO: fix btype
+    const fn jump(pc: &mut Address, b: BInstruction) -> Result<(), Exception> {
+        let target = (*pc - PC_STEP).wrapping_add_signed(b.imm() as i64);
+        if !target.is_multiple_of(PC_STEP) {
+            return Err(Exception::InstructionAddressMisaligned(target));
         }
+        *pc = target;
+        Ok(())
     }
 
-    const fn srl(&mut self, r: RInstruction) {
-        *self.0.get_mut(r.rd()) = self.0.get(r.rs1()) >> self.0.get(r.rs2())
-    }
-
-    const fn sra(&mut self, r: RInstruction) {
-        *self.0.get_mut(r.rd()) =
-            ((self.0.get(r.rs1()) as i32) >> (self.0.get(r.rs2()) as i32)) as u32;
+    pub const fn execute(&mut self, r: RInstruction) -> Result<(), Exception> {
+        let Some(op) = AluOp::register(&r, Xlen::X32) else {
+            return Err(Exception::IllegalInstruction(r.0.0));
+        };
+        *self.0.get_mut(r.rd()) = op.apply(Xlen::X32, self.0.get(r.rs1()), self.0.get(r.rs2()));
+        Ok(())
     }
 
-    // TODO: fix btype
-    const fn beq(&mut self, pc: &mut Address, b: BInstruction) {
-        if *self.0.get_mut(b.rs1()) == self.0.get(b.rs2()) {
-            *pc = ((*pc - 4) as i32 + b.imm()) as u32;
+    pub const fn branch(&mut self, pc: &mut Address, b: BInstruction) -> Result<(), Exception> {
+        let Some(op) = BranchOp::decode(&b) else {
+            return Err(Exception::IllegalInstruction(b.0.0));
+        };
+        if op.taken(Xlen::X32, self.0.get(b.rs1()), self.0.get(b.rs2())) {
+            return Self::jump(pc, b);
         }
+        Ok(())
     }
 
-    const fn bne(&mut self, pc: &mut Address, b: BInstruction) {
-        if *self.0.get_mut(b.rs1()) != self.0.get(b.rs2()) {
-            *pc = ((*pc - 4) as i32 + b.imm()) as u32;
-        }
+    pub const fn immediate(&mut self, i: IInstruction) -> Result<(), Exception> {
+        let Some((op, operand)) = AluOp::immediate(&i, Xlen::X32) else {
+            return Err(Exception::IllegalInstruction(i.0.0));
+        };
+        *self.0.get_mut(i.rd()) = op.apply(Xlen::X32, self.0.get(i.rs1()), operand);
+        Ok(())
     }
+}
 
-    const fn blt(&mut self, pc: &mut Address, b: BInstruction) {
-        if (*self.0.get_mut(b.rs1()) as i32) < (self.0.get(b.rs2()) as i32) {
-            *pc = ((*pc - 4) as i32 + b.imm()) as u32;
+// the same operations on 32 and 64 bits, `$wide` for the upper halves of
+// the products
+macro_rules! apply {
+    ($name:ident, $u:ty, $i:ty, $wide:ty, $wide_signed:ty) => {
+        #[inline(always)]
+        const fn $name(self, a: $u, b: $u) -> $u {
+            const BITS: u32 = <$u>::BITS;
+            let shamt = b as u32 & (BITS - 1);
+            match self {
+                Self::Add => a.wrapping_add(b),
+                Self::Sub => a.wrapping_sub(b),
+                Self::Sll => a << shamt,
+                Self::Slt => ((a as $i) < (b as $i)) as $u,
+                Self::Sltu => (a < b) as $u,
+                Self::Xor => a ^ b,
+                Self::Srl => a >> shamt,
+                Self::Sra => ((a as $i) >> shamt) as $u,
+                Self::Or => a | b,
+                Self::And => a & b,
+                Self::Mul => a.wrapping_mul(b),
+                Self::Mulh => ((a as $i as $wide_signed * b as $i as $wide_signed) >> BITS) as $u,
+                Self::Mulhsu => ((a as $i as $wide_signed * b as $wide_signed) >> BITS) as $u,
+                Self::Mulhu => ((a as $wide * b as $wide) >> BITS) as $u,
+                // dividing by zero doesn't trap, it gives all ones and leaves the
+                // dividend as the remainder. MIN / -1 wraps back to MIN, remainder 0
+                Self::Div if b == 0 => <$u>::MAX,
+                Self::Div => (a as $i).wrapping_div(b as $i) as $u,
+                Self::Divu if b == 0 => <$u>::MAX,
+                Self::Divu => a / b,
+                Self::Rem if b == 0 => a,
+                Self::Rem => (a as $i).wrapping_rem(b as $i) as $u,
+                Self::Remu if b == 0 => a,
+                Self::Remu => a % b,
+                Self::Sh1add => (a << 1).wrapping_add(b),
+                Self::Sh2add => (a << 2).wrapping_add(b),
+                Self::Sh3add => (a << 3).wrapping_add(b),
+                Self::AddUw => (a as u32 as $u).wrapping_add(b),
+                Self::Sh1addUw => ((a as u32 as $u) << 1).wrapping_add(b),
+                Self::Sh2addUw => ((a as u32 as $u) << 2).wrapping_add(b),
+                Self::Sh3addUw => ((a as u32 as $u) << 3).wrapping_add(b),
+                Self::SlliUw => (a as u32 as $u) << shamt,
+                Self::Andn => a & !b,
+                Self::Orn => a | !b,
+                Self::Xnor => !(a ^ b),
+                Self::Clz => a.leading_zeros() as $u,
+                Self::Ctz => a.trailing_zeros() as $u,
+                Self::Cpop => a.count_ones() as $u,
+                Self::Min if (a as $i) < (b as $i) => a,
+                Self::Max if (a as $i) > (b as $i) => a,
+                Self::Minu if a < b => a,
+                Self::Maxu if a > b => a,
+                Self::Min | Self::Max | Self::Minu | Self::Maxu => b,
+                Self::SextB => a as i8 as $u,
+                Self::SextH => a as i16 as $u,
+                Self::ZextH => a & 0xFFFF,
+                Self::Rol => a.rotate_left(shamt),
+                Self::Ror => a.rotate_right(shamt),
+                Self::OrcB => {
+                    // every byte that isn't zero becomes 0xFF
+                    let mut result = 0;
+                    let mut byte = 0;
+                    while byte < BITS {
+                        if (a >> byte) & 0xFF != 0 {
+                            result |= 0xFF << byte;
+                        }
+                        byte += 8;
+                    }
+                    result
+                }
+                Self::Rev8 => a.swap_bytes(),
+                Self::Bset => a | 1 << shamt,
+                Self::Bclr => a & !(1 << shamt),
+                Self::Binv => a ^ 1 << shamt,
+                Self::Bext => (a >> shamt) & 1,
+            }
         }
-    }
+    };
+}
+/// The operations `OP` and `OP-IMM` share, with the operands already read.
+/// The `*w` instructions of RV64 are the same operations on the low words,
+/// see `apply_word`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AluOp {
+    Add,
+    Sub,
+    Sll,
+    Slt,
+    Sltu,
+    Xor,
+    Srl,
+    Sra,
+    Or,
+    And,
+    Mul,
+    Mulh,
+    Mulhsu,
+    Mulhu,
+    Div,
+    Divu,
+    Rem,
+    Remu,
+    // Zba
+    Sh1add,
+    Sh2add,
+    Sh3add,
+    // the first operand zero-extended from its low word, RV64 only
+    AddUw,
+    Sh1addUw,
+    Sh2addUw,
+    Sh3addUw,
+    SlliUw,
+    // Zbb, the unary ones ignore the second operand
+    Andn,
+    Orn,
+    Xnor,
+    Clz,
+    Ctz,
+    Cpop,
+    Min,
+    Minu,
+    Max,
+    Maxu,
+    SextB,
+    SextH,
+    ZextH,
+    Rol,
+    Ror,
+    OrcB,
+    Rev8,
+    // Zbs
+    Bset,
+    Bclr,
+    Binv,
+    Bext,
+}
 
-    const fn bge(&mut self, pc: &mut Address, b: BInstruction) {
-        if (*self.0.get_mut(b.rs1()) as i32) >= (self.0.get(b.rs2()) as i32) {
-            *pc = ((*pc - 4) as i32 + b.imm()) as u32;
-        }
+impl AluOp {
+    pub const fn register(r: &RInstruction, xlen: Xlen) -> Option<Self> {
+        Some(match (r.funct3(), r.funct7()) {
+            (0b000, 0) => Self::Add,
+            (0b000, 0x20) => Self::Sub,
+            (0b001, 0) => Self::Sll,
+            (0b010, 0) => Self::Slt,
+            (0b011, 0) => Self::Sltu,
+            (0b100, 0) => Self::Xor,
+            (0b101, 0) => Self::Srl,
+            (0b101, 0x20) => Self::Sra,
+            (0b110, 0) => Self::Or,
+            (0b111, 0) => Self::And,
+            // RV32M
+            (0b000, 1) => Self::Mul,
+            (0b001, 1) => Self::Mulh,
+            (0b010, 1) => Self::Mulhsu,
+            (0b011, 1) => Self::Mulhu,
+            (0b100, 1) => Self::Div,
+            (0b101, 1) => Self::Divu,
+            (0b110, 1) => Self::Rem,
+            (0b111, 1) => Self::Remu,
+            // Zba
+            (0b010, 0x10) => Self::Sh1add,
+            (0b100, 0x10) => Self::Sh2add,
+            (0b110, 0x10) => Self::Sh3add,
+            // Zbb
+            (0b111, 0x20) => Self::Andn,
+            (0b110, 0x20) => Self::Orn,
+            (0b100, 0x20) => Self::Xnor,
+            (0b100, 0x05) => Self::Min,
+            (0b101, 0x05) => Self::Minu,
+            (0b110, 0x05) => Self::Max,
+            (0b111, 0x05) => Self::Maxu,
+            (0b001, 0x30) => Self::Rol,
+            (0b101, 0x30) => Self::Ror,
+            // RV64 encodes it in OP-32
+            (0b100, 0x04) if r.rs2() == 0 && matches!(xlen, Xlen::X32) => Self::ZextH,
+            // Zbs
+            (0b001, 0x14) => Self::Bset,
+            (0b001, 0x24) => Self::Bclr,
+            (0b001, 0x34) => Self::Binv,
+            (0b101, 0x24) => Self::Bext,
+            _ => return None,
+        })
     }
 
-    const fn bltu(&mut self, pc: &mut Address, b: BInstruction) {
-        if *self.0.get_mut(b.rs1()) < self.0.get(b.rs2()) {
-            *pc = ((*pc - 4) as i32 + b.imm()) as u32;
-        }
+    /// the operation and its second operand
+    pub const fn immediate(i: &IInstruction, xlen: Xlen) -> Option<(Self, Register)> {
+        let (funct7, shamt) = match xlen {
+            Xlen::X32 => (i.umm() >> 5, i.umm() as Register & 0x1F),
+            // shamt[5] takes the low bit of funct7
+            Xlen::X64 => (i.umm() >> 6 << 1, i.umm() as Register & 0x3F),
+        };
+        Some(match i.funct3() {
+            0b000 => (Self::Add, i.imm() as Register),
+            0b010 => (Self::Slt, i.imm() as Register),
+            // sign-extended like every I-type immediate, sltiu included
+            0b011 => (Self::Sltu, i.imm() as Register),
+            0b100 => (Self::Xor, i.imm() as Register),
+            0b110 => (Self::Or, i.imm() as Register),
+            0b111 => (Self::And, i.imm() as Register),
+            0b001 if funct7 == 0 => (Self::Sll, shamt),
+            0b101 if funct7 == 0 => (Self::Srl, shamt),
+            0b101 if funct7 == 0x20 => (Self::Sra, shamt),
+            // the unary Zbb ops, rs2 picks the operation
+            0b001 => match i.umm() {
+                0x600 => (Self::Clz, 0),
+                0x601 => (Self::Ctz, 0),
+                0x602 => (Self::Cpop, 0),
+                0x604 => (Self::SextB, 0),
+                0x605 => (Self::SextH, 0),
+                _ => match funct7 {
+                    0x14 => (Self::Bset, shamt),
+                    0x24 => (Self::Bclr, shamt),
+                    0x34 => (Self::Binv, shamt),
+                    _ => return None,
+                },
+            },
+            0b101 => match i.umm() {
+                0x287 => (Self::OrcB, 0),
+                0x698 if matches!(xlen, Xlen::X32) => (Self::Rev8, 0),
+                0x6B8 if matches!(xlen, Xlen::X64) => (Self::Rev8, 0),
+                _ => match funct7 {
+                    0x30 => (Self::Ror, shamt),
+                    0x24 => (Self::Bext, shamt),
+                    _ => return None,
+                },
+            },
+            _ => return None,
+        })
     }
 
-    const fn bgeu(&mut self, pc: &mut Address, b: BInstruction) {
-        if *self.0.get_mut(b.rs1()) >= self.0.get(b.rs2()) {
-            *pc = ((*pc - 4) as i32 + b.imm()) as u32;
-        }
+    /// OP-32 of RV64, the ones `apply_word` computes. `add.uw` and the
+    /// `sh*add.uw` are full width and come from `register_unsigned_word`.
+    pub const fn register_word(r: &RInstruction) -> Option<Self> {
+        Some(match (r.funct3(), r.funct7()) {
+            (0b000, 0) => Self::Add,
+            (0b000, 0x20) => Self::Sub,
+            (0b001, 0) => Self::Sll,
+            (0b101, 0) => Self::Srl,
+            (0b101, 0x20) => Self::Sra,
+            (0b000, 1) => Self::Mul,
+            (0b100, 1) => Self::Div,
+            (0b101, 1) => Self::Divu,
+            (0b110, 1) => Self::Rem,
+            (0b111, 1) => Self::Remu,
+            (0b001, 0x30) => Self::Rol,
+            (0b101, 0x30) => Self::Ror,
+            // the upper half of the word is zero, sign-extending leaves it so
+            (0b100, 0x04) if r.rs2() == 0 => Self::ZextH,
+            _ => return None,
+        })
     }
 
-    const fn addi(&mut self, i: IInstruction) {
-        *self.0.get_mut(i.rd()) = (self.0.get(i.rs1()) as i32 + i.imm()) as u32;
+    pub const fn register_unsigned_word(r: &RInstruction) -> Option<Self> {
+        Some(match (r.funct3(), r.funct7()) {
+            (0b000, 0x04) => Self::AddUw,
+            (0b010, 0x10) => Self::Sh1addUw,
+            (0b100, 0x10) => Self::Sh2addUw,
+            (0b110, 0x10) => Self::Sh3addUw,
+            _ => return None,
+        })
     }
 
-    const fn slti(&mut self, i: IInstruction) {
-        *self.0.get_mut(i.rd()) = if (self.0.get(i.rs1()) as i32) < i.imm() {
-            1
-        } else {
-            0
-        };
+    /// OP-IMM-32 of RV64 and the second operand, `slli.uw` being the only
+    /// one `apply_word` isn't for
+    pub const fn immediate_word(i: &IInstruction) -> Option<(Self, Register)> {
+        let shamt = i.umm() as Register & 0x1F;
+        Some(match (i.funct3(), i.umm() >> 5) {
+            (0b000, _) => (Self::Add, i.imm() as Register),
+            (0b001, 0) => (Self::Sll, shamt),
+            (0b101, 0) => (Self::Srl, shamt),
+            (0b101, 0x20) => (Self::Sra, shamt),
+            (0b101, 0x30) => (Self::Ror, shamt),
+            (0b001, _) if i.umm() >> 6 == 0b000010 => (Self::SlliUw, i.umm() as Register & 0x3F),
+            (0b001, _) => match i.umm() {
+                0x600 => (Self::Clz, 0),
+                0x601 => (Self::Ctz, 0),
+                0x602 => (Self::Cpop, 0),
+                _ => return None,
+            },
+            _ => return None,
+        })
     }
 
-    const fn sltiu(&mut self, i: IInstruction) {
-        *self.0.get_mut(i.rd()) = if self.0.get(i.rs1()) < i.umm() { 1 } else { 0 };
+    // inlined so each block loop arm, with `self` known, keeps only its own
+    // operation
+    #[inline(always)]
+    pub const fn apply(self, xlen: Xlen, a: Register, b: Register) -> Register {
+        match xlen {
+            Xlen::X32 => self.apply_32(a as u32, b as u32) as Register,
+            Xlen::X64 => self.apply_64(a, b),
+        }
     }
 
-    const fn xori(&mut self, i: IInstruction) {
-        *self.0.get_mut(i.rd()) = self.0.get(i.rs1()) ^ i.umm();
+    /// The `*w` form: the operation on the low words, the result
+    /// sign-extended. On RV32 `Registers::write` cuts it back down.
+    #[inline(always)]
+    pub const fn apply_word(self, a: Register, b: Register) -> Register {
+        self.apply_32(a as u32, b as u32) as i32 as Register
     }
 
-    const fn ori(&mut self, i: IInstruction) {
-        *self.0.get_mut(i.rd()) = self.0.get(i.rs1()) | i.umm();
-    }
+    apply!(apply_32, u32, i32, u64, i64);
+    apply!(apply_64, u64, i64, u128, i128);
+}
 
-    const fn andi(&mut self, i: IInstruction) {
-        *self.0.get_mut(i.rd()) = self.0.get(i.rs1()) & i.umm();
-    }
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BranchOp {
+    Eq,
+    Ne,
+    Lt,
+    Ge,
+    Ltu,
+    Geu,
+}
 
-    const fn slli(&mut self, i: ISInstruction) {
-        *self.0.get_mut(i.rd()) = self.0.get(i.rs1()) << (i.umm() & 0b11111);
+impl BranchOp {
+    pub const fn decode(b: &BInstruction) -> Option<Self> {
+        Some(match b.funct3() {
+            0b000 => Self::Eq,
+            0b001 => Self::Ne,
+            0b100 => Self::Lt,
+            0b101 => Self::Ge,
+            0b110 => Self::Ltu,
+            0b111 => Self::Geu,
+            _ => return None,
+        })
     }
 
-    const fn srli(&mut self, i: ISInstruction) {
-        *self.0.get_mut(i.rd()) = self.0.get(i.rs1()) >> (i.umm() & 0b11111);
+    pub const fn taken(self, xlen: Xlen, a: Register, b: Register) -> bool {
+        match self {
+            Self::Eq => a == b,
+            Self::Ne => a != b,
+            Self::Lt => xlen.signed(a) < xlen.signed(b),
+            Self::Ge => xlen.signed(a) >= xlen.signed(b),
+            Self::Ltu => a < b,
+            Self::Geu => a >= b,
+        }
     }
+}
+
+/// The read-modify-write operations of the `amo*.w` and `amo*.d`
+/// instructions.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AmoOp {
+    Swap,
+    Add,
+    Xor,
+    And,
+    Or,
+    Min,
+    Max,
+    Minu,
+    Maxu,
+}
 
-    const fn srai(&mut self, i: ISInstruction) {
-        *self.0.get_mut(i.rd()) = (self.0.get(i.rs1()) as i32 >> (i.umm() & 0b11111)) as u32;
+impl AmoOp {
+    /// from funct5, `None` for `lr` and `sc` too
+    pub const fn decode(r: &RInstruction) -> Option<Self> {
+        Some(match r.funct7() >> 2 {
+            0b00001 => Self::Swap,
+            0b00000 => Self::Add,
+            0b00100 => Self::Xor,
+            0b01100 => Self::And,
+            0b01000 => Self::Or,
+            0b10000 => Self::Min,
+            0b10100 => Self::Max,
+            0b11000 => Self::Minu,
+            0b11100 => Self::Maxu,
+            _ => return None,
+        })
     }
 
-    pub const fn execute(&mut self, r: RInstruction) {
-        match (r.funct3(), r.funct7()) {
-            (0b000, 0) => self.add(r),
-            (0b000, 0x20) => self.sub(r),
-            (0b001, 0) => self.sll(r),
-            (0b010, 0) => self.slt(r),
-            (0b011, 0) => self.sltu(r),
-            (0b100, 0) => self.xor(r),
-            (0b101, 0) => self.srl(r),
-            (0b101, 0x20) => self.sra(r),
-            (0b110, 0) => self.or(r),
-            (0b111, 0) => self.and(r),
-            _ => {
-                panic!("no such instruction");
-            }
+    /// The value stored back, from the one loaded and rs2. `width` is
+    /// `X32` for the `.w` forms, only the low word of the result is stored.
+    pub const fn apply(self, width: Xlen, old: u64, operand: u64) -> u64 {
+        let (old, operand) = (width.truncate(old), width.truncate(operand));
+        let less = width.signed(old) < width.signed(operand);
+        match self {
+            Self::Swap => operand,
+            Self::Add => old.wrapping_add(operand),
+            Self::Xor => old ^ operand,
+            Self::And => old & operand,
+            Self::Or => old | operand,
+            Self::Min if less => old,
+            Self::Max if !less => old,
+            Self::Minu if old < operand => old,
+            Self::Maxu if old >= operand => old,
+            Self::Min | Self::Max | Self::Minu | Self::Maxu => operand,
         }
     }
+}
 
-    pub const fn branch(&mut self, pc: &mut Address, b: BInstruction) {
-        match b.funct3() {
-            0b000 => self.beq(pc, b),
-            0b001 => self.bne(pc, b),
-            0b100 => self.blt(pc, b),
-            0b101 => self.bge(pc, b),
-            0b110 => self.bltu(pc, b),
-            0b111 => self.bgeu(pc, b),
-            _ => panic!("branch unknown funct3"),
+/// The OP-FP and fused multiply-add operations, the format is decoded
+/// alongside.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FpOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Sqrt,
+    /// `fmadd`, `rs1 * rs2 + rs3`
+    MulAdd,
+    /// `fmsub`, `rs1 * rs2 - rs3`
+    MulSub,
+    /// `fnmsub`, `-(rs1 * rs2) + rs3`
+    NegMulSub,
+    /// `fnmadd`, `-(rs1 * rs2) - rs3`
+    NegMulAdd,
+    /// `fsgnj`, `fsgnjn` and `fsgnjx`
+    SignInject,
+    SignInjectNeg,
+    SignInjectXor,
+    Min,
+    Max,
+    /// `fcvt.s.d` and `fcvt.d.s`, from the other format
+    Convert,
+    /// `fcvt.w`, `fcvt.wu` and on RV64 `fcvt.l` and `fcvt.lu`
+    ToInt {
+        signed: bool,
+        long: bool,
+    },
+    /// `fcvt.*.w`, `fcvt.*.wu` and on RV64 `fcvt.*.l` and `fcvt.*.lu`
+    FromInt {
+        signed: bool,
+        long: bool,
+    },
+    /// `fmv.x.w`, and `fmv.x.d` on RV64
+    MoveToInt,
+    /// `fmv.w.x`, and `fmv.d.x` on RV64
+    MoveFromInt,
+    Eq,
+    Lt,
+    Le,
+    Class,
+}
+
+impl FpOp {
+    /// `None` for reserved formats, functions and static rounding modes
+    pub const fn decode(instruction: &Instruction, xlen: Xlen) -> Option<(Self, Format)> {
+        let r = instruction.as_r();
+        let rv64 = matches!(xlen, Xlen::X64);
+        let (fmt, rm, rs2) = (r.funct7() & 0b11, r.funct3(), r.rs2());
+        let format = match fmt {
+            0b00 => SINGLE,
+            0b01 => DOUBLE,
+            _ => return None,
+        };
+        let op = match instruction.opcode() as Byte {
+            MADD => Self::MulAdd,
+            MSUB => Self::MulSub,
+            NMSUB => Self::NegMulSub,
+            NMADD => Self::NegMulAdd,
+            OP_FP => match (r.funct7() >> 2, rm, rs2) {
+                (0b00000, ..) => Self::Add,
+                (0b00001, ..) => Self::Sub,
+                (0b00010, ..) => Self::Mul,
+                (0b00011, ..) => Self::Div,
+                (0b01011, _, 0) => Self::Sqrt,
+                (0b00100, 0b000, _) => Self::SignInject,
+                (0b00100, 0b001, _) => Self::SignInjectNeg,
+                (0b00100, 0b010, _) => Self::SignInjectXor,
+                (0b00101, 0b000, _) => Self::Min,
+                (0b00101, 0b001, _) => Self::Max,
+                // rs2 is the format converted from
+                (0b01000, _, 1) if fmt == 0b00 => Self::Convert,
+                (0b01000, _, 0) if fmt == 0b01 => Self::Convert,
+                (0b10100, 0b010, _) => Self::Eq,
+                (0b10100, 0b001, _) => Self::Lt,
+                (0b10100, 0b000, _) => Self::Le,
+                // rs2 is the integer type, the long ones are RV64 only
+                (0b11000, _, 0..=3) if rs2 < 2 || rv64 => Self::ToInt {
+                    signed: rs2 & 1 == 0,
+                    long: rs2 >= 2,
+                },
+                (0b11010, _, 0..=3) if rs2 < 2 || rv64 => Self::FromInt {
+                    signed: rs2 & 1 == 0,
+                    long: rs2 >= 2,
+                },
+                // RV32 has no fmv.x.d or fmv.d.x
+                (0b11100, 0b000, 0) if fmt == 0b00 || rv64 => Self::MoveToInt,
+                (0b11100, 0b001, 0) => Self::Class,
+                (0b11110, 0b000, 0) if fmt == 0b00 || rv64 => Self::MoveFromInt,
+                _ => return None,
+            },
+            _ => return None,
+        };
+        // 5 and 6 are reserved, dyn is checked against frm when executed
+        if op.rounds() && (rm == 0b101 || rm == 0b110) {
+            return None;
         }
+        Some((op, format))
     }
 
-    pub const fn immediate(&mut self, i: IInstruction) {
-        match i.funct3() {
-            0b000 => self.addi(i),
-            0b010 => self.slti(i),
-            0b011 => self.sltiu(i),
-            0b100 => self.xori(i),
-            0b110 => self.ori(i),
-            0b111 => self.andi(i),
-            0b001 => self.slli(i.as_s()),
-            0b101 => {
-                if i.imm() >> 5 == 0 {
-                    self.srli(i.as_s())
-                } else {
-                    self.srai(i.as_s())
-                }
-            }
-            _ => panic!("immediate unknown funct3"),
-        }
+    /// whether funct3 is a rounding mode
+    pub const fn rounds(self) -> bool {
+        matches!(
+            self,
+            Self::Add
+                | Self::Sub
+                | Self::Mul
+                | Self::Div
+                | Self::Sqrt
+                | Self::MulAdd
+                | Self::MulSub
+                | Self::NegMulSub
+                | Self::NegMulAdd
+                | Self::Convert
+                | Self::ToInt { .. }
+                | Self::FromInt { .. }
+        )
     }
 }
@@ -20,9 +20,10 @@ pub type RegisterType = u32;
 
 pub const RISC_V_32_INSTRUCTION_BYTES: usize = 4;
 pub const RISC_V_32_REGISTERS: usize = 32;
+// RV32E keeps x0..x15
+pub const RV32E_REGISTERS: usize = 16;
 pub type R32I = u32;
 
-
 struct Bytes<const N: usize>([Byte; N]);
 
 impl<const N: usize> Bytes<N> {
@@ -48,8 +49,78 @@ impl<const N: usize> Default for Bytes<N> {
 }
 
 pub type Byte = u8;
-pub type Address = u32;
+/// Wide enough for RV64. On RV32 the upper half stays zero.
+pub type Address = u64;
+
+/// The width of the integer registers, what `misa.MXL` says.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum Xlen {
+    #[default]
+    X32,
+    X64,
+}
+
+impl Xlen {
+    pub const fn bits(self) -> u32 {
+        match self {
+            Xlen::X32 => 32,
+            Xlen::X64 => 64,
+        }
+    }
+
+    pub const fn bytes(self) -> usize {
+        self.bits() as usize / BYTE_LEN
+    }
+
+    /// `value` cut to XLEN bits, RV32 values are kept zero-extended
+    pub const fn truncate(self, value: u64) -> u64 {
+        match self {
+            Xlen::X32 => value as u32 as u64,
+            Xlen::X64 => value,
+        }
+    }
+
+    /// an XLEN-bit value read as signed
+    pub const fn signed(self, value: u64) -> i64 {
+        match self {
+            Xlen::X32 => value as i32 as i64,
+            Xlen::X64 => value as i64,
+        }
+    }
+
+    /// the top bit, where mcause keeps the interrupt flag and mstatus SD
+    pub const fn sign_bit(self) -> u64 {
+        1 << (self.bits() - 1)
+    }
+}
+
+/// The privilege level the hart runs at, numbered as in mstatus.MPP.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum Privilege {
+    User = 0,
+    Supervisor = 1,
+    #[default]
+    Machine = 3,
+}
+
+impl Privilege {
+    /// from an MPP or SPP field, 2 is reserved and read as M
+    pub const fn from_bits(bits: u64) -> Self {
+        match bits & 0b11 {
+            0 => Privilege::User,
+            1 => Privilege::Supervisor,
+            _ => Privilege::Machine,
+        }
+    }
+
+    pub const fn bits(self) -> u64 {
+        self as u64
+    }
+}
 
 pub const PC_DEFAULT_ADDRESS: Address = 0;
 pub const STACK_DEFAULT_ADDRESS: Address = 1 << 10;
-pub const PC_STEP: Address = 4;
\ No newline at end of file
+// the length of an uncompressed instruction
+pub const PC_STEP: Address = 4;
+// with C, instructions and jump targets only need to be halfword aligned
+pub const IALIGN: Address = 2;
@@ -0,0 +1,219 @@
+use crate::arch::Xlen;
+use crate::opcode::{
+    B_TYPE, I_TYPE, J_TYPE, JALR, LOAD_FP, LUI, R_TYPE, R_TYPE_32, RI_TYPE, RI_TYPE_32, S_TYPE,
+    STORE_FP, SYSTEM,
+};
+
+// the encodings of the instructions compressed ones expand to
+
+const fn r_type(funct7: u32, rs2: u32, rs1: u32, funct3: u32, rd: u32, opcode: u8) -> u32 {
+    funct7 << 25 | rs2 << 20 | rs1 << 15 | funct3 << 12 | rd << 7 | opcode as u32
+}
+
+const fn i_type(imm: u32, rs1: u32, funct3: u32, rd: u32, opcode: u8) -> u32 {
+    (imm & 0xFFF) << 20 | rs1 << 15 | funct3 << 12 | rd << 7 | opcode as u32
+}
+
+const fn s_type(imm: u32, rs2: u32, rs1: u32, funct3: u32, opcode: u8) -> u32 {
+    (imm >> 5 & 0x7F) << 25
+        | rs2 << 20
+        | rs1 << 15
+        | funct3 << 12
+        | (imm & 0x1F) << 7
+        | opcode as u32
+}
+
+// rs2 is always x0, c.beqz and c.bnez compare with zero
+const fn b_type(imm: u32, rs1: u32, funct3: u32) -> u32 {
+    bits(imm, 12, 12) << 31
+        | bits(imm, 10, 5) << 25
+        | rs1 << 15
+        | funct3 << 12
+        | bits(imm, 4, 1) << 8
+        | bits(imm, 11, 11) << 7
+        | B_TYPE as u32
+}
+
+const fn j_type(imm: u32, rd: u32) -> u32 {
+    bits(imm, 20, 20) << 31
+        | bits(imm, 10, 1) << 21
+        | bits(imm, 11, 11) << 20
+        | bits(imm, 19, 12) << 12
+        | rd << 7
+        | J_TYPE as u32
+}
+
+/// bits `hi..=lo` of `value`, shifted down
+const fn bits(value: u32, hi: u32, lo: u32) -> u32 {
+    (value >> lo) & ((1 << (hi - lo + 1)) - 1)
+}
+
+/// sign-extends the low `len` bits
+const fn sext(value: u32, len: u32) -> u32 {
+    ((value << (32 - len)) as i32 >> (32 - len)) as u32
+}
+
+/// The 32-bit instruction a compressed one stands for, `None` for the
+/// reserved encodings. A few encodings mean something else on RV64:
+/// `c.jal` is `c.addiw` there, and the `c.flw` family are the doubleword
+/// loads and stores.
+pub const fn expand(half: u16, xlen: Xlen) -> Option<u32> {
+    let rv64 = matches!(xlen, Xlen::X64);
+    let c = half as u32;
+    let funct3 = bits(c, 15, 13);
+    let rd = bits(c, 11, 7);
+    let rs2 = bits(c, 6, 2);
+    // rd', rs1' and rs2' only reach x8..x15
+    let rd_short = 8 + bits(c, 4, 2);
+    let rs1_short = 8 + bits(c, 9, 7);
+    let imm6 = sext(bits(c, 12, 12) << 5 | bits(c, 6, 2), 6);
+    // c.lw, c.sw, c.flw and c.fsw
+    let word_offset = bits(c, 12, 10) << 3 | bits(c, 6, 6) << 2 | bits(c, 5, 5) << 6;
+    // c.ld, c.sd, c.fld and c.fsd
+    let double_offset = bits(c, 12, 10) << 3 | bits(c, 6, 5) << 6;
+    // c.lwsp and c.flwsp, c.ldsp and c.fldsp
+    let word_sp_offset = bits(c, 12, 12) << 5 | bits(c, 6, 4) << 2 | bits(c, 3, 2) << 6;
+    let double_sp_offset = bits(c, 12, 12) << 5 | bits(c, 6, 5) << 3 | bits(c, 4, 2) << 6;
+    // c.swsp and c.fswsp, c.sdsp and c.fsdsp
+    let word_sp_store = bits(c, 12, 9) << 2 | bits(c, 8, 7) << 6;
+    let double_sp_store = bits(c, 12, 10) << 3 | bits(c, 9, 7) << 6;
+    // c.slli, c.srli and c.srai, shamt[5] must be 0 on RV32
+    let shamt = bits(c, 12, 12) << 5 | rs2;
+    let shamt_legal = rv64 || bits(c, 12, 12) == 0;
+    // c.j and c.jal
+    let jump_offset = sext(
+        bits(c, 12, 12) << 11
+            | bits(c, 11, 11) << 4
+            | bits(c, 10, 9) << 8
+            | bits(c, 8, 8) << 10
+            | bits(c, 7, 7) << 6
+            | bits(c, 6, 6) << 7
+            | bits(c, 5, 3) << 1
+            | bits(c, 2, 2) << 5,
+        12,
+    );
+    // c.beqz and c.bnez
+    let branch_offset = sext(
+        bits(c, 12, 12) << 8
+            | bits(c, 11, 10) << 3
+            | bits(c, 6, 5) << 6
+            | bits(c, 4, 3) << 1
+            | bits(c, 2, 2) << 5,
+        9,
+    );
+    Some(match (c & 0b11, funct3) {
+        // c.addi4spn
+        (0b00, 0b000) => {
+            let imm = bits(c, 12, 11) << 4
+                | bits(c, 10, 7) << 6
+                | bits(c, 6, 6) << 2
+                | bits(c, 5, 5) << 3;
+            if imm == 0 {
+                return None;
+            }
+            i_type(imm, 2, 0b000, rd_short, RI_TYPE)
+        }
+        // c.fld
+        (0b00, 0b001) => i_type(double_offset, rs1_short, 0b011, rd_short, LOAD_FP),
+        (0b00, 0b010) => i_type(word_offset, rs1_short, 0b010, rd_short, I_TYPE),
+        (0b00, 0b011) if rv64 => i_type(double_offset, rs1_short, 0b011, rd_short, I_TYPE),
+        // c.flw
+        (0b00, 0b011) => i_type(word_offset, rs1_short, 0b010, rd_short, LOAD_FP),
+        // c.fsd
+        (0b00, 0b101) => s_type(double_offset, rd_short, rs1_short, 0b011, STORE_FP),
+        (0b00, 0b110) => s_type(word_offset, rd_short, rs1_short, 0b010, S_TYPE),
+        (0b00, 0b111) if rv64 => s_type(double_offset, rd_short, rs1_short, 0b011, S_TYPE),
+        // c.fsw
+        (0b00, 0b111) => s_type(word_offset, rd_short, rs1_short, 0b010, STORE_FP),
+        // c.nop is c.addi x0
+        (0b01, 0b000) => i_type(imm6, rd, 0b000, rd, RI_TYPE),
+        // c.addiw
+        (0b01, 0b001) if rv64 => {
+            if rd == 0 {
+                return None;
+            }
+            i_type(imm6, rd, 0b000, rd, RI_TYPE_32)
+        }
+        (0b01, 0b001) => j_type(jump_offset, 1),
+        // c.li
+        (0b01, 0b010) => i_type(imm6, 0, 0b000, rd, RI_TYPE),
+        // c.addi16sp
+        (0b01, 0b011) if rd == 2 => {
+            let imm = sext(
+                bits(c, 12, 12) << 9
+                    | bits(c, 6, 6) << 4
+                    | bits(c, 5, 5) << 6
+                    | bits(c, 4, 3) << 7
+                    | bits(c, 2, 2) << 5,
+                10,
+            );
+            if imm == 0 {
+                return None;
+            }
+            i_type(imm, 2, 0b000, 2, RI_TYPE)
+        }
+        // c.lui
+        (0b01, 0b011) => {
+            if imm6 == 0 {
+                return None;
+            }
+            (imm6 << 12) | rd << 7 | LUI as u32
+        }
+        (0b01, 0b100) => match bits(c, 11, 10) {
+            // c.srli and c.srai
+            0b00 if shamt_legal => i_type(shamt, rs1_short, 0b101, rs1_short, RI_TYPE),
+            0b01 if shamt_legal => i_type(0x400 | shamt, rs1_short, 0b101, rs1_short, RI_TYPE),
+            0b10 => i_type(imm6, rs1_short, 0b111, rs1_short, RI_TYPE),
+            // c.sub, c.xor, c.or and c.and
+            0b11 if bits(c, 12, 12) == 0 => {
+                let (funct7, funct3) = match bits(c, 6, 5) {
+                    0b00 => (0x20, 0b000),
+                    0b01 => (0, 0b100),
+                    0b10 => (0, 0b110),
+                    _ => (0, 0b111),
+                };
+                r_type(funct7, rd_short, rs1_short, funct3, rs1_short, R_TYPE)
+            }
+            // c.subw and c.addw
+            0b11 if rv64 && bits(c, 6, 6) == 0 => {
+                let funct7 = if bits(c, 5, 5) == 0 { 0x20 } else { 0 };
+                r_type(funct7, rd_short, rs1_short, 0b000, rs1_short, R_TYPE_32)
+            }
+            _ => return None,
+        },
+        (0b01, 0b101) => j_type(jump_offset, 0),
+        // c.beqz and c.bnez, funct3 picks beq or bne
+        (0b01, 0b110 | 0b111) => b_type(branch_offset, rs1_short, funct3 & 1),
+        (0b10, 0b000) if shamt_legal => i_type(shamt, rd, 0b001, rd, RI_TYPE),
+        // c.fldsp, any f register
+        (0b10, 0b001) => i_type(double_sp_offset, 2, 0b011, rd, LOAD_FP),
+        // c.lwsp
+        (0b10, 0b010) if rd != 0 => i_type(word_sp_offset, 2, 0b010, rd, I_TYPE),
+        // c.ldsp
+        (0b10, 0b011) if rv64 && rd != 0 => i_type(double_sp_offset, 2, 0b011, rd, I_TYPE),
+        // c.flwsp
+        (0b10, 0b011) if !rv64 => i_type(word_sp_offset, 2, 0b010, rd, LOAD_FP),
+        (0b10, 0b100) => match (bits(c, 12, 12), rd, rs2) {
+            (0, 0, 0) => return None,
+            // c.jr
+            (0, _, 0) => i_type(0, rd, 0b000, 0, JALR),
+            // c.mv
+            (0, _, _) => r_type(0, rs2, 0, 0b000, rd, R_TYPE),
+            // c.ebreak
+            (_, 0, 0) => 1 << 20 | SYSTEM as u32,
+            // c.jalr
+            (_, _, 0) => i_type(0, rd, 0b000, 1, JALR),
+            // c.add
+            _ => r_type(0, rs2, rd, 0b000, rd, R_TYPE),
+        },
+        // c.fsdsp
+        (0b10, 0b101) => s_type(double_sp_store, rs2, 2, 0b011, STORE_FP),
+        // c.swsp
+        (0b10, 0b110) => s_type(word_sp_store, rs2, 2, 0b010, S_TYPE),
+        // c.sdsp
+        (0b10, 0b111) if rv64 => s_type(double_sp_store, rs2, 2, 0b011, S_TYPE),
+        // c.fswsp
+        (0b10, 0b111) => s_type(word_sp_store, rs2, 2, 0b010, STORE_FP),
+        _ => return None,
+    })
+}
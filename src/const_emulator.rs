@@ -3,7 +3,10 @@ use crate::arch::{Address, Byte, PC_STEP};
 use crate::instruction_type::{IInstruction, Instruction, SInstruction};
 use crate::mask::{BYTE_MASK, HALF_WORD_MASK, WORD_MASK};
 use crate::opcode::{AUIPC, B_TYPE, I_TYPE, J_TYPE, JALR, LUI, NOP, R_TYPE, RI_TYPE, S_TYPE};
-use crate::register::Registers;
+use crate::register::{Register, Registers};
+
+// enough for the demos in main, small enough to fail fast on a runaway loop
+pub const DEFAULT_STEP_LIMIT: usize = 1 << 17;
 
 pub struct ConstantEmulator;
 impl ConstantEmulator {
@@ -11,19 +14,31 @@ impl ConstantEmulator {
         regs: &mut Registers,
         data: &mut [u32],
         code_len: usize,
-        pc: &mut u32,
+        pc: &mut Address,
         stop: &mut bool,
     ) {
-        if *pc >> 2 >= code_len as u32 {
+        if *pc >> 2 >= code_len as Address {
             *stop = true;
             return;
         }
         let instruction = Instruction(data[(*pc >> 2) as usize]);
 
         match instruction.opcode() as Byte {
-            R_TYPE => ALU::with(regs).execute(instruction.as_r()),
-            RI_TYPE => ALU::with(regs).immediate(instruction.as_i()),
-            B_TYPE => ALU::with(regs).branch(pc, instruction.as_b()),
+            R_TYPE => {
+                if ALU::with(regs).execute(instruction.as_r()).is_err() {
+                    panic!("illegal R_TYPE instruction");
+                }
+            }
+            RI_TYPE => {
+                if ALU::with(regs).immediate(instruction.as_i()).is_err() {
+                    panic!("illegal RI_TYPE instruction");
+                }
+            }
+            B_TYPE => {
+                if ALU::with(regs).branch(pc, instruction.as_b()).is_err() {
+                    panic!("illegal or misaligned branch");
+                }
+            }
             I_TYPE => {
                 let i = instruction.as_i();
                 match i.funct3() {
@@ -47,97 +62,110 @@ impl ConstantEmulator {
             J_TYPE => {
                 let j = instruction.as_j();
                 *regs.get_mut(j.rd()) = *pc;
-                *pc = ((*pc - PC_STEP) as i32 + j.imm()) as u32;
+                *pc = ((*pc - PC_STEP) as i32 + j.imm()) as u32 as Address;
             }
             JALR => {
                 let i = instruction.as_i();
                 *regs.get_mut(i.rd()) = *pc;
-                *pc = (((regs.get(i.rs1())) & !1) as i32 + i.imm()) as Address;
+                *pc = (((regs.get(i.rs1())) & !1) as i32 + i.imm()) as u32 as Address;
             }
             LUI => {
                 let u = instruction.as_u();
-                *regs.get_mut(u.rd()) = (regs.get(u.rd()) & 0xFFF) + u.high_imm();
+                *regs.get_mut(u.rd()) = u.high_imm() as Register;
             }
             AUIPC => {
                 let u = instruction.as_u();
-                *regs.get_mut(u.rd()) = *pc + u.high_imm();
+                *regs.get_mut(u.rd()) = *pc + u.high_imm() as Register;
             }
             NOP => *stop = true,
             _ => *stop = true,
         }
     }
+    /// `run_loop_limited` with `DEFAULT_STEP_LIMIT`
     pub const fn run_loop<const N: usize>(code: &[u32; N]) -> u32 {
+        Self::run_loop_limited::<N, DEFAULT_STEP_LIMIT>(code)
+    }
+
+    /// Runs `code` until the stop word and returns `a0`. A guest still running
+    /// after `LIMIT` instructions fails const evaluation with a panic, so keep
+    /// `LIMIT` below what the `long_running_const_eval` lint tolerates.
+    pub const fn run_loop_limited<const N: usize, const LIMIT: usize>(code: &[u32; N]) -> u32 {
         assert!(N < 1 << 16);
         let mut regs = Registers::default();
         let mut pc = 0;
         let mut stop = false;
         let mut data = [0; 1 << 21];
         data.first_chunk_mut::<N>().unwrap().copy_from_slice(code);
-        *regs.sp() = ((data.len() as u32) << 2) - 100;
+        *regs.sp() = ((data.len() as Register) << 2) - 100;
+        let mut steps = 0;
         while !stop {
+            if steps == LIMIT {
+                panic!("ConstantEmulator: step limit reached before the stop word");
+            }
+            steps += 1;
             Self::run(&mut regs, &mut data, code.len(), &mut pc, &mut stop);
             pc += 4;
         }
-        *regs.a(0)
+        *regs.a(0) as u32
     }
     const fn lb(registers: &mut Registers, i: IInstruction, data: &[u32]) {
-        let base = registers.get(i.rs1()) as u32;
+        let base = registers.get(i.rs1());
         let offset = i.imm();
         let target = (base as i32 + offset) as u32;
         let data = data[(target >> 2) as usize];
-        *registers.get_mut(i.rd()) = (data) << 24 >> 24;
+        *registers.get_mut(i.rd()) = ((data) << 24 >> 24) as Register;
     }
 
     const fn lbu(registers: &mut Registers, i: IInstruction, data: &[u32]) {
-        let base = registers.get(i.rs1()) as u32;
+        let base = registers.get(i.rs1());
         let offset = i.imm();
         let target = (base as i32 + offset) as u32;
         let data = data[(target >> 2) as usize];
-        *registers.get_mut(i.rd()) = data;
+        *registers.get_mut(i.rd()) = data as Register;
     }
 
     const fn lh(registers: &mut Registers, i: IInstruction, data: &[u32]) {
-        let base = registers.get(i.rs1()) as u32;
+        let base = registers.get(i.rs1());
         let offset = i.imm();
         let target = (base as i32 + offset) as u32;
         let data = data[(target >> 2) as usize];
-        *registers.get_mut(i.rd()) = (data) << 16 >> 16;
+        *registers.get_mut(i.rd()) = ((data) << 16 >> 16) as Register;
     }
 
     const fn lhu(registers: &mut Registers, i: IInstruction, data: &[u32]) {
-        let base = registers.get(i.rs1()) as u32;
+        let base = registers.get(i.rs1());
         let offset = i.imm();
         let target = (base as i32 + offset) as u32;
         let data = data[(target >> 2) as usize];
-        *registers.get_mut(i.rd()) = data;
+        *registers.get_mut(i.rd()) = data as Register;
     }
 
     const fn lw(registers: &mut Registers, i: IInstruction, data: &[u32]) {
-        let base = registers.get(i.rs1()) as u32;
+        let base = registers.get(i.rs1());
         let offset = i.imm();
         let target = (base as i32 + offset) as u32;
         let data = data[(target >> 2) as usize];
-        *registers.get_mut(i.rd()) = data;
+        *registers.get_mut(i.rd()) = data as Register;
     }
 
     const fn sb(registers: &mut Registers, s: SInstruction, data: &mut [u32]) {
-        let base = registers.get(s.rs1()) as u32;
+        let base = registers.get(s.rs1());
         let offset = s.imm();
         let target = base as i32 + offset;
-        data[target as usize >> 2] = registers.get(s.rs2()) & BYTE_MASK;
+        data[target as usize >> 2] = registers.get(s.rs2()) as u32 & BYTE_MASK;
     }
 
     const fn sw(registers: &mut Registers, s: SInstruction, data: &mut [u32]) {
-        let base = registers.get(s.rs1()) as u32;
+        let base = registers.get(s.rs1());
         let offset = s.imm();
         let target = base as i32 + offset;
-        data[target as usize >> 2] = registers.get(s.rs2()) & WORD_MASK;
+        data[target as usize >> 2] = registers.get(s.rs2()) as u32 & WORD_MASK;
     }
 
     const fn sh(registers: &mut Registers, s: SInstruction, data: &mut [u32]) {
-        let base = registers.get(s.rs1()) as u32;
+        let base = registers.get(s.rs1());
         let offset = s.imm();
         let target = base as i32 + offset;
-        data[target as usize >> 2] = registers.get(s.rs2()) & HALF_WORD_MASK;
+        data[target as usize >> 2] = registers.get(s.rs2()) as u32 & HALF_WORD_MASK;
     }
 }
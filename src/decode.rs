@@ -0,0 +1,312 @@
+use crate::alu::{AluOp, AmoOp, BranchOp, FpOp};
+use crate::arch::{Address, Byte, Xlen};
+use crate::float::Format;
+use crate::instruction_type::Instruction;
+use crate::opcode::{
+    AMO, AUIPC, B_TYPE, I_TYPE, J_TYPE, JALR, LOAD_FP, LUI, MADD, MISC_MEM, MSUB, NMADD, NMSUB,
+    NOP, OP_FP, R_TYPE, R_TYPE_32, RI_TYPE, RI_TYPE_32, S_TYPE, STORE_FP, SYSTEM,
+};
+use crate::register::Register;
+
+/// What the interpreter dispatches on, the funct3/funct7 matches already done.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Op {
+    /// `rd = rs1 op rs2`
+    Alu(AluOp),
+    /// `rd = rs1 op imm`
+    AluImm(AluOp),
+    /// the RV64 `*w` ops, see `AluOp::apply_word`
+    AluWord(AluOp),
+    AluImmWord(AluOp),
+    Load {
+        size: u8,
+        signed: bool,
+    },
+    Store {
+        size: u8,
+    },
+    Branch(BranchOp),
+    Jal,
+    Jalr,
+    Lui,
+    Auipc,
+    /// `lr.w` and `lr.d`
+    LoadReserved {
+        size: u8,
+    },
+    /// `sc.w` and `sc.d`
+    StoreConditional {
+        size: u8,
+    },
+    /// `amo*.w` and `amo*.d`, the aq and rl bits don't matter on a single
+    /// hart
+    Amo {
+        op: AmoOp,
+        size: u8,
+    },
+    /// `flw` and `fld`
+    FloatLoad {
+        size: u8,
+    },
+    /// `fsw` and `fsd`
+    FloatStore {
+        size: u8,
+    },
+    /// OP-FP and the fused multiply-adds
+    Float(FpOp, Format),
+    /// `fence`, `fence.tso` and `pause`, nothing to order on a single hart
+    Fence,
+    /// `fence.i`, drops every decoded instruction
+    FenceI,
+    /// csr*, ecall, ebreak, mret and wfi, executed from the word
+    System,
+    /// the stop word
+    Stop,
+    Illegal,
+}
+
+/// An instruction decoded once, with its operand fields extracted.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Decoded {
+    pub op: Op,
+    pub rd: u8,
+    pub rs1: u8,
+    pub rs2: u8,
+    /// sign-extended to 64 bits the way the instruction uses it, the upper
+    /// immediate for `lui` and `auipc`. For `Op::Float` the rounding mode,
+    /// with rs3 above it
+    pub imm: Register,
+    /// a compressed instruction in the low half
+    pub word: u32,
+    /// 2 for compressed instructions, 4 otherwise
+    pub len: u8,
+}
+
+impl Decoded {
+    /// `xlen` picks between the RV32 and RV64 meanings of an encoding
+    pub const fn new(word: u32, xlen: Xlen) -> Self {
+        let instruction = Instruction(word);
+        if instruction.is_compressed() {
+            // the expansion is never compressed itself
+            return Self {
+                word,
+                len: 2,
+                ..Self::new(instruction.expanded(xlen).0, xlen)
+            };
+        }
+        let rv64 = matches!(xlen, Xlen::X64);
+        let i = instruction.as_i();
+        let (op, imm) = match instruction.opcode() as Byte {
+            R_TYPE => match AluOp::register(&instruction.as_r(), xlen) {
+                Some(op) => (Op::Alu(op), 0),
+                None => (Op::Illegal, 0),
+            },
+            RI_TYPE => match AluOp::immediate(&i, xlen) {
+                Some((op, operand)) => (Op::AluImm(op), operand),
+                None => (Op::Illegal, 0),
+            },
+            R_TYPE_32 if rv64 => {
+                let r = instruction.as_r();
+                match (AluOp::register_word(&r), AluOp::register_unsigned_word(&r)) {
+                    (Some(op), _) => (Op::AluWord(op), 0),
+                    (_, Some(op)) => (Op::Alu(op), 0),
+                    _ => (Op::Illegal, 0),
+                }
+            }
+            RI_TYPE_32 if rv64 => match AluOp::immediate_word(&i) {
+                Some((AluOp::SlliUw, operand)) => (Op::AluImm(AluOp::SlliUw), operand),
+                Some((op, operand)) => (Op::AluImmWord(op), operand),
+                None => (Op::Illegal, 0),
+            },
+            I_TYPE => {
+                let op = match i.funct3() {
+                    0b000 => Op::Load {
+                        size: 1,
+                        signed: true,
+                    },
+                    0b001 => Op::Load {
+                        size: 2,
+                        signed: true,
+                    },
+                    0b010 => Op::Load {
+                        size: 4,
+                        signed: true,
+                    },
+                    0b011 if rv64 => Op::Load {
+                        size: 8,
+                        signed: false,
+                    },
+                    0b100 => Op::Load {
+                        size: 1,
+                        signed: false,
+                    },
+                    0b101 => Op::Load {
+                        size: 2,
+                        signed: false,
+                    },
+                    0b110 if rv64 => Op::Load {
+                        size: 4,
+                        signed: false,
+                    },
+                    _ => Op::Illegal,
+                };
+                (op, i.imm() as Register)
+            }
+            S_TYPE => {
+                let s = instruction.as_s();
+                let op = match s.funct3() {
+                    0b000 => Op::Store { size: 1 },
+                    0b001 => Op::Store { size: 2 },
+                    0b010 => Op::Store { size: 4 },
+                    0b011 if rv64 => Op::Store { size: 8 },
+                    _ => Op::Illegal,
+                };
+                (op, s.imm() as Register)
+            }
+            B_TYPE => match BranchOp::decode(&instruction.as_b()) {
+                Some(op) => (Op::Branch(op), instruction.as_b().imm() as Register),
+                None => (Op::Illegal, 0),
+            },
+            J_TYPE => (Op::Jal, instruction.as_j().imm() as Register),
+            JALR if i.funct3() == 0 => (Op::Jalr, i.imm() as Register),
+            // sign-extended on RV64
+            LUI => (Op::Lui, instruction.as_u().high_imm() as i32 as Register),
+            AUIPC => (Op::Auipc, instruction.as_u().high_imm() as i32 as Register),
+            AMO if i.funct3() == 0b010 || (i.funct3() == 0b011 && rv64) => {
+                let r = instruction.as_r();
+                let size = 1 << i.funct3();
+                let op = match (r.funct7() >> 2, AmoOp::decode(&r)) {
+                    (0b00010, _) if r.rs2() == 0 => Op::LoadReserved { size },
+                    (0b00011, _) => Op::StoreConditional { size },
+                    (_, Some(op)) => Op::Amo { op, size },
+                    (_, None) => Op::Illegal,
+                };
+                (op, 0)
+            }
+            LOAD_FP => {
+                let op = match i.funct3() {
+                    0b010 => Op::FloatLoad { size: 4 },
+                    0b011 => Op::FloatLoad { size: 8 },
+                    _ => Op::Illegal,
+                };
+                (op, i.imm() as Register)
+            }
+            STORE_FP => {
+                let s = instruction.as_s();
+                let op = match s.funct3() {
+                    0b010 => Op::FloatStore { size: 4 },
+                    0b011 => Op::FloatStore { size: 8 },
+                    _ => Op::Illegal,
+                };
+                (op, s.imm() as Register)
+            }
+            OP_FP | MADD | MSUB | NMSUB | NMADD => match FpOp::decode(&instruction, xlen) {
+                Some((op, format)) => {
+                    // rs3 is the top five bits of funct7
+                    let r = instruction.as_r();
+                    let imm = r.funct3() as Register | (r.funct7() as Register >> 2) << 3;
+                    (Op::Float(op, format), imm)
+                }
+                None => (Op::Illegal, 0),
+            },
+            // the fm, pred and succ fields of fence don't matter here
+            MISC_MEM if i.funct3() == 0b000 => (Op::Fence, 0),
+            MISC_MEM if i.funct3() == 0b001 => (Op::FenceI, 0),
+            SYSTEM => (Op::System, 0),
+            NOP => (Op::Stop, 0),
+            _ => (Op::Illegal, 0),
+        };
+        Self {
+            op,
+            rd: i.rd(),
+            rs1: i.rs1(),
+            rs2: instruction.as_r().rs2(),
+            imm,
+            word,
+            len: 4,
+        }
+    }
+
+    /// Illegal if it names an integer register at or above `count`, which is
+    /// 16 on RV32E.
+    pub const fn limit_registers(self, count: u8) -> Self {
+        let [rd, rs1, rs2] = self.integer_registers();
+        if rd < count && rs1 < count && rs2 < count {
+            return self;
+        }
+        Self {
+            op: Op::Illegal,
+            ..self
+        }
+    }
+
+    // rd, rs1 and rs2 where they are integer registers, 0 where not
+    const fn integer_registers(&self) -> [u8; 3] {
+        let (rd, rs1, rs2) = (self.rd, self.rs1, self.rs2);
+        match self.op {
+            Op::Alu(_) | Op::AluWord(_) | Op::StoreConditional { .. } | Op::Amo { .. } => {
+                [rd, rs1, rs2]
+            }
+            Op::AluImm(_)
+            | Op::AluImmWord(_)
+            | Op::Load { .. }
+            | Op::Jalr
+            | Op::LoadReserved { .. } => [rd, rs1, 0],
+            Op::Store { .. } | Op::Branch(_) => [0, rs1, rs2],
+            Op::Jal | Op::Lui | Op::Auipc => [rd, 0, 0],
+            Op::FloatLoad { .. } | Op::FloatStore { .. } => [0, rs1, 0],
+            Op::Float(
+                FpOp::ToInt { .. } | FpOp::MoveToInt | FpOp::Eq | FpOp::Lt | FpOp::Le | FpOp::Class,
+                _,
+            ) => [rd, 0, 0],
+            Op::Float(FpOp::FromInt { .. } | FpOp::MoveFromInt, _) => [0, rs1, 0],
+            // sfence.vma names registers in rs1 and rs2, the other funct3 0
+            // instructions only have small numbers in rs2. csrrwi and the like
+            // have an immediate in rs1.
+            Op::System if Instruction(self.word).as_i().funct3() == 0 => [rd, rs1, rs2],
+            Op::System if Instruction(self.word).as_i().funct3() < 0b100 => [rd, rs1, 0],
+            Op::System => [rd, 0, 0],
+            Op::Float(..) | Op::Fence | Op::FenceI | Op::Stop | Op::Illegal => [0, 0, 0],
+        }
+    }
+}
+
+/// Decoded RAM instructions by halfword address. Writing a word drops every
+/// instruction that overlaps it.
+#[derive(Debug, Default)]
+pub struct DecodeCache {
+    halves: Vec<Option<Decoded>>,
+}
+
+impl DecodeCache {
+    /// `pc` must be halfword aligned
+    pub fn get(&self, pc: Address) -> Option<Decoded> {
+        self.halves.get((pc >> 1) as usize).copied().flatten()
+    }
+
+    pub fn insert(&mut self, pc: Address, decoded: Decoded) {
+        let index = (pc >> 1) as usize;
+        if index >= self.halves.len() {
+            self.halves.resize(index + 1, None);
+        }
+        self.halves[index] = Some(decoded);
+    }
+
+    /// drops what overlaps the word containing `address`, `true` if anything
+    /// was cached
+    pub fn invalidate(&mut self, address: Address) -> bool {
+        // a 32-bit instruction from the halfword before can reach into it
+        let first = ((address >> 1) & !1) as usize;
+        let mut dropped = false;
+        for index in first.saturating_sub(1)..first + 2 {
+            if let Some(half) = self.halves.get_mut(index) {
+                dropped |= half.take().is_some();
+            }
+        }
+        dropped
+    }
+
+    pub fn clear(&mut self) {
+        self.halves.clear();
+    }
+}
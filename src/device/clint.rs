@@ -0,0 +1,146 @@
+use crate::arch::Address;
+use crate::device::Device;
+use crate::exception::Interrupt;
+use crate::register::Register;
+use std::any::Any;
+use std::time::Instant;
+
+pub const CLINT_BASE: Address = 0x0200_0000;
+pub const CLINT_SIZE: Address = 0x1_0000;
+
+const MSIP: Address = 0x0000;
+const MTIMECMP: Address = 0x4000;
+const MTIME: Address = 0xBFF8;
+
+/// What drives `mtime`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ClockSource {
+    /// one tick per retired instruction, so runs are reproducible
+    Instructions,
+    /// follows the host clock at `frequency` ticks per second
+    WallClock { frequency: u64 },
+}
+
+/// Core-local interruptor with the SiFive register layout.
+#[derive(Debug)]
+pub struct Clint {
+    msip: bool,
+    mtimecmp: u64,
+    mtime: u64,
+    source: ClockSource,
+    // mtime at `epoch` in wall-clock mode
+    epoch: Instant,
+}
+
+impl Default for Clint {
+    fn default() -> Self {
+        Self {
+            msip: false,
+            // no timer interrupt until the guest programs one
+            mtimecmp: u64::MAX,
+            mtime: 0,
+            source: ClockSource::Instructions,
+            epoch: Instant::now(),
+        }
+    }
+}
+
+impl Clint {
+    pub fn set_clock_source(&mut self, source: ClockSource) {
+        let now = self.mtime();
+        self.source = source;
+        self.set_mtime(now);
+    }
+
+    pub fn mtime(&self) -> u64 {
+        match self.source {
+            ClockSource::Instructions => self.mtime,
+            ClockSource::WallClock { frequency } => {
+                let elapsed = self.epoch.elapsed().as_nanos() * frequency as u128 / 1_000_000_000;
+                self.mtime.wrapping_add(elapsed as u64)
+            }
+        }
+    }
+
+    fn set_mtime(&mut self, value: u64) {
+        self.mtime = value;
+        self.epoch = Instant::now();
+    }
+}
+
+impl Device for Clint {
+    /// `offset` is relative to `CLINT_BASE`, `None` for unmapped registers
+    fn read_word(&mut self, offset: Address) -> Option<u32> {
+        let low_high = |value: u64, offset: Address| (value >> ((offset & 4) << 3)) as u32;
+        match offset {
+            MSIP => Some(self.msip as u32),
+            MTIMECMP | 0x4004 => Some(low_high(self.mtimecmp, offset)),
+            MTIME | 0xBFFC => Some(low_high(self.mtime(), offset)),
+            _ => None,
+        }
+    }
+
+    fn write_word(&mut self, offset: Address, value: u32) -> Option<()> {
+        let replace = |old: u64, offset: Address| {
+            let shift = (offset & 4) << 3;
+            (old & !(0xFFFF_FFFF << shift)) | (value as u64) << shift
+        };
+        match offset {
+            MSIP => self.msip = value & 1 != 0,
+            MTIMECMP | 0x4004 => self.mtimecmp = replace(self.mtimecmp, offset),
+            MTIME | 0xBFFC => {
+                let mtime = replace(self.mtime(), offset);
+                self.set_mtime(mtime)
+            }
+            _ => return None,
+        }
+        Some(())
+    }
+
+    fn tick(&mut self) {
+        if self.source == ClockSource::Instructions {
+            self.mtime = self.mtime.wrapping_add(1);
+        }
+    }
+
+    fn advance(&mut self, ticks: u64) {
+        if self.source == ClockSource::Instructions {
+            self.mtime = self.mtime.wrapping_add(ticks);
+        }
+    }
+
+    // msip, mtimecmp, mtime; the clock source is configuration
+    fn save(&self) -> Vec<u8> {
+        let mut state = vec![self.msip as u8];
+        state.extend_from_slice(&self.mtimecmp.to_le_bytes());
+        state.extend_from_slice(&self.mtime().to_le_bytes());
+        state
+    }
+
+    fn restore(&mut self, state: &[u8]) -> Option<()> {
+        let [msip, rest @ ..] = state else {
+            return None;
+        };
+        let (mtimecmp, mtime) = rest.split_first_chunk::<8>()?;
+        let mtime = mtime.try_into().ok()?;
+        self.msip = *msip != 0;
+        self.mtimecmp = u64::from_le_bytes(*mtimecmp);
+        self.set_mtime(u64::from_le_bytes(mtime));
+        Some(())
+    }
+
+    fn as_any_mut(&mut self) -> Option<&mut dyn Any> {
+        Some(self)
+    }
+
+    fn pending(&self) -> Register {
+        let mut pending = 0;
+        if self.msip {
+            pending |= Interrupt::MachineSoftware.mask();
+        }
+        if self.mtime() >= self.mtimecmp {
+            pending |= Interrupt::MachineTimer.mask();
+        }
+        pending
+    }
+}
@@ -0,0 +1,72 @@
+pub mod clint;
+pub mod uart;
+
+use crate::arch::Address;
+use crate::mask::{BYTE_MASK, HALF_WORD_MASK};
+use crate::register::Register;
+use std::any::Any;
+
+/// A memory-mapped peripheral. `offset` is relative to the base the device
+/// is mapped at, and `None` means the access faults.
+///
+/// Only the word accessors are required: narrower reads pick the lane out of
+/// the containing word, and narrower writes read-modify-write it. Devices
+/// whose registers have side effects on read should override them.
+pub trait Device: Send {
+    fn read_word(&mut self, offset: Address) -> Option<u32>;
+    fn write_word(&mut self, offset: Address, value: u32) -> Option<()>;
+
+    fn read_halfword(&mut self, offset: Address) -> Option<u16> {
+        let word = self.read_word(offset & !0b11)?;
+        Some(((word >> ((offset & 0b10) << 3)) & HALF_WORD_MASK) as u16)
+    }
+
+    fn read_byte(&mut self, offset: Address) -> Option<u8> {
+        let word = self.read_word(offset & !0b11)?;
+        Some(((word >> ((offset & 0b11) << 3)) & BYTE_MASK) as u8)
+    }
+
+    fn write_halfword(&mut self, offset: Address, value: u16) -> Option<()> {
+        let word = self.read_word(offset & !0b11)?;
+        let shift = (offset & 0b10) << 3;
+        let word = (word & !(HALF_WORD_MASK << shift)) | (value as u32) << shift;
+        self.write_word(offset & !0b11, word)
+    }
+
+    fn write_byte(&mut self, offset: Address, value: u8) -> Option<()> {
+        let word = self.read_word(offset & !0b11)?;
+        let shift = (offset & 0b11) << 3;
+        let word = (word & !(BYTE_MASK << shift)) | (value as u32) << shift;
+        self.write_word(offset & !0b11, word)
+    }
+
+    /// called once per retired instruction
+    fn tick(&mut self) {}
+
+    /// `tick` called `ticks` times, for callers that batch them up
+    fn advance(&mut self, ticks: u64) {
+        for _ in 0..ticks {
+            self.tick();
+        }
+    }
+
+    /// the mip bits this device drives
+    fn pending(&self) -> Register {
+        0
+    }
+
+    /// the device's state for a snapshot, host-side configuration isn't included
+    fn save(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    /// `None` if `state` didn't come from `save` on this kind of device
+    fn restore(&mut self, state: &[u8]) -> Option<()> {
+        state.is_empty().then_some(())
+    }
+
+    /// the device itself, for host-side configuration of a mapped device
+    fn as_any_mut(&mut self) -> Option<&mut dyn Any> {
+        None
+    }
+}
@@ -0,0 +1,352 @@
+use crate::arch::Address;
+use crate::device::Device;
+use crate::exception::Interrupt;
+use crate::register::Register;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufReader, Read, Write};
+use std::path::Path;
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex};
+
+// where QEMU's virt machine puts its first 16550
+pub const UART_BASE: Address = 0x1000_0000;
+pub const UART_SIZE: Address = 0x100;
+
+// byte offsets, DLL/DLM replace RBR/THR and IER while LCR.DLAB is set
+const RBR_THR_DLL: Address = 0;
+const IER_DLM: Address = 1;
+const IIR_FCR: Address = 2;
+const LCR: Address = 3;
+const MCR: Address = 4;
+const LSR: Address = 5;
+const MSR: Address = 6;
+const SCR: Address = 7;
+
+const IER_RX_AVAILABLE: u8 = 1 << 0;
+const IER_TX_EMPTY: u8 = 1 << 1;
+const IIR_NO_INTERRUPT: u8 = 0x01;
+const IIR_TX_EMPTY: u8 = 0x02;
+const IIR_RX_AVAILABLE: u8 = 0x04;
+const IIR_FIFO_ENABLED: u8 = 0xC0;
+const FCR_FIFO_ENABLE: u8 = 1 << 0;
+const LCR_DLAB: u8 = 1 << 7;
+const LSR_DATA_READY: u8 = 1 << 0;
+const LSR_THR_EMPTY: u8 = 1 << 5;
+const LSR_TRANSMITTER_EMPTY: u8 = 1 << 6;
+
+/// Host side of the UART.
+pub trait UartBackend: Send {
+    /// the next received byte, `None` if nothing has arrived yet
+    fn read(&mut self) -> Option<u8>;
+    fn write(&mut self, byte: u8);
+}
+
+/// The process stdin and stdout. Stdin is read on a background thread so the
+/// guest can poll LSR without blocking the hart.
+pub struct StdioBackend {
+    stdin: Receiver<u8>,
+}
+
+impl StdioBackend {
+    pub fn new() -> Self {
+        let (sender, stdin) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            for byte in std::io::stdin().lock().bytes() {
+                match byte {
+                    Ok(byte) if sender.send(byte).is_ok() => {}
+                    _ => break,
+                }
+            }
+        });
+        Self { stdin }
+    }
+}
+
+impl Default for StdioBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UartBackend for StdioBackend {
+    fn read(&mut self) -> Option<u8> {
+        // a closed stdin just never delivers another byte
+        self.stdin.try_recv().ok()
+    }
+
+    fn write(&mut self, byte: u8) {
+        let mut stdout = std::io::stdout().lock();
+        // the guest has no way to see a host I/O error
+        let _ = stdout.write_all(&[byte]).and_then(|_| stdout.flush());
+    }
+}
+
+/// In-memory input and output. Clones share the buffers, so a test can keep
+/// one handle and give the other to the UART.
+#[derive(Debug, Clone, Default)]
+pub struct BufferBackend {
+    input: Arc<Mutex<VecDeque<u8>>>,
+    output: Arc<Mutex<Vec<u8>>>,
+}
+
+impl BufferBackend {
+    pub fn push_input(&self, data: &[u8]) {
+        self.input.lock().unwrap().extend(data);
+    }
+
+    /// everything the guest has transmitted so far
+    pub fn output(&self) -> Vec<u8> {
+        self.output.lock().unwrap().clone()
+    }
+}
+
+impl UartBackend for BufferBackend {
+    fn read(&mut self) -> Option<u8> {
+        self.input.lock().unwrap().pop_front()
+    }
+
+    fn write(&mut self, byte: u8) {
+        self.output.lock().unwrap().push(byte);
+    }
+}
+
+/// Transmits into a file and, optionally, receives the contents of another.
+pub struct FileBackend {
+    input: Option<BufReader<File>>,
+    output: File,
+}
+
+impl FileBackend {
+    pub fn create(output: impl AsRef<Path>) -> std::io::Result<Self> {
+        Ok(Self {
+            input: None,
+            output: File::create(output)?,
+        })
+    }
+
+    pub fn with_input(mut self, input: impl AsRef<Path>) -> std::io::Result<Self> {
+        self.input = Some(BufReader::new(File::open(input)?));
+        Ok(self)
+    }
+}
+
+impl UartBackend for FileBackend {
+    fn read(&mut self) -> Option<u8> {
+        let mut byte = [0];
+        match self.input.as_mut()?.read(&mut byte) {
+            Ok(1) => Some(byte[0]),
+            _ => None,
+        }
+    }
+
+    fn write(&mut self, byte: u8) {
+        let _ = self.output.write_all(&[byte]);
+    }
+}
+
+/// 16550-compatible UART with byte-wide registers. Transmission is immediate
+/// and there is no FIFO, a single received byte is held in RBR. Without a
+/// PLIC its interrupt is wired straight to the machine external interrupt.
+pub struct Uart {
+    backend: Box<dyn UartBackend>,
+    rbr: Option<u8>,
+    ier: u8,
+    fcr: u8,
+    lcr: u8,
+    mcr: u8,
+    scr: u8,
+    divisor: u16,
+    // THR-empty interrupt, cleared by reading IIR or writing THR
+    tx_empty_pending: bool,
+}
+
+impl Uart {
+    pub fn new(backend: impl UartBackend + 'static) -> Self {
+        Self {
+            backend: Box::new(backend),
+            rbr: None,
+            ier: 0,
+            fcr: 0,
+            lcr: 0,
+            mcr: 0,
+            scr: 0,
+            divisor: 0,
+            tx_empty_pending: false,
+        }
+    }
+
+    fn poll(&mut self) {
+        if self.rbr.is_none() {
+            self.rbr = self.backend.read();
+        }
+    }
+
+    const fn dlab(&self) -> bool {
+        self.lcr & LCR_DLAB != 0
+    }
+
+    const fn rx_interrupt(&self) -> bool {
+        self.ier & IER_RX_AVAILABLE != 0 && self.rbr.is_some()
+    }
+
+    const fn tx_interrupt(&self) -> bool {
+        self.ier & IER_TX_EMPTY != 0 && self.tx_empty_pending
+    }
+
+    fn iir(&mut self) -> u8 {
+        let fifo = if self.fcr & FCR_FIFO_ENABLE != 0 {
+            IIR_FIFO_ENABLED
+        } else {
+            0
+        };
+        let id = if self.rx_interrupt() {
+            IIR_RX_AVAILABLE
+        } else if self.tx_interrupt() {
+            self.tx_empty_pending = false;
+            IIR_TX_EMPTY
+        } else {
+            IIR_NO_INTERRUPT
+        };
+        fifo | id
+    }
+}
+
+impl Device for Uart {
+    /// word and halfword accesses reach the byte register at `offset`
+    fn read_word(&mut self, offset: Address) -> Option<u32> {
+        self.read_byte(offset).map(|byte| byte as u32)
+    }
+
+    fn write_word(&mut self, offset: Address, value: u32) -> Option<()> {
+        self.write_byte(offset, value as u8)
+    }
+
+    fn read_halfword(&mut self, offset: Address) -> Option<u16> {
+        self.read_byte(offset).map(|byte| byte as u16)
+    }
+
+    fn write_halfword(&mut self, offset: Address, value: u16) -> Option<()> {
+        self.write_byte(offset, value as u8)
+    }
+
+    fn read_byte(&mut self, offset: Address) -> Option<u8> {
+        Some(match offset {
+            RBR_THR_DLL if self.dlab() => self.divisor as u8,
+            IER_DLM if self.dlab() => (self.divisor >> 8) as u8,
+            RBR_THR_DLL => {
+                self.poll();
+                self.rbr.take().unwrap_or(0)
+            }
+            IER_DLM => self.ier,
+            IIR_FCR => self.iir(),
+            LCR => self.lcr,
+            MCR => self.mcr,
+            LSR => {
+                self.poll();
+                let ready = if self.rbr.is_some() {
+                    LSR_DATA_READY
+                } else {
+                    0
+                };
+                ready | LSR_THR_EMPTY | LSR_TRANSMITTER_EMPTY
+            }
+            // CTS, DSR and DCD asserted
+            MSR => 0xB0,
+            SCR => self.scr,
+            _ => return None,
+        })
+    }
+
+    fn write_byte(&mut self, offset: Address, value: u8) -> Option<()> {
+        match offset {
+            RBR_THR_DLL if self.dlab() => self.divisor = (self.divisor & 0xFF00) | value as u16,
+            IER_DLM if self.dlab() => self.divisor = (self.divisor & 0x00FF) | (value as u16) << 8,
+            RBR_THR_DLL => {
+                self.backend.write(value);
+                // sent at once, so THR is empty again
+                self.tx_empty_pending = true;
+            }
+            IER_DLM => {
+                // enabling the THR-empty interrupt raises it right away
+                if value & IER_TX_EMPTY != 0 && self.ier & IER_TX_EMPTY == 0 {
+                    self.tx_empty_pending = true;
+                }
+                self.ier = value & 0x0F;
+            }
+            IIR_FCR => self.fcr = value,
+            LCR => self.lcr = value,
+            MCR => self.mcr = value & 0x1F,
+            // LSR and MSR are read-only
+            LSR | MSR => {}
+            SCR => self.scr = value,
+            _ => return None,
+        }
+        Some(())
+    }
+
+    fn tick(&mut self) {
+        if self.ier & IER_RX_AVAILABLE != 0 {
+            self.poll();
+        }
+    }
+
+    // polling again before the guest reads the byte does nothing
+    fn advance(&mut self, ticks: u64) {
+        if ticks != 0 {
+            self.tick();
+        }
+    }
+
+    // the received byte and the registers, the backend is configuration
+    fn save(&self) -> Vec<u8> {
+        let [dll, dlm] = self.divisor.to_le_bytes();
+        vec![
+            self.rbr.is_some() as u8,
+            self.rbr.unwrap_or(0),
+            self.ier,
+            self.fcr,
+            self.lcr,
+            self.mcr,
+            self.scr,
+            dll,
+            dlm,
+            self.tx_empty_pending as u8,
+        ]
+    }
+
+    fn restore(&mut self, state: &[u8]) -> Option<()> {
+        let &[
+            has_rbr,
+            rbr,
+            ier,
+            fcr,
+            lcr,
+            mcr,
+            scr,
+            dll,
+            dlm,
+            tx_empty_pending,
+        ] = state
+        else {
+            return None;
+        };
+        self.rbr = (has_rbr != 0).then_some(rbr);
+        self.ier = ier;
+        self.fcr = fcr;
+        self.lcr = lcr;
+        self.mcr = mcr;
+        self.scr = scr;
+        self.divisor = u16::from_le_bytes([dll, dlm]);
+        self.tx_empty_pending = tx_empty_pending != 0;
+        Some(())
+    }
+
+    fn pending(&self) -> Register {
+        if self.rx_interrupt() || self.tx_interrupt() {
+            Interrupt::MachineExternal.mask()
+        } else {
+            0
+        }
+    }
+}
@@ -0,0 +1,739 @@
+use crate::alu::{AluOp, BranchOp};
+use crate::arch::{Address, IALIGN, RISC_V_32_REGISTERS, Xlen};
+use crate::decode::{Decoded, Op};
+use crate::emulator::EmulatorContext;
+use crate::emulator::debug::StoppedAt;
+use crate::emulator::step::StepStatus;
+use crate::exception::Trap;
+use crate::memory::MemoryWrapper;
+use crate::mmu::Access;
+use std::collections::HashMap;
+
+// interrupts and the run limit are only looked at between blocks
+const MAX_BLOCK_LEN: usize = 64;
+
+/// How `run`, `run_for` and `run_until` execute the guest. `step` always
+/// interprets.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum ExecutionMode {
+    /// one `step` per instruction, the reference the other mode must match
+    #[default]
+    Interpreter,
+    /// Straight-line code is decoded into blocks once and run without the
+    /// per-step bookkeeping, each block chained to the ones it was seen to
+    /// jump to. A block is only left early by a taken branch, a trap, a
+    /// store that overwrites cached code or turning interrupts on or off, and
+    /// interrupts are taken between blocks.
+    ///
+    /// Tracing, the undo log, breakpoints and watchpoints need every step,
+    /// so while any of them is set the interpreter runs instead.
+    Blocks,
+}
+
+/// `Op` with the ALU operation or branch condition folded in, so the block
+/// loop dispatches once per instruction rather than twice.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Kind {
+    Add,
+    Sub,
+    Sll,
+    Slt,
+    Sltu,
+    Xor,
+    Srl,
+    Sra,
+    Or,
+    And,
+    Mul,
+    Mulh,
+    Mulhsu,
+    Mulhu,
+    Div,
+    Divu,
+    Rem,
+    Remu,
+    Sh1add,
+    Sh2add,
+    Sh3add,
+    Andn,
+    Orn,
+    Xnor,
+    Min,
+    Minu,
+    Max,
+    Maxu,
+    Rol,
+    Ror,
+    ZextH,
+    Bset,
+    Bclr,
+    Binv,
+    Bext,
+    Addi,
+    Slli,
+    Slti,
+    Sltiu,
+    Xori,
+    Srli,
+    Srai,
+    Ori,
+    Andi,
+    Clz,
+    Ctz,
+    Cpop,
+    SextB,
+    SextH,
+    OrcB,
+    Rev8,
+    Rori,
+    Bseti,
+    Bclri,
+    Binvi,
+    Bexti,
+    AddUw,
+    Sh1addUw,
+    Sh2addUw,
+    Sh3addUw,
+    SlliUw,
+    Addw,
+    Subw,
+    Sllw,
+    Srlw,
+    Sraw,
+    Mulw,
+    Divw,
+    Divuw,
+    Remw,
+    Remuw,
+    Addiw,
+    Slliw,
+    Srliw,
+    Sraiw,
+    Beq,
+    Bne,
+    Blt,
+    Bge,
+    Bltu,
+    Bgeu,
+    Jal,
+    Jalr,
+    Lui,
+    Auipc,
+    Load {
+        size: u8,
+        signed: bool,
+    },
+    Store {
+        size: u8,
+    },
+    /// everything else, run through `execute`
+    Execute,
+}
+
+impl Kind {
+    const fn new(op: Op) -> Self {
+        match op {
+            Op::Alu(op) => match op {
+                AluOp::Add => Self::Add,
+                AluOp::Sub => Self::Sub,
+                AluOp::Sll => Self::Sll,
+                AluOp::Slt => Self::Slt,
+                AluOp::Sltu => Self::Sltu,
+                AluOp::Xor => Self::Xor,
+                AluOp::Srl => Self::Srl,
+                AluOp::Sra => Self::Sra,
+                AluOp::Or => Self::Or,
+                AluOp::And => Self::And,
+                AluOp::Mul => Self::Mul,
+                AluOp::Mulh => Self::Mulh,
+                AluOp::Mulhsu => Self::Mulhsu,
+                AluOp::Mulhu => Self::Mulhu,
+                AluOp::Div => Self::Div,
+                AluOp::Divu => Self::Divu,
+                AluOp::Rem => Self::Rem,
+                AluOp::Remu => Self::Remu,
+                AluOp::Sh1add => Self::Sh1add,
+                AluOp::Sh2add => Self::Sh2add,
+                AluOp::Sh3add => Self::Sh3add,
+                AluOp::Andn => Self::Andn,
+                AluOp::Orn => Self::Orn,
+                AluOp::Xnor => Self::Xnor,
+                AluOp::Min => Self::Min,
+                AluOp::Minu => Self::Minu,
+                AluOp::Max => Self::Max,
+                AluOp::Maxu => Self::Maxu,
+                AluOp::Rol => Self::Rol,
+                AluOp::Ror => Self::Ror,
+                AluOp::ZextH => Self::ZextH,
+                AluOp::Bset => Self::Bset,
+                AluOp::Bclr => Self::Bclr,
+                AluOp::Binv => Self::Binv,
+                AluOp::Bext => Self::Bext,
+                AluOp::AddUw => Self::AddUw,
+                AluOp::Sh1addUw => Self::Sh1addUw,
+                AluOp::Sh2addUw => Self::Sh2addUw,
+                AluOp::Sh3addUw => Self::Sh3addUw,
+                // the unary ops are OP-IMM, decoding never produces them here
+                AluOp::Clz
+                | AluOp::Ctz
+                | AluOp::Cpop
+                | AluOp::SextB
+                | AluOp::SextH
+                | AluOp::OrcB
+                | AluOp::Rev8
+                | AluOp::SlliUw => Self::Execute,
+            },
+            Op::AluImm(op) => match op {
+                AluOp::Add => Self::Addi,
+                AluOp::Sll => Self::Slli,
+                AluOp::Slt => Self::Slti,
+                AluOp::Sltu => Self::Sltiu,
+                AluOp::Xor => Self::Xori,
+                AluOp::Srl => Self::Srli,
+                AluOp::Sra => Self::Srai,
+                AluOp::Or => Self::Ori,
+                AluOp::And => Self::Andi,
+                AluOp::Clz => Self::Clz,
+                AluOp::Ctz => Self::Ctz,
+                AluOp::Cpop => Self::Cpop,
+                AluOp::SextB => Self::SextB,
+                AluOp::SextH => Self::SextH,
+                AluOp::OrcB => Self::OrcB,
+                AluOp::Rev8 => Self::Rev8,
+                AluOp::Ror => Self::Rori,
+                AluOp::Bset => Self::Bseti,
+                AluOp::Bclr => Self::Bclri,
+                AluOp::Binv => Self::Binvi,
+                AluOp::Bext => Self::Bexti,
+                AluOp::SlliUw => Self::SlliUw,
+                // no `subi`, immediate RV32M or immediate forms of these,
+                // decoding never produces them
+                AluOp::Sub
+                | AluOp::Mul
+                | AluOp::Mulh
+                | AluOp::Mulhsu
+                | AluOp::Mulhu
+                | AluOp::Div
+                | AluOp::Divu
+                | AluOp::Rem
+                | AluOp::Remu
+                | AluOp::Sh1add
+                | AluOp::Sh2add
+                | AluOp::Sh3add
+                | AluOp::Andn
+                | AluOp::Orn
+                | AluOp::Xnor
+                | AluOp::Min
+                | AluOp::Minu
+                | AluOp::Max
+                | AluOp::Maxu
+                | AluOp::Rol
+                | AluOp::ZextH
+                | AluOp::AddUw
+                | AluOp::Sh1addUw
+                | AluOp::Sh2addUw
+                | AluOp::Sh3addUw => Self::Execute,
+            },
+            // the rarer Zbb ones go through `execute`
+            Op::AluWord(op) => match op {
+                AluOp::Add => Self::Addw,
+                AluOp::Sub => Self::Subw,
+                AluOp::Sll => Self::Sllw,
+                AluOp::Srl => Self::Srlw,
+                AluOp::Sra => Self::Sraw,
+                AluOp::Mul => Self::Mulw,
+                AluOp::Div => Self::Divw,
+                AluOp::Divu => Self::Divuw,
+                AluOp::Rem => Self::Remw,
+                AluOp::Remu => Self::Remuw,
+                _ => Self::Execute,
+            },
+            Op::AluImmWord(op) => match op {
+                AluOp::Add => Self::Addiw,
+                AluOp::Sll => Self::Slliw,
+                AluOp::Srl => Self::Srliw,
+                AluOp::Sra => Self::Sraiw,
+                _ => Self::Execute,
+            },
+            Op::Branch(op) => match op {
+                BranchOp::Eq => Self::Beq,
+                BranchOp::Ne => Self::Bne,
+                BranchOp::Lt => Self::Blt,
+                BranchOp::Ge => Self::Bge,
+                BranchOp::Ltu => Self::Bltu,
+                BranchOp::Geu => Self::Bgeu,
+            },
+            Op::Jal => Self::Jal,
+            Op::Jalr => Self::Jalr,
+            Op::Lui => Self::Lui,
+            Op::Auipc => Self::Auipc,
+            Op::Load { size, signed } => Self::Load { size, signed },
+            Op::Store { size } => Self::Store { size },
+            _ => Self::Execute,
+        }
+    }
+}
+
+/// Instructions from `start` up to the first `jalr`, system instruction, stop
+/// word or illegal instruction. A `jal` is followed to its target, so a loop
+/// closed by one runs around inside the block. Conditional branches don't end
+/// a block either, a taken one just leaves it early.
+#[derive(Debug)]
+struct Block {
+    start: Address,
+    ops: Box<[(Kind, Decoded)]>,
+    // (pc, block) of the successors seen so far, tried before the index
+    links: [Option<(Address, usize)>; 2],
+}
+
+impl Block {
+    /// the successor at `pc`, if it has been linked
+    fn link(&self, pc: Address) -> Option<usize> {
+        self.links
+            .iter()
+            .flatten()
+            .find(|(target, _)| *target == pc)
+            .map(|(_, index)| *index)
+    }
+
+    /// `None` if there's no RAM code at `start` to build from
+    fn build(
+        memory: &mut MemoryWrapper,
+        xlen: Xlen,
+        registers: u8,
+        start: Address,
+    ) -> Option<Self> {
+        let mut ops = Vec::new();
+        let mut pc = start;
+        // device words aren't cached, so they can't be in a block either
+        while ops.len() < MAX_BLOCK_LEN && memory.peek(pc, 2).is_some() {
+            let Ok(decoded) = memory.fetch_decoded(pc, xlen, registers) else {
+                break;
+            };
+            ops.push((Kind::new(decoded.op), decoded));
+            let target = xlen.truncate(pc.wrapping_add(decoded.imm));
+            match decoded.op {
+                // a misaligned target traps, `execute` takes care of that
+                Op::Jal if target.is_multiple_of(IALIGN) => pc = target,
+                Op::Jal | Op::Jalr | Op::System | Op::Stop | Op::Illegal => break,
+                _ => pc = xlen.truncate(pc.wrapping_add(decoded.len as Address)),
+            }
+        }
+        (!ops.is_empty()).then(|| Self {
+            start,
+            ops: ops.into_boxed_slice(),
+            links: [None; 2],
+        })
+    }
+}
+
+/// The blocks built so far, all thrown away once cached code is written.
+#[derive(Debug, Default)]
+pub(crate) struct BlockCache {
+    blocks: Vec<Block>,
+    index: HashMap<Address, usize>,
+    // the `code_generation` the blocks were built from
+    generation: u64,
+    // `retired` when the devices were last ticked
+    ticked: u64,
+}
+
+impl BlockCache {
+    /// `true` if the blocks were stale and have been dropped
+    fn sync(&mut self, generation: u64) -> bool {
+        if self.generation == generation {
+            return false;
+        }
+        self.blocks.clear();
+        self.index.clear();
+        self.generation = generation;
+        true
+    }
+
+    /// the block starting at `pc`, reached from the block `from`
+    fn find(
+        &mut self,
+        memory: &mut MemoryWrapper,
+        xlen: Xlen,
+        registers: u8,
+        from: Option<usize>,
+        pc: Address,
+    ) -> Option<usize> {
+        if let Some(index) = from.and_then(|from| self.blocks[from].link(pc)) {
+            return Some(index);
+        }
+        let index = match self.index.get(&pc) {
+            Some(index) => *index,
+            None => {
+                self.blocks.push(Block::build(memory, xlen, registers, pc)?);
+                self.index.insert(pc, self.blocks.len() - 1);
+                self.blocks.len() - 1
+            }
+        };
+        if let Some(from) = from
+            && let Some(link) = self.blocks[from]
+                .links
+                .iter_mut()
+                .find(|link| link.is_none())
+        {
+            *link = Some((pc, index));
+        }
+        Some(index)
+    }
+}
+
+impl EmulatorContext {
+    pub fn set_execution_mode(&mut self, mode: ExecutionMode) -> &mut Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn execution_mode(&self) -> ExecutionMode {
+        self.mode
+    }
+
+    /// `run_bounded` for `ExecutionMode::Blocks`
+    pub(crate) fn run_blocks(&mut self, limit: u64) -> Option<Result<StoppedAt, Trap>> {
+        // taken out so the blocks can be run while `self` is borrowed mutably
+        let mut blocks = std::mem::take(&mut self.blocks);
+        blocks.ticked = self.retired;
+        let stopped = self.run_blocks_from(&mut blocks, limit);
+        self.memory.advance_devices(self.retired - blocks.ticked);
+        self.blocks = blocks;
+        // a later `step` must not report the last access made here
+        self.memory.take_access();
+        stopped
+    }
+
+    fn run_blocks_from(
+        &mut self,
+        blocks: &mut BlockCache,
+        limit: u64,
+    ) -> Option<Result<StoppedAt, Trap>> {
+        let xlen = self.xlen();
+        let registers = self.registers.count();
+        let mut remaining = limit;
+        let mut previous = None;
+        while remaining != 0 {
+            if self.stop {
+                return Some(Ok(StoppedAt::Halted));
+            }
+            if blocks.sync(self.memory.code_generation()) {
+                previous = None;
+            }
+            // blocks are found by virtual pc, translated code is interpreted
+            let translation = self.csr.translation();
+            self.memory.set_translation(translation);
+            let block = if translation.active() {
+                None
+            } else {
+                blocks
+                    .find(
+                        &mut self.memory,
+                        xlen,
+                        registers,
+                        previous,
+                        self.program_counter,
+                    )
+                    .filter(|index| blocks.blocks[*index].ops.len() as u64 <= remaining)
+            };
+            let Some(index) = block else {
+                // no block fits, the interpreter takes any interrupt itself
+                self.memory.advance_devices(self.retired - blocks.ticked);
+                previous = None;
+                remaining -= 1;
+                let status = self.step().status;
+                // `step` ticks the devices itself
+                blocks.ticked = self.retired;
+                match status {
+                    StepStatus::Retired | StepStatus::Handled(_) => continue,
+                    StepStatus::Halted => return Some(Ok(StoppedAt::Halted)),
+                    StepStatus::Trap(trap) => return Some(Err(trap)),
+                }
+            };
+            if self.csr.interrupts_enabled() {
+                self.memory.advance_devices(self.retired - blocks.ticked);
+                blocks.ticked = self.retired;
+                if self.take_interrupt().is_some() {
+                    previous = None;
+                    continue;
+                }
+            }
+            self.memory.set_translation(self.csr.translation());
+            let chain = match xlen {
+                Xlen::X32 => Self::run_chain::<false>,
+                Xlen::X64 => Self::run_chain::<true>,
+            };
+            match chain(self, blocks, index, remaining) {
+                Ok((steps, last)) => {
+                    remaining -= steps;
+                    previous = Some(last);
+                }
+                Err(trap) => return Some(Err(trap)),
+            }
+        }
+        None
+    }
+
+    /// Runs the block at `index` and then the blocks linked after it, for at
+    /// most `limit` steps, until a block is left some other way than by
+    /// falling off its end or a branch or jump that doesn't trap. Returns
+    /// the steps taken the way `step` counts them, a trap the guest handles
+    /// being one, and the last block run, or the trap if the guest doesn't
+    /// handle it.
+    ///
+    /// While interrupts are enabled every block is a chain of its own, so
+    /// they can be taken in between. A block that enables them ends at that
+    /// instruction, see `execute_in_block`.
+    fn run_chain<const RV64: bool>(
+        &mut self,
+        blocks: &mut BlockCache,
+        mut index: usize,
+        limit: u64,
+    ) -> Result<(u64, usize), Trap> {
+        // an instruction that enables them ends the block and with it the chain
+        let chained = !self.csr.interrupts_enabled();
+        let xlen = if RV64 { Xlen::X64 } else { Xlen::X32 };
+        let first = self.retired;
+        let mut retired = first;
+        let mut handled = 0;
+        loop {
+            let block = &blocks.blocks[index];
+            let mut pc = block.start;
+            'run: {
+                for (kind, d) in &block.ops {
+                    let r = self.registers.file_mut();
+                    let rs1 = r[reg(d.rs1)];
+                    let rs2 = r[reg(d.rs2)];
+                    let next = xlen.truncate(pc.wrapping_add(d.len as Address));
+                    let target = xlen.truncate(pc.wrapping_add(d.imm));
+                    let address = xlen.truncate(rs1.wrapping_add(d.imm));
+                    match kind {
+                        Kind::Add => r[reg(d.rd)] = AluOp::Add.apply(xlen, rs1, rs2),
+                        Kind::Sub => r[reg(d.rd)] = AluOp::Sub.apply(xlen, rs1, rs2),
+                        Kind::Sll => r[reg(d.rd)] = AluOp::Sll.apply(xlen, rs1, rs2),
+                        Kind::Slt => r[reg(d.rd)] = AluOp::Slt.apply(xlen, rs1, rs2),
+                        Kind::Sltu => r[reg(d.rd)] = AluOp::Sltu.apply(xlen, rs1, rs2),
+                        Kind::Xor => r[reg(d.rd)] = AluOp::Xor.apply(xlen, rs1, rs2),
+                        Kind::Srl => r[reg(d.rd)] = AluOp::Srl.apply(xlen, rs1, rs2),
+                        Kind::Sra => r[reg(d.rd)] = AluOp::Sra.apply(xlen, rs1, rs2),
+                        Kind::Or => r[reg(d.rd)] = AluOp::Or.apply(xlen, rs1, rs2),
+                        Kind::And => r[reg(d.rd)] = AluOp::And.apply(xlen, rs1, rs2),
+                        Kind::Mul => r[reg(d.rd)] = AluOp::Mul.apply(xlen, rs1, rs2),
+                        Kind::Mulh => r[reg(d.rd)] = AluOp::Mulh.apply(xlen, rs1, rs2),
+                        Kind::Mulhsu => r[reg(d.rd)] = AluOp::Mulhsu.apply(xlen, rs1, rs2),
+                        Kind::Mulhu => r[reg(d.rd)] = AluOp::Mulhu.apply(xlen, rs1, rs2),
+                        Kind::Div => r[reg(d.rd)] = AluOp::Div.apply(xlen, rs1, rs2),
+                        Kind::Divu => r[reg(d.rd)] = AluOp::Divu.apply(xlen, rs1, rs2),
+                        Kind::Rem => r[reg(d.rd)] = AluOp::Rem.apply(xlen, rs1, rs2),
+                        Kind::Remu => r[reg(d.rd)] = AluOp::Remu.apply(xlen, rs1, rs2),
+                        Kind::Sh1add => r[reg(d.rd)] = AluOp::Sh1add.apply(xlen, rs1, rs2),
+                        Kind::Sh2add => r[reg(d.rd)] = AluOp::Sh2add.apply(xlen, rs1, rs2),
+                        Kind::Sh3add => r[reg(d.rd)] = AluOp::Sh3add.apply(xlen, rs1, rs2),
+                        Kind::Andn => r[reg(d.rd)] = AluOp::Andn.apply(xlen, rs1, rs2),
+                        Kind::Orn => r[reg(d.rd)] = AluOp::Orn.apply(xlen, rs1, rs2),
+                        Kind::Xnor => r[reg(d.rd)] = AluOp::Xnor.apply(xlen, rs1, rs2),
+                        Kind::Min => r[reg(d.rd)] = AluOp::Min.apply(xlen, rs1, rs2),
+                        Kind::Minu => r[reg(d.rd)] = AluOp::Minu.apply(xlen, rs1, rs2),
+                        Kind::Max => r[reg(d.rd)] = AluOp::Max.apply(xlen, rs1, rs2),
+                        Kind::Maxu => r[reg(d.rd)] = AluOp::Maxu.apply(xlen, rs1, rs2),
+                        Kind::Rol => r[reg(d.rd)] = AluOp::Rol.apply(xlen, rs1, rs2),
+                        Kind::Ror => r[reg(d.rd)] = AluOp::Ror.apply(xlen, rs1, rs2),
+                        Kind::ZextH => r[reg(d.rd)] = AluOp::ZextH.apply(xlen, rs1, rs2),
+                        Kind::Bset => r[reg(d.rd)] = AluOp::Bset.apply(xlen, rs1, rs2),
+                        Kind::Bclr => r[reg(d.rd)] = AluOp::Bclr.apply(xlen, rs1, rs2),
+                        Kind::Binv => r[reg(d.rd)] = AluOp::Binv.apply(xlen, rs1, rs2),
+                        Kind::Bext => r[reg(d.rd)] = AluOp::Bext.apply(xlen, rs1, rs2),
+                        Kind::Addi => r[reg(d.rd)] = AluOp::Add.apply(xlen, rs1, d.imm),
+                        Kind::Slli => r[reg(d.rd)] = AluOp::Sll.apply(xlen, rs1, d.imm),
+                        Kind::Slti => r[reg(d.rd)] = AluOp::Slt.apply(xlen, rs1, d.imm),
+                        Kind::Sltiu => r[reg(d.rd)] = AluOp::Sltu.apply(xlen, rs1, d.imm),
+                        Kind::Xori => r[reg(d.rd)] = AluOp::Xor.apply(xlen, rs1, d.imm),
+                        Kind::Srli => r[reg(d.rd)] = AluOp::Srl.apply(xlen, rs1, d.imm),
+                        Kind::Srai => r[reg(d.rd)] = AluOp::Sra.apply(xlen, rs1, d.imm),
+                        Kind::Ori => r[reg(d.rd)] = AluOp::Or.apply(xlen, rs1, d.imm),
+                        Kind::Andi => r[reg(d.rd)] = AluOp::And.apply(xlen, rs1, d.imm),
+                        Kind::Clz => r[reg(d.rd)] = AluOp::Clz.apply(xlen, rs1, d.imm),
+                        Kind::Ctz => r[reg(d.rd)] = AluOp::Ctz.apply(xlen, rs1, d.imm),
+                        Kind::Cpop => r[reg(d.rd)] = AluOp::Cpop.apply(xlen, rs1, d.imm),
+                        Kind::SextB => r[reg(d.rd)] = AluOp::SextB.apply(xlen, rs1, d.imm),
+                        Kind::SextH => r[reg(d.rd)] = AluOp::SextH.apply(xlen, rs1, d.imm),
+                        Kind::OrcB => r[reg(d.rd)] = AluOp::OrcB.apply(xlen, rs1, d.imm),
+                        Kind::Rev8 => r[reg(d.rd)] = AluOp::Rev8.apply(xlen, rs1, d.imm),
+                        Kind::Rori => r[reg(d.rd)] = AluOp::Ror.apply(xlen, rs1, d.imm),
+                        Kind::Bseti => r[reg(d.rd)] = AluOp::Bset.apply(xlen, rs1, d.imm),
+                        Kind::Bclri => r[reg(d.rd)] = AluOp::Bclr.apply(xlen, rs1, d.imm),
+                        Kind::Binvi => r[reg(d.rd)] = AluOp::Binv.apply(xlen, rs1, d.imm),
+                        Kind::Bexti => r[reg(d.rd)] = AluOp::Bext.apply(xlen, rs1, d.imm),
+                        Kind::AddUw => r[reg(d.rd)] = AluOp::AddUw.apply(xlen, rs1, rs2),
+                        Kind::Sh1addUw => r[reg(d.rd)] = AluOp::Sh1addUw.apply(xlen, rs1, rs2),
+                        Kind::Sh2addUw => r[reg(d.rd)] = AluOp::Sh2addUw.apply(xlen, rs1, rs2),
+                        Kind::Sh3addUw => r[reg(d.rd)] = AluOp::Sh3addUw.apply(xlen, rs1, rs2),
+                        Kind::SlliUw => r[reg(d.rd)] = AluOp::SlliUw.apply(xlen, rs1, d.imm),
+                        // only decoded on RV64, so the results need no cutting
+                        Kind::Addw => r[reg(d.rd)] = AluOp::Add.apply_word(rs1, rs2),
+                        Kind::Subw => r[reg(d.rd)] = AluOp::Sub.apply_word(rs1, rs2),
+                        Kind::Sllw => r[reg(d.rd)] = AluOp::Sll.apply_word(rs1, rs2),
+                        Kind::Srlw => r[reg(d.rd)] = AluOp::Srl.apply_word(rs1, rs2),
+                        Kind::Sraw => r[reg(d.rd)] = AluOp::Sra.apply_word(rs1, rs2),
+                        Kind::Mulw => r[reg(d.rd)] = AluOp::Mul.apply_word(rs1, rs2),
+                        Kind::Divw => r[reg(d.rd)] = AluOp::Div.apply_word(rs1, rs2),
+                        Kind::Divuw => r[reg(d.rd)] = AluOp::Divu.apply_word(rs1, rs2),
+                        Kind::Remw => r[reg(d.rd)] = AluOp::Rem.apply_word(rs1, rs2),
+                        Kind::Remuw => r[reg(d.rd)] = AluOp::Remu.apply_word(rs1, rs2),
+                        Kind::Addiw => r[reg(d.rd)] = AluOp::Add.apply_word(rs1, d.imm),
+                        Kind::Slliw => r[reg(d.rd)] = AluOp::Sll.apply_word(rs1, d.imm),
+                        Kind::Srliw => r[reg(d.rd)] = AluOp::Srl.apply_word(rs1, d.imm),
+                        Kind::Sraiw => r[reg(d.rd)] = AluOp::Sra.apply_word(rs1, d.imm),
+                        Kind::Lui => r[reg(d.rd)] = xlen.truncate(d.imm),
+                        Kind::Auipc => r[reg(d.rd)] = target,
+                        // RAM needs no device ticks, anything else goes
+                        // through `execute`
+                        Kind::Load { size, signed }
+                            if self
+                                .memory
+                                .plain_ram(address, *size as Address, Access::Load) =>
+                        {
+                            let value = self.memory.load_ram(address, *size as Address, *signed);
+                            r[reg(d.rd)] = xlen.truncate(value);
+                        }
+                        Kind::Store { size }
+                            if self
+                                .memory
+                                .plain_ram(address, *size as Address, Access::Store) =>
+                        {
+                            let generation = self.memory.code_generation();
+                            self.memory.store_ram(address, *size as Address, rs2);
+                            // overwrote cached code, the blocks are rebuilt
+                            if self.memory.code_generation() != generation {
+                                self.program_counter = next;
+                                self.retired = retired + 1;
+                                return Ok((retired + 1 - first, index));
+                            }
+                        }
+                        Kind::Beq if !BranchOp::Eq.taken(xlen, rs1, rs2) => {}
+                        Kind::Bne if !BranchOp::Ne.taken(xlen, rs1, rs2) => {}
+                        Kind::Blt if !BranchOp::Lt.taken(xlen, rs1, rs2) => {}
+                        Kind::Bge if !BranchOp::Ge.taken(xlen, rs1, rs2) => {}
+                        Kind::Bltu if !BranchOp::Ltu.taken(xlen, rs1, rs2) => {}
+                        Kind::Bgeu if !BranchOp::Geu.taken(xlen, rs1, rs2) => {}
+                        // jumps to misaligned targets trap, `execute` takes
+                        // care of those
+                        Kind::Beq | Kind::Bne | Kind::Blt | Kind::Bge | Kind::Bltu | Kind::Bgeu
+                            if target.is_multiple_of(IALIGN) =>
+                        {
+                            retired += 1;
+                            pc = target;
+                            break 'run;
+                        }
+                        // the block goes on at the target
+                        Kind::Jal if target.is_multiple_of(IALIGN) => {
+                            r[reg(d.rd)] = next;
+                            r[0] = 0;
+                            retired += 1;
+                            pc = target;
+                            continue;
+                        }
+                        // clearing bit 0 is all the alignment jalr needs
+                        Kind::Jalr => {
+                            r[reg(d.rd)] = next;
+                            r[0] = 0;
+                            retired += 1;
+                            pc = xlen.truncate(rs1.wrapping_add(d.imm)) & !1;
+                            break 'run;
+                        }
+                        _ => {
+                            self.retired = retired;
+                            let flow = self.execute_in_block(d, pc, &mut blocks.ticked);
+                            retired = self.retired;
+                            match flow {
+                                Ok(true) => {
+                                    pc = next;
+                                    continue;
+                                }
+                                Ok(false) => {}
+                                Err(StepStatus::Trap(trap)) => return Err(trap),
+                                Err(_) => handled = 1,
+                            }
+                            // the pc is wherever `execute` or the trap put it
+                            self.retired = retired;
+                            return Ok((retired - first + handled, index));
+                        }
+                    }
+                    r[0] = 0;
+                    retired += 1;
+                    pc = next;
+                }
+            }
+            self.program_counter = pc;
+            let steps = retired - first;
+            let next = block
+                .link(pc)
+                .filter(|next| blocks.blocks[*next].ops.len() as u64 <= limit - steps);
+            match next {
+                Some(next) if chained => index = next,
+                _ => {
+                    self.retired = retired;
+                    return Ok((steps, index));
+                }
+            }
+        }
+    }
+
+    /// What the block fast path leaves to `execute`. `Ok(true)` if the block
+    /// goes on, `Ok(false)` if it jumped away, overwrote cached code, changed
+    /// how addresses are translated or whether interrupts are enabled or
+    /// halted, and the status of the trap if it raised one.
+    ///
+    /// `ticked` is `retired` when the devices were last ticked.
+    // kept out of line so the fast path around it stays small
+    #[inline(never)]
+    fn execute_in_block(
+        &mut self,
+        d: &Decoded,
+        pc: Address,
+        ticked: &mut u64,
+    ) -> Result<bool, StepStatus> {
+        let generation = self.memory.code_generation();
+        let translation = self.csr.translation();
+        let interrupts = self.csr.interrupts_enabled();
+        if matches!(
+            d.op,
+            Op::Load { .. }
+                | Op::Store { .. }
+                | Op::LoadReserved { .. }
+                | Op::StoreConditional { .. }
+                | Op::Amo { .. }
+                | Op::FloatLoad { .. }
+                | Op::FloatStore { .. }
+                | Op::System
+        ) {
+            self.memory.advance_devices(self.retired - *ticked);
+            *ticked = self.retired;
+        }
+        if d.op == Op::System {
+            // csrr mip must read what the interpreter would
+            self.csr.set_pending(self.memory.pending_interrupts());
+        }
+        let next = self.xlen().truncate(pc.wrapping_add(d.len as Address));
+        self.program_counter = next;
+        if let Err(exception) = self.execute(d) {
+            return Err(self.take_trap(Trap {
+                pc,
+                instruction: d.word,
+                exception,
+            }));
+        }
+        self.retired += 1;
+        Ok(self.program_counter == next
+            && self.memory.code_generation() == generation
+            && self.csr.translation() == translation
+            && self.csr.interrupts_enabled() == interrupts
+            && !self.stop)
+    }
+}
+
+// register fields are 5 bits, saying so spares the bounds checks
+const fn reg(i: u8) -> usize {
+    i as usize % RISC_V_32_REGISTERS
+}
@@ -0,0 +1,177 @@
+use crate::arch::{Address, RISC_V_32_REGISTERS};
+use crate::emulator::step::{RegisterWrite, StepOutcome};
+use crate::exception::Trap;
+use crate::memory::MemoryAccess;
+use crate::register::Register;
+use std::collections::HashSet;
+use std::ops::Range;
+
+/// Why `EmulatorContext::run` returned to the host.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StoppedAt {
+    /// the guest reached the stop word or exited
+    Halted,
+    /// about to execute the instruction at this address
+    Breakpoint(Address),
+    /// a watched range was accessed, `kind` is the kind of the watchpoint
+    /// that fired. For loads `old` and `new` are both the value read, `old`
+    /// is `None` for stores to a device register
+    Watch {
+        kind: WatchKind,
+        addr: Address,
+        old: Option<u64>,
+        new: u64,
+    },
+    /// a watched register was written
+    RegisterWatch {
+        register: u8,
+        old: Register,
+        new: Register,
+    },
+    /// `run_for` used up its instruction budget
+    InstructionLimit,
+    /// `run_until` reached its deadline
+    Deadline,
+    /// `reverse_continue` undid every recorded step
+    HistoryStart,
+}
+
+/// What `run_for` and `run_until` did.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RunReport {
+    /// instructions retired during the run, steps that trapped don't count
+    pub retired: u64,
+    pub stopped: Result<StoppedAt, Trap>,
+}
+
+/// Which accesses a watchpoint stops on.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    Access,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Watchpoint {
+    range: Range<Address>,
+    kind: WatchKind,
+}
+
+impl Watchpoint {
+    /// `kind` is `Access` for an AMO, which any watchpoint stops on
+    fn hits(&self, address: Address, size: Address, kind: WatchKind) -> bool {
+        (self.kind == WatchKind::Access || kind == WatchKind::Access || self.kind == kind)
+            && address < self.range.end
+            && self.range.start < address.saturating_add(size)
+    }
+}
+
+/// The breakpoints and watchpoints the run loop checks.
+#[derive(Debug, Default)]
+pub struct Watches {
+    breakpoints: HashSet<Address>,
+    watchpoints: Vec<Watchpoint>,
+    // one bit per integer register
+    registers: u32,
+}
+
+impl Watches {
+    pub fn set_breakpoint(&mut self, pc: Address) {
+        self.breakpoints.insert(pc);
+    }
+
+    /// `false` if there was no breakpoint at `pc`
+    pub fn remove_breakpoint(&mut self, pc: Address) -> bool {
+        self.breakpoints.remove(&pc)
+    }
+
+    /// no breakpoint, watchpoint or watched register is set
+    pub fn is_empty(&self) -> bool {
+        self.breakpoints.is_empty() && self.watchpoints.is_empty() && self.registers == 0
+    }
+
+    pub fn is_breakpoint(&self, pc: Address) -> bool {
+        !self.breakpoints.is_empty() && self.breakpoints.contains(&pc)
+    }
+
+    pub fn set_watchpoint(&mut self, range: Range<Address>, kind: WatchKind) {
+        self.watchpoints.push(Watchpoint { range, kind });
+    }
+
+    /// `false` if no watchpoint matches both `range` and `kind`
+    pub fn remove_watchpoint(&mut self, range: Range<Address>, kind: WatchKind) -> bool {
+        let watchpoint = Watchpoint { range, kind };
+        match self.watchpoints.iter().position(|w| *w == watchpoint) {
+            Some(i) => {
+                self.watchpoints.remove(i);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// panics unless `register` names one of x0..x31
+    pub fn watch_register(&mut self, register: u8) {
+        assert!(
+            (register as usize) < RISC_V_32_REGISTERS,
+            "x{register} is not an integer register"
+        );
+        self.registers |= 1 << register;
+    }
+
+    /// nothing is watched past x31, so those are ignored
+    pub fn unwatch_register(&mut self, register: u8) {
+        self.registers &= !1u32.checked_shl(register as u32).unwrap_or(0);
+    }
+
+    /// the watchpoint the step hit, if any
+    pub fn check(&self, outcome: &StepOutcome) -> Option<StoppedAt> {
+        self.check_effects(outcome.register, outcome.memory)
+    }
+
+    /// the watchpoint a register write or memory access hits, if any
+    pub fn check_effects(
+        &self,
+        register: Option<RegisterWrite>,
+        memory: Option<MemoryAccess>,
+    ) -> Option<StoppedAt> {
+        if let Some(write) = register
+            && self.registers & 1 << write.register != 0
+        {
+            return Some(StoppedAt::RegisterWatch {
+                register: write.register,
+                old: write.old,
+                new: write.new,
+            });
+        }
+        let (addr, size, kind, old, new) = match memory? {
+            MemoryAccess::Load {
+                address,
+                size,
+                value,
+            } => (address, size, WatchKind::Read, Some(value), value),
+            MemoryAccess::Store {
+                address,
+                size,
+                old,
+                new,
+            } => (address, size, WatchKind::Write, old, new),
+            MemoryAccess::Amo {
+                address,
+                size,
+                old,
+                new,
+            } => (address, size, WatchKind::Access, Some(old), new),
+        };
+        self.watchpoints
+            .iter()
+            .find(|w| w.hits(addr, size, kind))
+            .map(|w| StoppedAt::Watch {
+                kind: w.kind,
+                addr,
+                old,
+                new,
+            })
+    }
+}
@@ -0,0 +1,442 @@
+use crate::arch::{Address, RISC_V_32_REGISTERS, Xlen};
+use crate::emulator::EmulatorContext;
+use crate::emulator::debug::{StoppedAt, WatchKind};
+use crate::emulator::step::StepStatus;
+use crate::exception::{Exception, Trap};
+use crate::mmu::Access;
+use crate::register::{ABI_NAMES, Register};
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+// GDB's own signal numbers, not the host's
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGBUS: u8 = 10;
+const SIGSEGV: u8 = 11;
+
+const PC_REGNUM: usize = RISC_V_32_REGISTERS;
+// instructions run between checks for a ^C from GDB
+const INTERRUPT_POLL: u64 = 1 << 12;
+
+// only x0..x15 on RV32E, pc keeps its regnum
+fn target_xml(xlen: Xlen, registers: u8) -> String {
+    let bits = xlen.bits();
+    let mut xml = String::from(concat!(
+        r#"<?xml version="1.0"?>"#,
+        r#"<!DOCTYPE target SYSTEM "gdb-target.dtd">"#,
+        r#"<target version="1.0">"#,
+    ));
+    xml += &format!("<architecture>riscv:rv{bits}</architecture>");
+    xml += r#"<feature name="org.gnu.gdb.riscv.cpu">"#;
+    for (regnum, name) in ABI_NAMES.iter().enumerate().take(registers as usize) {
+        let ty = match *name {
+            "ra" => "code_ptr",
+            "sp" | "gp" | "tp" | "fp" => "data_ptr",
+            _ => "int",
+        };
+        xml += &format!(r#"<reg name="{name}" bitsize="{bits}" type="{ty}" regnum="{regnum}"/>"#);
+    }
+    xml += &format!(r#"<reg name="pc" bitsize="{bits}" type="code_ptr" regnum="{PC_REGNUM}"/>"#);
+    xml += "</feature></target>";
+    xml
+}
+
+// registers go over the wire as little-endian hex, XLEN bits of it
+fn register_hex(value: Register, xlen: Xlen) -> String {
+    value.to_le_bytes()[..xlen.bytes()]
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+fn parse_register(hex: &str, xlen: Xlen) -> Option<Register> {
+    let bytes = decode_hex_bytes(hex).filter(|bytes| bytes.len() == xlen.bytes())?;
+    let mut value = [0; 8];
+    value[..bytes.len()].copy_from_slice(&bytes);
+    Some(Register::from_le_bytes(value))
+}
+
+fn parse_hex(hex: &str) -> Option<Address> {
+    Address::from_str_radix(hex, 16).ok()
+}
+
+fn decode_hex_bytes(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// `addr,len` as in the `m`, `M` and `Z` packets
+fn parse_range(args: &str) -> Option<(Address, Address)> {
+    let (address, len) = args.split_once(',')?;
+    Some((parse_hex(address)?, parse_hex(len)?))
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+}
+
+/// One GDB connection. Acks are sent and expected until GDB asks for
+/// no-ack mode.
+struct Connection {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    ack: bool,
+    last: Vec<u8>,
+}
+
+impl Connection {
+    fn new(stream: TcpStream) -> std::io::Result<Self> {
+        Ok(Self {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+            ack: true,
+            last: Vec::new(),
+        })
+    }
+
+    fn read_byte(&mut self) -> std::io::Result<Option<u8>> {
+        let mut byte = [0];
+        match self.reader.read(&mut byte)? {
+            0 => Ok(None),
+            _ => Ok(Some(byte[0])),
+        }
+    }
+
+    /// the next packet's payload, `None` once GDB has hung up
+    fn read_packet(&mut self) -> std::io::Result<Option<String>> {
+        loop {
+            match self.read_byte()? {
+                None => return Ok(None),
+                Some(b'$') => {}
+                Some(b'-') => {
+                    let last = std::mem::take(&mut self.last);
+                    self.writer.write_all(&last)?;
+                    self.last = last;
+                    continue;
+                }
+                // acks, and a ^C that arrived while the target was already stopped
+                Some(_) => continue,
+            }
+            let mut data = Vec::new();
+            if self.reader.read_until(b'#', &mut data)? == 0 {
+                return Ok(None);
+            }
+            data.pop();
+            let mut sum = [0; 2];
+            self.reader.read_exact(&mut sum)?;
+            let valid = std::str::from_utf8(&sum)
+                .ok()
+                .and_then(|sum| u8::from_str_radix(sum, 16).ok())
+                == Some(checksum(&data));
+            if self.ack {
+                self.writer.write_all(if valid { b"+" } else { b"-" })?;
+            }
+            if valid {
+                return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+            }
+        }
+    }
+
+    fn send(&mut self, data: &str) -> std::io::Result<()> {
+        let packet = format!("${}#{:02x}", data, checksum(data.as_bytes()));
+        self.writer.write_all(packet.as_bytes())?;
+        self.last = packet.into_bytes();
+        Ok(())
+    }
+
+    /// whether GDB has sent a ^C, without blocking
+    fn interrupted(&mut self) -> std::io::Result<bool> {
+        if !self.reader.buffer().is_empty() {
+            return Ok(self.reader.buffer().contains(&0x03));
+        }
+        self.reader.get_ref().set_nonblocking(true)?;
+        let mut byte = [0];
+        let read = self.reader.get_ref().peek(&mut byte);
+        self.reader.get_ref().set_nonblocking(false)?;
+        match read {
+            Ok(1) if byte[0] == 0x03 => {
+                self.read_byte()?;
+                Ok(true)
+            }
+            Ok(_) => Ok(false),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+fn trap_signal(trap: &Trap) -> u8 {
+    match trap.exception {
+        Exception::IllegalInstruction(_) => SIGILL,
+        Exception::InstructionAddressMisaligned(_)
+        | Exception::LoadAddressMisaligned(_)
+        | Exception::StoreAddressMisaligned(_) => SIGBUS,
+        Exception::InstructionAccessFault(_)
+        | Exception::LoadAccessFault(_)
+        | Exception::StoreAccessFault(_)
+        | Exception::InstructionPageFault(_)
+        | Exception::LoadPageFault(_)
+        | Exception::StorePageFault(_) => SIGSEGV,
+        Exception::Breakpoint(_)
+        | Exception::EnvironmentCallFromUMode
+        | Exception::EnvironmentCallFromSMode
+        | Exception::EnvironmentCallFromMMode => SIGTRAP,
+    }
+}
+
+impl EmulatorContext {
+    /// Accepts one GDB connection on `listener` and serves it until GDB
+    /// detaches, kills the target or hangs up.
+    ///
+    /// `target remote localhost:<port>` from `riscv32-unknown-elf-gdb`.
+    pub fn serve_gdb(&mut self, listener: TcpListener) -> std::io::Result<()> {
+        let (stream, _) = listener.accept()?;
+        stream.set_nodelay(true)?;
+        let mut connection = Connection::new(stream)?;
+        while let Some(packet) = connection.read_packet()? {
+            let reply = match packet.as_bytes().first() {
+                Some(b'c') => self.gdb_continue(&mut connection, &packet[1..])?,
+                Some(b'D') => {
+                    connection.send("OK")?;
+                    return Ok(());
+                }
+                Some(b'k') => return Ok(()),
+                _ => self.gdb_command(&mut connection, &packet),
+            };
+            connection.send(&reply)?;
+        }
+        Ok(())
+    }
+
+    fn gdb_command(&mut self, connection: &mut Connection, packet: &str) -> String {
+        let command = packet.get(..1).unwrap_or_default();
+        let args = packet.get(1..).unwrap_or_default();
+        let reply = match command {
+            "?" => Some(format!("S{SIGTRAP:02x}")),
+            "g" => Some(self.gdb_read_registers()),
+            "G" => self.gdb_write_registers(args),
+            "p" => parse_hex(args).and_then(|regnum| self.gdb_read_register(regnum as usize)),
+            "P" => args.split_once('=').and_then(|(regnum, value)| {
+                let value = parse_register(value, self.xlen())?;
+                self.gdb_write_register(parse_hex(regnum)? as usize, value)
+            }),
+            "m" => parse_range(args).and_then(|(address, len)| self.gdb_read_memory(address, len)),
+            "M" => self.gdb_write_memory(args),
+            "s" => self.gdb_step(args),
+            "b" => self.gdb_reverse(args),
+            "Z" | "z" => self.gdb_watch(command == "Z", args),
+            "H" => Some("OK".to_string()),
+            "q" | "Q" => return self.gdb_query(connection, packet),
+            // vCont, vMustReplyEmpty and everything we don't implement
+            _ => return String::new(),
+        };
+        reply.unwrap_or_else(|| "E01".to_string())
+    }
+
+    fn gdb_query(&mut self, connection: &mut Connection, packet: &str) -> String {
+        if packet.starts_with("qSupported") {
+            let reverse = if self.history.recording() {
+                ";ReverseStep+;ReverseContinue+"
+            } else {
+                ""
+            };
+            return format!("PacketSize=4000;qXfer:features:read+;QStartNoAckMode+{reverse}");
+        }
+        if packet == "QStartNoAckMode" {
+            connection.ack = false;
+            return "OK".to_string();
+        }
+        if packet == "qAttached" {
+            return "1".to_string();
+        }
+        if let Some(args) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            let Some((offset, len)) = parse_range(args) else {
+                return "E01".to_string();
+            };
+            let xml = target_xml(self.xlen(), self.registers.count());
+            let start = (offset as usize).min(xml.len());
+            let end = (start + len as usize).min(xml.len());
+            let more = if end < xml.len() { 'm' } else { 'l' };
+            return format!("{more}{}", &xml[start..end]);
+        }
+        String::new()
+    }
+
+    fn gdb_read_register(&self, regnum: usize) -> Option<String> {
+        match regnum {
+            PC_REGNUM => Some(register_hex(self.program_counter, self.xlen())),
+            n if n < self.registers.count() as usize => {
+                Some(register_hex(self.registers.get(n as u8), self.xlen()))
+            }
+            _ => None,
+        }
+    }
+
+    fn gdb_write_register(&mut self, regnum: usize, value: Register) -> Option<String> {
+        match regnum {
+            PC_REGNUM => self.program_counter = self.xlen().truncate(value),
+            n if n < self.registers.count() as usize => self.registers.write(n as u8, value),
+            _ => return None,
+        }
+        // the undo log can't go back past an edit it didn't record
+        self.history.clear();
+        Some("OK".to_string())
+    }
+
+    fn gdb_read_registers(&self) -> String {
+        (0..=PC_REGNUM)
+            .filter_map(|regnum| self.gdb_read_register(regnum))
+            .collect()
+    }
+
+    fn gdb_write_registers(&mut self, args: &str) -> Option<String> {
+        let digits = self.xlen().bytes() * 2;
+        // in the order of `gdb_read_registers`, pc after the last one there is
+        let count = self.registers.count() as usize;
+        if args.len() < (count + 1) * digits {
+            return None;
+        }
+        let regnums = (0..count).chain([PC_REGNUM]);
+        for (position, regnum) in regnums.enumerate() {
+            let hex = args.get(position * digits..(position + 1) * digits)?;
+            let value = parse_register(hex, self.xlen())?;
+            self.gdb_write_register(regnum, value);
+        }
+        Some("OK".to_string())
+    }
+
+    /// Device registers aren't read, reading them could have side effects.
+    /// Addresses are virtual, as the hart would see them at its privilege.
+    fn gdb_read_memory(&self, address: Address, len: Address) -> Option<String> {
+        let bytes = (address..address.saturating_add(len))
+            .map_while(|address| {
+                let physical = self.memory.debug_translate(address, Access::Load)?;
+                self.memory.peek(physical, 1)
+            })
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<String>();
+        // a partial read is fine, an empty one is an error
+        (len == 0 || !bytes.is_empty()).then_some(bytes)
+    }
+
+    fn gdb_write_memory(&mut self, args: &str) -> Option<String> {
+        let (range, data) = args.split_once(':')?;
+        let (address, len) = parse_range(range)?;
+        let data = decode_hex_bytes(data)?;
+        if data.len() != len as usize {
+            return None;
+        }
+        // every page has to be mapped before any byte is written
+        let physical = (address..)
+            .take(data.len())
+            .map(|address| self.memory.debug_translate(address, Access::Store))
+            .collect::<Option<Vec<_>>>()?;
+        for (address, byte) in physical.iter().zip(data) {
+            self.memory.write_byte(address, byte)?;
+        }
+        self.history.clear();
+        Some("OK".to_string())
+    }
+
+    fn gdb_watch(&mut self, insert: bool, args: &str) -> Option<String> {
+        let (kind, range) = args.split_once(',')?;
+        let (address, len) = parse_range(range)?;
+        let watch = match kind {
+            // software and hardware breakpoints are the same thing here
+            "0" | "1" => None,
+            "2" => Some(WatchKind::Write),
+            "3" => Some(WatchKind::Read),
+            "4" => Some(WatchKind::Access),
+            _ => return Some(String::new()),
+        };
+        let range = address..address.saturating_add(len);
+        match (watch, insert) {
+            (None, true) => {
+                self.set_breakpoint(address);
+            }
+            (None, false) => {
+                self.remove_breakpoint(address);
+            }
+            (Some(kind), true) => {
+                self.set_watchpoint(range, kind);
+            }
+            (Some(kind), false) => {
+                self.remove_watchpoint(range, kind);
+            }
+        }
+        Some("OK".to_string())
+    }
+
+    fn gdb_resume_at(&mut self, args: &str) -> Option<()> {
+        if !args.is_empty() {
+            self.program_counter = parse_hex(args)?;
+        }
+        Some(())
+    }
+
+    fn gdb_stop_reply(&self, stopped: Result<StoppedAt, Trap>) -> String {
+        match stopped {
+            Ok(StoppedAt::Halted) => format!("W{:02x}", self.exit_code.unwrap_or(0) as u8),
+            Ok(StoppedAt::Watch { kind, addr, .. }) => {
+                let reason = match kind {
+                    WatchKind::Write => "watch",
+                    WatchKind::Read => "rwatch",
+                    WatchKind::Access => "awatch",
+                };
+                format!("T{SIGTRAP:02x}{reason}:{addr:x};")
+            }
+            Ok(StoppedAt::HistoryStart) => format!("T{SIGTRAP:02x}replaylog:begin;"),
+            Ok(
+                StoppedAt::Breakpoint(_)
+                | StoppedAt::RegisterWatch { .. }
+                | StoppedAt::InstructionLimit
+                | StoppedAt::Deadline,
+            ) => format!("S{SIGTRAP:02x}"),
+            Err(trap) => format!("S{:02x}", trap_signal(&trap)),
+        }
+    }
+
+    fn gdb_step(&mut self, args: &str) -> Option<String> {
+        self.gdb_resume_at(args)?;
+        let outcome = self.step();
+        let stopped = match outcome.status {
+            StepStatus::Halted => Ok(StoppedAt::Halted),
+            StepStatus::Trap(trap) => Err(trap),
+            StepStatus::Retired | StepStatus::Handled(_) => {
+                Ok(StoppedAt::Breakpoint(self.program_counter))
+            }
+        };
+        Some(self.gdb_stop_reply(stopped))
+    }
+
+    /// `bs` and `bc`, through the undo log
+    fn gdb_reverse(&mut self, args: &str) -> Option<String> {
+        let stopped = match args {
+            "s" if self.step_back() => StoppedAt::Breakpoint(self.program_counter),
+            "s" => StoppedAt::HistoryStart,
+            "c" => self.reverse_continue(),
+            _ => return None,
+        };
+        Some(self.gdb_stop_reply(Ok(stopped)))
+    }
+
+    fn gdb_continue(&mut self, connection: &mut Connection, args: &str) -> std::io::Result<String> {
+        if self.gdb_resume_at(args).is_none() {
+            return Ok("E01".to_string());
+        }
+        loop {
+            if let Some(stopped) = self.run_bounded(INTERRUPT_POLL) {
+                return Ok(self.gdb_stop_reply(stopped));
+            }
+            if connection.interrupted()? {
+                return Ok(format!("S{SIGINT:02x}"));
+            }
+        }
+    }
+}
@@ -1,17 +1,53 @@
-use crate::alu::ALU;
-use crate::arch::{Address, Byte, PC_DEFAULT_ADDRESS, PC_STEP, STACK_DEFAULT_ADDRESS};
+pub mod block;
+pub mod debug;
+pub mod gdb;
+pub mod reverse;
+pub mod snapshot;
+pub mod step;
+pub mod syscall;
+pub mod trace;
+
+use crate::arch::{Address, IALIGN, PC_DEFAULT_ADDRESS, Privilege, STACK_DEFAULT_ADDRESS, Xlen};
+use crate::decode::{Decoded, Op};
+use crate::device::Device;
+use crate::device::clint::{CLINT_BASE, CLINT_SIZE, Clint, ClockSource};
+use crate::exception::{Exception, Interrupt, Trap};
 use crate::instruction_type::Instruction;
 use crate::memory::MemoryWrapper;
-use crate::register::Registers;
+use crate::register::csr::CsrWrite;
+use crate::register::{CsrRegisters, FloatRegisters, Register, Registers};
+use block::{BlockCache, ExecutionMode};
+use debug::{RunReport, StoppedAt, WatchKind, Watches};
+use reverse::History;
+use std::ops::Range;
+use std::time::Instant;
+use step::{FloatWrite, RegisterWrite, StepOutcome, StepStatus};
+use syscall::{SyscallHandler, SyscallResult};
+use trace::TraceSink;
 
 pub struct EmulatorContext {
     pub(crate) registers: Registers,
+    pub(crate) float: FloatRegisters,
+    pub(crate) csr: CsrRegisters,
     memory: MemoryWrapper,
     program_counter: Address,
     max_address: Address,
     data_offset: Address,
     stack_offset: Address,
     stop: bool,
+    retired: u64,
+    exit_code: Option<i32>,
+    syscall_handler: Option<Box<dyn SyscallHandler>>,
+    watches: Watches,
+    // the pc and `retired` of the breakpoint the hart last stopped at, so
+    // running again from there resumes instead of stopping at once
+    resume_from: Option<(Address, u64)>,
+    history: History,
+    trace: Option<Box<dyn TraceSink>>,
+    // the write the last CSR instruction made, for `step`
+    csr_write: Option<CsrWrite>,
+    mode: ExecutionMode,
+    blocks: BlockCache,
 }
 
 impl Default for EmulatorContext {
@@ -20,12 +56,24 @@ impl Default for EmulatorContext {
         *regs.sp() = STACK_DEFAULT_ADDRESS;
         Self {
             registers: regs,
+            float: FloatRegisters::default(),
+            csr: CsrRegisters::default(),
             memory: MemoryWrapper::default(),
             program_counter: PC_DEFAULT_ADDRESS,
             max_address: 0,
             data_offset: 0,
             stack_offset: STACK_DEFAULT_ADDRESS,
             stop: false,
+            retired: 0,
+            exit_code: None,
+            syscall_handler: None,
+            watches: Watches::default(),
+            resume_from: None,
+            history: History::default(),
+            trace: None,
+            csr_write: None,
+            mode: ExecutionMode::default(),
+            blocks: BlockCache::default(),
         }
     }
 }
@@ -53,59 +101,525 @@ impl EmulatorContext {
         self
     }
 
-    fn execute(&mut self, instruction: &Instruction) {
-        use crate::opcode::*;
-        match instruction.opcode() as Byte {
-            I_TYPE => self.memory.load(&mut self.registers, instruction.as_i()),
-            RI_TYPE => ALU::with(&mut self.registers).immediate(instruction.as_i()),
-            R_TYPE => ALU::with(&mut self.registers).execute(instruction.as_r()),
-            S_TYPE => self.memory.store(&mut self.registers, instruction.as_s()),
-            J_TYPE => {
-                let j = instruction.as_j();
-                *self.registers.get_mut(j.rd()) = self.program_counter;
-                self.program_counter = ((self.program_counter - PC_STEP) as i32 + j.imm()) as u32;
+    /// Runs the hart as RV32 or RV64, RV32 unless this is called. The
+    /// registers and CSRs keep their low XLEN bits, and what was decoded is
+    /// decoded again.
+    pub fn set_xlen(&mut self, xlen: Xlen) -> &mut Self {
+        self.csr.set_xlen(xlen);
+        self.registers.set_xlen(xlen);
+        for i in 1..32 {
+            self.registers.write(i, self.registers.get(i));
+        }
+        self.program_counter = xlen.truncate(self.program_counter);
+        self.memory.forget_code();
+        self
+    }
+
+    /// what misa.MXL says
+    pub fn xlen(&self) -> Xlen {
+        self.csr.xlen()
+    }
+
+    /// Runs the hart as RV32E, with only x0..x15. Instructions naming x16..x31
+    /// are illegal, and `ecall` takes the syscall number in t0 as ilp32e does.
+    pub fn set_embedded(&mut self, embedded: bool) -> &mut Self {
+        self.csr.set_embedded(embedded);
+        self.registers.set_embedded(embedded);
+        self.memory.forget_code();
+        self
+    }
+
+    /// what misa.E says
+    pub fn embedded(&self) -> bool {
+        self.csr.embedded()
+    }
+
+    /// mtime counts retired instructions unless this selects the host clock.
+    /// The CLINT keeps mtime, mtimecmp and msip; if something else was
+    /// mapped over it, a fresh one is mapped back.
+    pub fn set_clock_source(&mut self, source: ClockSource) -> &mut Self {
+        if let Some(clint) = self.memory.device_at::<Clint>(CLINT_BASE) {
+            clint.set_clock_source(source);
+            return self;
+        }
+        let mut clint = Clint::default();
+        clint.set_clock_source(source);
+        self.map_device(CLINT_BASE, CLINT_SIZE, clint)
+    }
+
+    /// loads and stores to `base..base + size` go to `device` instead of RAM
+    pub fn map_device(
+        &mut self,
+        base: Address,
+        size: Address,
+        device: impl Device + 'static,
+    ) -> &mut Self {
+        self.memory.map_device(base, size, Box::new(device));
+        self
+    }
+
+    /// without a handler `ecall` and `ebreak` trap, and so does an `ecall`
+    /// the guest delegates to S-mode
+    pub fn set_syscall_handler(&mut self, handler: impl SyscallHandler + 'static) -> &mut Self {
+        self.syscall_handler = Some(Box::new(handler));
+        self
+    }
+
+    /// every retired instruction is reported to `sink`
+    pub fn set_trace_sink(&mut self, sink: impl TraceSink + 'static) -> &mut Self {
+        self.trace = Some(Box::new(sink));
+        self
+    }
+
+    /// `run` stops before executing the instruction at `pc`
+    pub fn set_breakpoint(&mut self, pc: Address) -> &mut Self {
+        self.watches.set_breakpoint(pc);
+        self
+    }
+
+    pub fn remove_breakpoint(&mut self, pc: Address) -> bool {
+        self.watches.remove_breakpoint(pc)
+    }
+
+    /// `run` stops after an instruction accesses `range` in the way `kind` selects
+    pub fn set_watchpoint(&mut self, range: Range<Address>, kind: WatchKind) -> &mut Self {
+        self.watches.set_watchpoint(range, kind);
+        self
+    }
+
+    pub fn remove_watchpoint(&mut self, range: Range<Address>, kind: WatchKind) -> bool {
+        self.watches.remove_watchpoint(range, kind)
+    }
+
+    /// `run` stops after an instruction writes `register`, which must be one
+    /// of x0..x31
+    pub fn watch_register(&mut self, register: u8) -> &mut Self {
+        self.watches.watch_register(register);
+        self
+    }
+
+    pub fn unwatch_register(&mut self, register: u8) -> &mut Self {
+        self.watches.unwatch_register(register);
+        self
+    }
+
+    /// instructions retired since the context was created
+    pub fn retired(&self) -> u64 {
+        self.retired
+    }
+
+    /// the status the guest passed to `SyscallResult::Halt`, if it exited that way
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit_code
+    }
+
+    /// `instruction` is expanded, `pc` is its address
+    fn environment(&mut self, instruction: &Instruction, pc: Address) -> Result<(), Exception> {
+        let i = instruction.as_i();
+        let illegal = Exception::IllegalInstruction(instruction.0);
+        let privilege = self.csr.privilege();
+        // sfence.vma, the address and ASID in rs1 and rs2 aren't looked at,
+        // every translation is dropped along with the code decoded through it
+        if i.umm() >> 5 == 0b0001001 && i.rd() == 0 {
+            if privilege == Privilege::User {
+                return Err(illegal);
             }
-            JALR => {
-                let i = instruction.as_i();
-                *self.registers.get_mut(i.rd()) = self.program_counter;
-                self.program_counter =
-                    (((self.registers.get(i.rs1())) & !1) as i32 + i.imm()) as Address;
+            self.memory.flush_translations();
+            self.memory.forget_code();
+            return Ok(());
+        }
+        if i.rd() != 0 || i.rs1() != 0 {
+            return Err(illegal);
+        }
+        // mret and sret, which also drop the reservation so an `sc.w`
+        // interrupted after its `lr.w` fails
+        if i.umm() == 0x302 {
+            if privilege != Privilege::Machine {
+                return Err(illegal);
             }
-            B_TYPE => {
-                ALU::with(&mut self.registers).branch(&mut self.program_counter, instruction.as_b())
+            self.memory.clear_reservation();
+            self.program_counter = self.csr.trap_return();
+            return Ok(());
+        }
+        if i.umm() == 0x102 {
+            if privilege == Privilege::User {
+                return Err(illegal);
             }
-            LUI => {
-                let u = instruction.as_u();
-                *self.registers.get_mut(u.rd()) =
-                    (self.registers.get(u.rd()) & 0xFFF) + u.high_imm();
+            self.memory.clear_reservation();
+            self.program_counter = self.csr.supervisor_return();
+            return Ok(());
+        }
+        // wfi, the interrupt is taken before the next instruction anyway
+        if i.umm() == 0x105 {
+            return Ok(());
+        }
+        let ecall = match privilege {
+            Privilege::User => Exception::EnvironmentCallFromUMode,
+            Privilege::Supervisor => Exception::EnvironmentCallFromSMode,
+            Privilege::Machine => Exception::EnvironmentCallFromMMode,
+        };
+        // an ecall delegated to S-mode belongs to the guest kernel, the host
+        // only takes the ones that would reach M-mode
+        let handler = match i.umm() {
+            0 if self.csr.delegated(false, ecall.code()) => None,
+            _ => self.syscall_handler.as_mut(),
+        };
+        let result = match (i.umm(), handler) {
+            (0, Some(handler)) => handler.ecall(pc, &mut self.registers, &mut self.memory),
+            (1, Some(handler)) => handler.ebreak(pc, &mut self.registers, &mut self.memory),
+            (0, None) => SyscallResult::Trap(ecall),
+            (1, None) => SyscallResult::Trap(Exception::Breakpoint(pc)),
+            _ => return Err(illegal),
+        };
+        match result {
+            SyscallResult::Continue => Ok(()),
+            SyscallResult::Halt(code) => {
+                self.exit_code = Some(code);
+                self.stop = true;
+                Ok(())
             }
-            AUIPC => {
-                let u = instruction.as_u();
-                *self.registers.get_mut(u.rd()) = self.program_counter + u.high_imm();
+            SyscallResult::Trap(exception) => Err(exception),
+        }
+    }
+
+    fn jump(&mut self, target: Address) -> Result<(), Exception> {
+        let target = self.xlen().truncate(target);
+        if !target.is_multiple_of(IALIGN) {
+            return Err(Exception::InstructionAddressMisaligned(target));
+        }
+        self.program_counter = target;
+        Ok(())
+    }
+
+    fn execute(&mut self, d: &Decoded) -> Result<(), Exception> {
+        let xlen = self.xlen();
+        let rs1 = self.registers.get(d.rs1);
+        let rs2 = self.registers.get(d.rs2);
+        // the pc of this instruction, `program_counter` is already past it
+        let pc = self.program_counter.wrapping_sub(d.len as Address);
+        // loads, stores and atomics wrap around the XLEN address space
+        let address = xlen.truncate(rs1.wrapping_add(d.imm));
+        match d.op {
+            Op::Alu(op) => self.registers.write(d.rd, op.apply(xlen, rs1, rs2)),
+            Op::AluImm(op) => self.registers.write(d.rd, op.apply(xlen, rs1, d.imm)),
+            Op::AluWord(op) => self.registers.write(d.rd, op.apply_word(rs1, rs2)),
+            Op::AluImmWord(op) => self.registers.write(d.rd, op.apply_word(rs1, d.imm)),
+            Op::Load { size, signed } => {
+                let value = self.memory.load(address, size as Address, signed)?;
+                self.registers.write(d.rd, value);
+            }
+            Op::Store { size } => self.memory.store(address, size as Address, rs2)?,
+            Op::Branch(op) => {
+                if op.taken(xlen, rs1, rs2) {
+                    self.jump(pc.wrapping_add(d.imm))?;
+                }
+            }
+            Op::Jal => {
+                let link = self.program_counter;
+                self.jump(pc.wrapping_add(d.imm))?;
+                self.registers.write(d.rd, link);
+            }
+            Op::Jalr => {
+                let link = self.program_counter;
+                self.jump(rs1.wrapping_add(d.imm) & !1)?;
+                self.registers.write(d.rd, link);
+            }
+            Op::Lui => self.registers.write(d.rd, d.imm),
+            Op::Auipc => self.registers.write(d.rd, pc.wrapping_add(d.imm)),
+            Op::LoadReserved { size } => {
+                let value = self
+                    .memory
+                    .load_reserved(xlen.truncate(rs1), size as Address)?;
+                self.registers.write(d.rd, value);
+            }
+            Op::StoreConditional { size } => {
+                // 0 if the store happened
+                let stored =
+                    self.memory
+                        .store_conditional(xlen.truncate(rs1), size as Address, rs2)?;
+                self.registers.write(d.rd, !stored as Register);
+            }
+            Op::Amo { op, size } => {
+                let width = if size == 4 { Xlen::X32 } else { Xlen::X64 };
+                let old = self
+                    .memory
+                    .amo(xlen.truncate(rs1), size as Address, |old| {
+                        op.apply(width, old, rs2)
+                    })?;
+                // a word is sign-extended
+                let old = if size == 4 {
+                    old as i32 as Register
+                } else {
+                    old
+                };
+                self.registers.write(d.rd, old);
+            }
+            Op::FloatLoad { size } => {
+                if !self.csr.float_enabled() {
+                    return Err(Exception::IllegalInstruction(d.word));
+                }
+                if size == 4 {
+                    let value = self.memory.load(address, 4, false)?;
+                    self.float.write_single(d.rd, value as u32);
+                } else {
+                    let value = self.memory.load_double(address)?;
+                    self.float.write(d.rd, value);
+                }
+                self.csr.set_float_dirty();
+            }
+            Op::FloatStore { size } => {
+                if !self.csr.float_enabled() {
+                    return Err(Exception::IllegalInstruction(d.word));
+                }
+                // `fsw` stores the low half whether it's boxed or not
+                let value = self.float.get(d.rs2);
+                if size == 4 {
+                    self.memory.store(address, 4, value)?;
+                } else {
+                    self.memory.store_double(address, value)?;
+                }
+            }
+            Op::Float(op, format) => {
+                return self
+                    .float
+                    .execute(&mut self.registers, &mut self.csr, op, format, d);
+            }
+            Op::Fence => {}
+            // the block cache follows the code generation this bumps
+            Op::FenceI => self.memory.forget_code(),
+            Op::System => {
+                // c.ebreak is the only compressed one
+                let instruction = Instruction(d.word).expanded(xlen);
+                if instruction.as_i().funct3() == 0 {
+                    return self.environment(&instruction, pc);
+                }
+                self.csr_write = self.csr.execute(&mut self.registers, instruction.as_i())?;
+            }
+            Op::Stop => self.stop = true,
+            Op::Illegal => return Err(Exception::IllegalInstruction(d.word)),
+        }
+        Ok(())
+    }
+
+    /// Hands the trap to the guest handler in mtvec, or stvec when it is
+    /// delegated. Until the guest has installed one, the trap is returned to
+    /// the host instead.
+    fn take_trap(&mut self, trap: Trap) -> StepStatus {
+        if !self.csr.has_trap_vector(false, trap.exception.code()) {
+            self.program_counter = trap.pc;
+            return StepStatus::Trap(trap);
+        }
+        self.program_counter =
+            self.csr
+                .trap_enter(trap.pc, false, trap.exception.code(), trap.exception.tval());
+        StepStatus::Handled(trap.exception)
+    }
+
+    /// enters the handler of the highest priority enabled interrupt, if any
+    fn take_interrupt(&mut self) -> Option<Interrupt> {
+        self.csr.set_pending(self.memory.pending_interrupts());
+        let interrupt = self.csr.pending_interrupt()?;
+        self.program_counter = self
+            .csr
+            .trap_enter(self.program_counter, true, interrupt.code(), 0);
+        Some(interrupt)
+    }
+
+    /// Executes exactly one instruction. A pending interrupt is taken first,
+    /// and once the hart has halted nothing is executed.
+    pub fn step(&mut self) -> StepOutcome {
+        if !self.history.recording() || self.stop {
+            return self.step_once();
+        }
+        let pc = self.program_counter;
+        let csr = self.csr.clone();
+        let registers = self.registers.clone();
+        let float = self.float.clone();
+        let reservation = self.memory.reservation();
+        self.memory.log_pte_writes();
+        let outcome = self.step_once();
+        let pte_writes = self.memory.take_pte_log();
+        self.history.record(
+            pc,
+            (csr, registers, float, reservation),
+            &outcome,
+            (&self.csr, &self.float),
+            pte_writes,
+        );
+        outcome
+    }
+
+    fn step_once(&mut self) -> StepOutcome {
+        let mut outcome = StepOutcome {
+            interrupt: None,
+            pc: self.program_counter,
+            instruction: Instruction::default(),
+            xlen: self.xlen(),
+            privilege: self.csr.privilege(),
+            register: None,
+            float: None,
+            csr: None,
+            memory: None,
+            status: StepStatus::Halted,
+        };
+        if self.stop {
+            return outcome;
+        }
+        outcome.interrupt = self.take_interrupt();
+        outcome.privilege = self.csr.privilege();
+        self.memory.set_translation(self.csr.translation());
+        let pc = self.program_counter;
+        outcome.pc = pc;
+        let xlen = self.xlen();
+        let decoded = match self.memory.fetch_decoded(pc, xlen, self.registers.count()) {
+            Ok(decoded) => decoded,
+            Err(exception) => {
+                outcome.status = self.take_trap(Trap {
+                    pc,
+                    instruction: 0,
+                    exception,
+                });
+                return outcome;
+            }
+        };
+        let i = Instruction(decoded.word);
+        outcome.instruction = i;
+        let destination = step::destination(&i, xlen);
+        let old = destination.map(|rd| self.registers.get(rd));
+        let float_destination = step::float_destination(&i, xlen);
+        let old_float = float_destination.map(|rd| self.float.get(rd));
+        self.program_counter = xlen.truncate(pc.wrapping_add(decoded.len as Address));
+        self.csr_write = None;
+        let result = self.execute(&decoded);
+        let memory = self.memory.take_access();
+        outcome.status = match result {
+            Ok(()) => {
+                self.retired += 1;
+                self.memory.tick_devices();
+                outcome.memory = memory;
+                outcome.register = destination.zip(old).map(|(register, old)| RegisterWrite {
+                    register,
+                    old,
+                    new: self.registers.get(register),
+                });
+                outcome.float =
+                    float_destination
+                        .zip(old_float)
+                        .map(|(register, old)| FloatWrite {
+                            register,
+                            old,
+                            new: self.float.get(register),
+                        });
+                outcome.csr = self.csr_write.take();
+                outcome.status = if self.stop {
+                    StepStatus::Halted
+                } else {
+                    StepStatus::Retired
+                };
+                if let Some(trace) = self.trace.as_mut() {
+                    trace.retire(&outcome);
+                }
+                outcome.status
+            }
+            Err(exception) => self.take_trap(Trap {
+                pc,
+                instruction: i.0,
+                exception,
+            }),
+        };
+        outcome
+    }
+
+    /// Runs until the guest reaches the stop word or hits a breakpoint or
+    /// watchpoint, or returns the first trap that the guest doesn't handle
+    /// itself. On a trap the pc is left pointing at the faulting instruction.
+    /// A breakpoint on the first instruction stops the run before it
+    /// executes, unless the run resumes from that very breakpoint.
+    pub fn run(&mut self) -> Result<StoppedAt, Trap> {
+        loop {
+            if let Some(stopped) = self.run_bounded(u64::MAX) {
+                return stopped;
+            }
+        }
+    }
+
+    /// `run` for at most `max_instructions` steps
+    pub fn run_for(&mut self, max_instructions: u64) -> RunReport {
+        let start = self.retired;
+        let stopped = self
+            .run_bounded(max_instructions)
+            .unwrap_or(Ok(StoppedAt::InstructionLimit));
+        RunReport {
+            retired: self.retired - start,
+            stopped,
+        }
+    }
+
+    /// `run` until the host clock passes `deadline`
+    pub fn run_until(&mut self, deadline: Instant) -> RunReport {
+        // instructions run between looks at the clock
+        const CLOCK_POLL: u64 = 1 << 12;
+        let start = self.retired;
+        let stopped = loop {
+            if Instant::now() >= deadline {
+                break Ok(StoppedAt::Deadline);
+            }
+            if let Some(stopped) = self.run_bounded(CLOCK_POLL) {
+                break stopped;
             }
-            NOP => self.stop = true,
-            _ => panic!("SegmentFault"),
+        };
+        RunReport {
+            retired: self.retired - start,
+            stopped,
         }
     }
 
-    pub fn run(&mut self) {
-        while !self.stop {
-            let i: Instruction = Instruction::from(self.memory.read_word(&self.program_counter));
-            self.program_counter += PC_STEP;
-            // println!("{}", i);
-            self.execute(&i);
+    /// `run` for at most `limit` instructions, `None` if it didn't stop by then
+    pub(crate) fn run_bounded(&mut self, limit: u64) -> Option<Result<StoppedAt, Trap>> {
+        let resumed = self.resume_from.take() == Some((self.program_counter, self.retired));
+        if !resumed && self.watches.is_breakpoint(self.program_counter) {
+            return Some(Ok(self.stop_at_breakpoint()));
+        }
+        if self.mode == ExecutionMode::Blocks
+            && self.trace.is_none()
+            && !self.history.recording()
+            && self.watches.is_empty()
+        {
+            return self.run_blocks(limit);
         }
+        for _ in 0..limit {
+            let outcome = self.step();
+            match outcome.status {
+                StepStatus::Retired | StepStatus::Handled(_) => {}
+                StepStatus::Halted => return Some(Ok(StoppedAt::Halted)),
+                StepStatus::Trap(trap) => return Some(Err(trap)),
+            }
+            if let Some(stopped) = self.watches.check(&outcome) {
+                return Some(Ok(stopped));
+            }
+            if self.watches.is_breakpoint(self.program_counter) {
+                return Some(Ok(self.stop_at_breakpoint()));
+            }
+        }
+        None
+    }
+
+    // the hart is about to execute a breakpoint, the next run resumes past it
+    fn stop_at_breakpoint(&mut self) -> StoppedAt {
+        self.resume_from = Some((self.program_counter, self.retired));
+        StoppedAt::Breakpoint(self.program_counter)
     }
 
-    pub fn run_with_thread(mut self) {
+    pub fn run_with_thread(mut self) -> Result<(), Trap> {
         std::thread::spawn(move || {
-            self.run();
+            self.run()?;
             println!(
                 "the sorted nums: {:?}",
                 &self.memory.test_get_memory()[..10]
             );
+            Ok(())
         })
         .join()
-        .unwrap();
+        .unwrap()
     }
 }
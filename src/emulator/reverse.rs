@@ -0,0 +1,194 @@
+use crate::arch::{Address, Byte};
+use crate::emulator::EmulatorContext;
+use crate::emulator::debug::StoppedAt;
+use crate::emulator::step::{RegisterWrite, StepOutcome, StepStatus};
+use crate::memory::MemoryAccess;
+use crate::register::{CsrRegisters, FloatRegisters, Registers};
+use std::collections::VecDeque;
+
+/// What it takes to put the hart back to where it was before one step.
+#[derive(Debug)]
+struct Undo {
+    pc: Address,
+    register: Option<RegisterWrite>,
+    memory: Option<MemoryAccess>,
+    // only kept when the step changed them
+    csr: Option<Box<CsrRegisters>>,
+    // ecall and ebreak, the syscall handler may write any register
+    registers: Option<Box<Registers>>,
+    float: Option<Box<FloatRegisters>>,
+    // the reservation before the step, `sc` has to see it again
+    reservation: Option<Address>,
+    // the A and D bits page walks set, `(address, old)` oldest first
+    pte_writes: Vec<(Address, u32)>,
+    retired: bool,
+    halted: bool,
+}
+
+/// The undo log of the last `depth` steps, oldest first.
+#[derive(Debug, Default)]
+pub struct History {
+    depth: usize,
+    entries: VecDeque<Undo>,
+}
+
+impl History {
+    pub fn recording(&self) -> bool {
+        self.depth != 0
+    }
+
+    pub fn set_depth(&mut self, depth: usize) {
+        self.depth = depth;
+        let excess = self.entries.len().saturating_sub(depth);
+        self.entries.drain(..excess);
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// steps that can be undone
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// `pc` and the register files are the state before the step
+    pub(crate) fn record(
+        &mut self,
+        pc: Address,
+        (csr, registers, float, reservation): (
+            CsrRegisters,
+            Registers,
+            FloatRegisters,
+            Option<Address>,
+        ),
+        outcome: &StepOutcome,
+        (csr_now, float_now): (&CsrRegisters, &FloatRegisters),
+        pte_writes: Vec<(Address, u32)>,
+    ) {
+        // a trap handed to the host leaves the hart as it was
+        if matches!(outcome.status, StepStatus::Trap(_)) && outcome.interrupt.is_none() {
+            return;
+        }
+        let instruction = outcome.instruction.expanded(outcome.xlen);
+        let environment = instruction.opcode() as Byte == crate::opcode::SYSTEM
+            && instruction.as_i().funct3() == 0;
+        if self.entries.len() == self.depth {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(Undo {
+            pc,
+            register: outcome.register,
+            memory: outcome.memory,
+            csr: (csr != *csr_now).then(|| Box::new(csr)),
+            registers: environment.then(|| Box::new(registers)),
+            float: (float != *float_now).then(|| Box::new(float)),
+            reservation,
+            pte_writes,
+            retired: matches!(outcome.status, StepStatus::Retired | StepStatus::Halted),
+            halted: outcome.status == StepStatus::Halted,
+        });
+    }
+}
+
+impl EmulatorContext {
+    /// Keeps an undo log of the last `depth` steps for `step_back` and
+    /// `reverse_continue`, 0 (the default) turns it off.
+    ///
+    /// Device state, mtime included, and memory the syscall handler writes
+    /// are not rewound.
+    pub fn set_undo_depth(&mut self, depth: usize) -> &mut Self {
+        self.history.set_depth(depth);
+        self
+    }
+
+    /// the recorded steps, the most `step_back` can undo
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    /// Undoes the last recorded step, `false` once the log is empty.
+    pub fn step_back(&mut self) -> bool {
+        match self.history.entries.pop_back() {
+            Some(undo) => {
+                self.undo(undo);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Steps back until the hart is about to execute a breakpoint again or
+    /// has undone an access a watchpoint covers. `HistoryStart` once every
+    /// recorded step is undone.
+    pub fn reverse_continue(&mut self) -> StoppedAt {
+        while let Some(undo) = self.history.entries.pop_back() {
+            let watch = self.watches.check_effects(undo.register, undo.memory);
+            self.undo(undo);
+            if let Some(stopped) = watch {
+                return stopped;
+            }
+            if self.watches.is_breakpoint(self.program_counter) {
+                return self.stop_at_breakpoint();
+            }
+        }
+        StoppedAt::HistoryStart
+    }
+
+    fn undo(&mut self, undo: Undo) {
+        if let Some(registers) = undo.registers {
+            self.registers = *registers;
+        } else if let Some(write) = undo.register {
+            self.registers.write(write.register, write.old);
+        }
+        // stores to device registers have no old value and stay
+        if let Some(
+            MemoryAccess::Store {
+                address,
+                size,
+                old: Some(old),
+                ..
+            }
+            | MemoryAccess::Amo {
+                address, size, old, ..
+            },
+        ) = undo.memory
+        {
+            match size {
+                1 => self.memory.write_byte(&address, old as u8),
+                2 => self.memory.write_halfword(&address, old as u16),
+                4 => self.memory.write_word(&address, old as u32),
+                _ => self
+                    .memory
+                    .write_word(&address, old as u32)
+                    .and(self.memory.write_word(&(address + 4), (old >> 32) as u32)),
+            };
+        }
+        for &(address, old) in undo.pte_writes.iter().rev() {
+            self.memory.write_word(&address, old);
+        }
+        // satp may have gone back to another page table, and the TLB may
+        // hold the A and D bits just cleared
+        if undo.csr.is_some() || !undo.pte_writes.is_empty() {
+            self.memory.flush_translations();
+        }
+        if let Some(csr) = undo.csr {
+            self.csr = *csr;
+        }
+        // after the stores above, which drop it
+        self.memory.restore_reservation(undo.reservation);
+        if let Some(float) = undo.float {
+            self.float = *float;
+        }
+        self.program_counter = undo.pc;
+        self.retired -= undo.retired as u64;
+        if undo.halted {
+            self.stop = false;
+            self.exit_code = None;
+        }
+    }
+}
@@ -0,0 +1,269 @@
+use crate::arch::{Address, RISC_V_32_REGISTERS};
+use crate::emulator::EmulatorContext;
+use crate::register::Register;
+use crate::register::float::FloatRegister;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+const MAGIC: &[u8; 8] = b"R32ISNAP";
+/// bumped whenever the file layout changes
+pub const SNAPSHOT_VERSION: u32 = 4;
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(std::io::Error),
+    /// not a snapshot file
+    BadMagic,
+    UnsupportedVersion(u32),
+    /// the file ends early or a field is out of range
+    Corrupt,
+    /// the context doesn't have the device at this base mapped the way the
+    /// snapshot does, or the device rejected the saved state
+    DeviceMismatch(Address),
+}
+
+impl Display for SnapshotError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SnapshotError::Io(e) => write!(f, "snapshot I/O error: {}", e),
+            SnapshotError::BadMagic => f.write_str("not a snapshot file"),
+            SnapshotError::UnsupportedVersion(v) => {
+                write!(f, "unsupported snapshot version {}", v)
+            }
+            SnapshotError::Corrupt => f.write_str("corrupt snapshot"),
+            SnapshotError::DeviceMismatch(base) => {
+                write!(f, "snapshot device at {:#010x} doesn't match", base)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl From<std::io::Error> for SnapshotError {
+    fn from(e: std::io::Error) -> Self {
+        match e.kind() {
+            std::io::ErrorKind::UnexpectedEof => SnapshotError::Corrupt,
+            _ => SnapshotError::Io(e),
+        }
+    }
+}
+
+/// The whole machine state. Host-side configuration (syscall handler, trace
+/// sink, breakpoints, device backends) is not part of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    registers: [Register; RISC_V_32_REGISTERS],
+    float_registers: [FloatRegister; RISC_V_32_REGISTERS],
+    program_counter: Address,
+    max_address: Address,
+    data_offset: Address,
+    stack_offset: Address,
+    stop: bool,
+    exit_code: Option<i32>,
+    retired: u64,
+    csr: Vec<u8>,
+    memory: Vec<u32>,
+    // (base, size, state)
+    devices: Vec<(Address, Address, Vec<u8>)>,
+}
+
+impl EmulatorContext {
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            registers: std::array::from_fn(|i| self.registers.get(i as u8)),
+            float_registers: std::array::from_fn(|i| self.float.get(i as u8)),
+            program_counter: self.program_counter,
+            max_address: self.max_address,
+            data_offset: self.data_offset,
+            stack_offset: self.stack_offset,
+            stop: self.stop,
+            exit_code: self.exit_code,
+            retired: self.retired,
+            csr: self.csr.save(),
+            memory: self.memory.ram().to_vec(),
+            devices: self.memory.save_devices(),
+        }
+    }
+
+    /// The context must have the same devices mapped as when the snapshot
+    /// was taken. The CSRs and devices are checked before anything else is
+    /// touched, so on an error the hart and its devices are left as they
+    /// were. The undo log is cleared.
+    pub fn restore(&mut self, snapshot: &Snapshot) -> Result<(), SnapshotError> {
+        let mut csr = self.csr.clone();
+        csr.restore(&snapshot.csr).ok_or(SnapshotError::Corrupt)?;
+        self.memory
+            .restore_devices(&snapshot.devices)
+            .map_err(SnapshotError::DeviceMismatch)?;
+        self.csr = csr;
+        // misa came back with the CSRs
+        self.registers.set_xlen(self.csr.xlen());
+        self.registers.set_embedded(self.csr.embedded());
+        for (i, value) in snapshot.registers.iter().enumerate() {
+            self.registers.write(i as u8, *value);
+        }
+        for (i, value) in snapshot.float_registers.iter().enumerate() {
+            self.float.write(i as u8, *value);
+        }
+        self.program_counter = snapshot.program_counter;
+        self.max_address = snapshot.max_address;
+        self.data_offset = snapshot.data_offset;
+        self.stack_offset = snapshot.stack_offset;
+        self.stop = snapshot.stop;
+        self.exit_code = snapshot.exit_code;
+        self.retired = snapshot.retired;
+        self.memory.restore_ram(&snapshot.memory);
+        self.history.clear();
+        Ok(())
+    }
+}
+
+fn write_u32(w: &mut impl Write, value: u32) -> std::io::Result<()> {
+    w.write_all(&value.to_le_bytes())
+}
+
+fn write_u64(w: &mut impl Write, value: u64) -> std::io::Result<()> {
+    w.write_all(&value.to_le_bytes())
+}
+
+fn write_bytes(w: &mut impl Write, bytes: &[u8]) -> std::io::Result<()> {
+    write_u32(w, bytes.len() as u32)?;
+    w.write_all(bytes)
+}
+
+fn read_u32(r: &mut impl Read) -> std::io::Result<u32> {
+    let mut bytes = [0; 4];
+    r.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(r: &mut impl Read) -> std::io::Result<u64> {
+    let mut bytes = [0; 8];
+    r.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_exact(r: &mut impl Read, len: usize) -> std::io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    // `take` so a corrupt length can't make us allocate gigabytes up front
+    r.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != len {
+        return Err(std::io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(bytes)
+}
+
+fn read_bytes(r: &mut impl Read) -> std::io::Result<Vec<u8>> {
+    let len = read_u32(r)? as usize;
+    read_exact(r, len)
+}
+
+/// Little-endian throughout:
+///
+/// ```text
+/// magic "R32ISNAP", version: u32
+/// x0..x31, f0..f31, pc, max_address, data_offset, stack_offset: u64
+/// stop: u32, has exit code: u32, exit code: i32, retired: u64
+/// csr: len-prefixed bytes
+/// memory: word count: u32, words
+/// devices: count: u32, then base: u64, size: u64, len-prefixed state
+/// ```
+impl Snapshot {
+    pub fn write_to(&self, w: &mut impl Write) -> std::io::Result<()> {
+        w.write_all(MAGIC)?;
+        write_u32(w, SNAPSHOT_VERSION)?;
+        for register in self.registers {
+            write_u64(w, register)?;
+        }
+        for register in self.float_registers {
+            write_u64(w, register)?;
+        }
+        write_u64(w, self.program_counter)?;
+        write_u64(w, self.max_address)?;
+        write_u64(w, self.data_offset)?;
+        write_u64(w, self.stack_offset)?;
+        write_u32(w, self.stop as u32)?;
+        write_u32(w, self.exit_code.is_some() as u32)?;
+        write_u32(w, self.exit_code.unwrap_or(0) as u32)?;
+        write_u64(w, self.retired)?;
+        write_bytes(w, &self.csr)?;
+        write_u32(w, self.memory.len() as u32)?;
+        for word in &self.memory {
+            write_u32(w, *word)?;
+        }
+        write_u32(w, self.devices.len() as u32)?;
+        for (base, size, state) in &self.devices {
+            write_u64(w, *base)?;
+            write_u64(w, *size)?;
+            write_bytes(w, state)?;
+        }
+        Ok(())
+    }
+
+    pub fn read_from(r: &mut impl Read) -> Result<Self, SnapshotError> {
+        let mut magic = [0; MAGIC.len()];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(SnapshotError::BadMagic);
+        }
+        let version = read_u32(r)?;
+        if version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+        let mut registers = [0; RISC_V_32_REGISTERS];
+        for register in &mut registers {
+            *register = read_u64(r)?;
+        }
+        let mut float_registers = [0; RISC_V_32_REGISTERS];
+        for register in &mut float_registers {
+            *register = read_u64(r)?;
+        }
+        let program_counter = read_u64(r)?;
+        let max_address = read_u64(r)?;
+        let data_offset = read_u64(r)?;
+        let stack_offset = read_u64(r)?;
+        let stop = read_u32(r)? != 0;
+        let has_exit_code = read_u32(r)? != 0;
+        let exit_code = read_u32(r)? as i32;
+        let retired = read_u64(r)?;
+        let csr = read_bytes(r)?;
+        let words = read_u32(r)?;
+        let memory = read_exact(r, words as usize * 4)?
+            .as_chunks::<4>()
+            .0
+            .iter()
+            .map(|word| u32::from_le_bytes(*word))
+            .collect();
+        let mut devices = Vec::new();
+        for _ in 0..read_u32(r)? {
+            devices.push((read_u64(r)?, read_u64(r)?, read_bytes(r)?));
+        }
+        Ok(Self {
+            registers,
+            float_registers,
+            program_counter,
+            max_address,
+            data_offset,
+            stack_offset,
+            stop,
+            exit_code: has_exit_code.then_some(exit_code),
+            retired,
+            csr,
+            memory,
+            devices,
+        })
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let mut w = BufWriter::new(File::create(path)?);
+        self.write_to(&mut w)?;
+        w.flush()
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SnapshotError> {
+        Self::read_from(&mut BufReader::new(File::open(path)?))
+    }
+}
@@ -0,0 +1,99 @@
+use crate::arch::{Address, Byte, Privilege, Xlen};
+use crate::exception::{Exception, Interrupt, Trap};
+use crate::instruction_type::Instruction;
+use crate::memory::MemoryAccess;
+use crate::register::Register;
+use crate::register::csr::CsrWrite;
+use crate::register::float::FloatRegister;
+
+/// A write to an integer register, `old` is the value it replaced.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RegisterWrite {
+    pub register: u8,
+    pub old: Register,
+    pub new: Register,
+}
+
+/// A write to a floating-point register, the raw bits as `f{register}`
+/// holds them.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FloatWrite {
+    pub register: u8,
+    pub old: FloatRegister,
+    pub new: FloatRegister,
+}
+
+/// What became of the hart after a step.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StepStatus {
+    /// the instruction retired and the hart can go on
+    Retired,
+    /// the instruction raised an exception and the guest's trap handler took it
+    Handled(Exception),
+    /// the guest reached the stop word or exited through the syscall handler
+    Halted,
+    /// a trap the guest doesn't handle, the pc is left at the instruction
+    Trap(Trap),
+}
+
+/// Everything one call to `EmulatorContext::step` did.
+#[derive(Debug, Copy, Clone)]
+pub struct StepOutcome {
+    /// taken before fetching, so `pc` is then the first instruction of the handler
+    pub interrupt: Option<Interrupt>,
+    pub pc: Address,
+    /// the all-zero word if the fetch faulted or the hart had already halted
+    pub instruction: Instruction,
+    /// what the hart ran as, for expanding `instruction` if it's compressed
+    pub xlen: Xlen,
+    /// the privilege the instruction ran at, after any interrupt was taken
+    pub privilege: Privilege,
+    /// results the syscall handler writes aren't reported
+    pub register: Option<RegisterWrite>,
+    pub float: Option<FloatWrite>,
+    /// only CSR instructions are reported, not the CSRs traps and `fflags`
+    /// accrual change
+    pub csr: Option<CsrWrite>,
+    pub memory: Option<MemoryAccess>,
+    pub status: StepStatus,
+}
+
+/// the integer register the instruction writes, writes to x0 don't count
+pub(crate) const fn destination(instruction: &Instruction, xlen: Xlen) -> Option<u8> {
+    use crate::opcode::*;
+    let instruction = &instruction.expanded(xlen);
+    let rd = instruction.as_i().rd();
+    let writes = match instruction.opcode() as Byte {
+        I_TYPE | RI_TYPE | R_TYPE | RI_TYPE_32 | R_TYPE_32 | J_TYPE | JALR | LUI | AUIPC | AMO => {
+            true
+        }
+        SYSTEM => instruction.as_i().funct3() != 0,
+        // compares, fcvt to an integer, fmv.x.w and fclass
+        OP_FP => matches!(
+            instruction.as_r().funct7() >> 2,
+            0b10100 | 0b11000 | 0b11100
+        ),
+        _ => false,
+    };
+    if writes && rd != 0 { Some(rd) } else { None }
+}
+
+/// the floating-point register the instruction writes
+pub(crate) const fn float_destination(instruction: &Instruction, xlen: Xlen) -> Option<u8> {
+    use crate::opcode::*;
+    let instruction = &instruction.expanded(xlen);
+    let writes = match instruction.opcode() as Byte {
+        LOAD_FP | MADD | MSUB | NMSUB | NMADD => true,
+        // everything but what `destination` counts as an integer write
+        OP_FP => !matches!(
+            instruction.as_r().funct7() >> 2,
+            0b10100 | 0b11000 | 0b11100
+        ),
+        _ => false,
+    };
+    if writes {
+        Some(instruction.as_i().rd())
+    } else {
+        None
+    }
+}
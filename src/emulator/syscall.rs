@@ -0,0 +1,144 @@
+use crate::arch::{Address, RV32E_REGISTERS};
+use crate::exception::Exception;
+use crate::memory::MemoryWrapper;
+use crate::mmu::Access;
+use crate::register::alias::{a0, a1, a2, a7, t0};
+use crate::register::{Register, Registers};
+use std::io::{Read, Write};
+
+/// What the hart does after the host has handled an `ecall` or `ebreak`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SyscallResult {
+    Continue,
+    Halt(i32),
+    Trap(Exception),
+}
+
+/// Host side of `ecall` / `ebreak`. `pc` is the address of the instruction.
+pub trait SyscallHandler: Send {
+    fn ecall(
+        &mut self,
+        pc: Address,
+        registers: &mut Registers,
+        memory: &mut MemoryWrapper,
+    ) -> SyscallResult;
+
+    fn ebreak(
+        &mut self,
+        pc: Address,
+        _registers: &mut Registers,
+        _memory: &mut MemoryWrapper,
+    ) -> SyscallResult {
+        SyscallResult::Trap(Exception::Breakpoint(pc))
+    }
+}
+
+pub const SYS_READ: Register = 63;
+pub const SYS_WRITE: Register = 64;
+pub const SYS_EXIT: Register = 93;
+pub const SYS_EXIT_GROUP: Register = 94;
+
+const EBADF: i32 = 9;
+const EFAULT: i32 = 14;
+const EIO: i32 = 5;
+const ENOSYS: i32 = 38;
+
+/// The Linux/newlib calling convention: number in `a7` (`t0` on RV32E, which
+/// has no `a7`), arguments in `a0`-`a2`, result (or `-errno`) in `a0`.
+/// Supports read, write and exit.
+pub struct HostSyscalls {
+    stdin: Box<dyn Read + Send>,
+    stdout: Box<dyn Write + Send>,
+    stderr: Box<dyn Write + Send>,
+}
+
+impl HostSyscalls {
+    pub fn new(
+        stdin: impl Read + Send + 'static,
+        stdout: impl Write + Send + 'static,
+        stderr: impl Write + Send + 'static,
+    ) -> Self {
+        Self {
+            stdin: Box::new(stdin),
+            stdout: Box::new(stdout),
+            stderr: Box::new(stderr),
+        }
+    }
+
+    pub fn stdio() -> Self {
+        Self::new(std::io::stdin(), std::io::stdout(), std::io::stderr())
+    }
+
+    fn read(
+        &mut self,
+        memory: &mut MemoryWrapper,
+        fd: Register,
+        buf: Address,
+        count: Register,
+    ) -> i32 {
+        if fd != 0 {
+            return -EBADF;
+        }
+        if !memory.accessible(buf, count, Access::Store) {
+            return -EFAULT;
+        }
+        let mut data = vec![0; count as usize];
+        match self.stdin.read(&mut data) {
+            Ok(n) => match memory.write_bytes(buf, &data[..n]) {
+                Ok(()) => n as i32,
+                Err(_) => -EFAULT,
+            },
+            Err(_) => -EIO,
+        }
+    }
+
+    fn write(
+        &mut self,
+        memory: &mut MemoryWrapper,
+        fd: Register,
+        buf: Address,
+        count: Register,
+    ) -> i32 {
+        let out = match fd {
+            1 => &mut self.stdout,
+            2 => &mut self.stderr,
+            _ => return -EBADF,
+        };
+        if !memory.accessible(buf, count, Access::Load) {
+            return -EFAULT;
+        }
+        let mut data = vec![0; count as usize];
+        if memory.read_bytes(buf, &mut data).is_err() {
+            return -EFAULT;
+        }
+        match out.write_all(&data).and_then(|_| out.flush()) {
+            Ok(()) => count as i32,
+            Err(_) => -EIO,
+        }
+    }
+}
+
+impl SyscallHandler for HostSyscalls {
+    fn ecall(
+        &mut self,
+        _pc: Address,
+        registers: &mut Registers,
+        memory: &mut MemoryWrapper,
+    ) -> SyscallResult {
+        let (arg0, arg1, arg2) = (registers.get(a0), registers.get(a1), registers.get(a2));
+        let number = if registers.count() == RV32E_REGISTERS as u8 {
+            t0
+        } else {
+            a7
+        };
+        let ret = match registers.get(number) {
+            SYS_READ => self.read(memory, arg0, arg1, arg2),
+            SYS_WRITE => self.write(memory, arg0, arg1, arg2),
+            SYS_EXIT | SYS_EXIT_GROUP => return SyscallResult::Halt(arg0 as i32),
+            _ => -ENOSYS,
+        };
+        // cut to XLEN, so -1 is all ones on RV32 as well
+        registers.write(a0, ret as Register);
+        SyscallResult::Continue
+    }
+}
@@ -0,0 +1,103 @@
+use crate::emulator::step::StepOutcome;
+use crate::memory::MemoryAccess;
+use crate::register::csr;
+use std::io::Write;
+
+/// Receives every retired instruction. Instructions that trap don't retire.
+pub trait TraceSink: Send {
+    fn retire(&mut self, outcome: &StepOutcome);
+}
+
+/// Writes the `spike --log-commits` format, one line per instruction:
+///
+/// ```text
+/// core   0: 3 0x00000004 (0x00150513) x10 0x00000001
+/// core   0: 3 0x00000008 (0x00a12223) mem 0x00000404 0x00000001
+/// core   0: 3 0x0000000c (0x30029073) c768_mstatus 0x00002000
+/// core   0: 3 0x00000010 (0x00052507) f10 0xffffffff00000007
+/// ```
+///
+/// With disassembly each line is preceded by the one `spike -l` prints.
+/// Addresses, integer registers and CSRs are XLEN wide and floating-point
+/// registers 64 bits, as Spike prints them. CSRs that change as a side
+/// effect, of a trap or of `fflags` accruing, are not logged.
+pub struct SpikeCommitLog<W: Write + Send> {
+    out: W,
+    disassembly: bool,
+}
+
+impl<W: Write + Send> SpikeCommitLog<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            disassembly: false,
+        }
+    }
+
+    pub fn with_disassembly(mut self) -> Self {
+        self.disassembly = true;
+        self
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    fn write(&mut self, outcome: &StepOutcome) -> std::io::Result<()> {
+        let (pc, word) = (outcome.pc, outcome.instruction.0);
+        let privilege = outcome.privilege.bits();
+        let xlen = outcome.xlen.bytes() * 2;
+        // compressed instructions print as 4 digits
+        let width = outcome.instruction.len() as usize * 2;
+        if self.disassembly {
+            writeln!(
+                self.out,
+                "core   0: 0x{pc:0xlen$x} (0x{word:0width$x}) {}",
+                outcome.instruction.display(outcome.xlen)
+            )?;
+        }
+        write!(
+            self.out,
+            "core   0: {privilege} 0x{pc:0xlen$x} (0x{word:0width$x})"
+        )?;
+        if let Some(write) = outcome.register {
+            write!(self.out, " x{:<2} 0x{:0xlen$x}", write.register, write.new)?;
+        }
+        if let Some(write) = outcome.float {
+            write!(self.out, " f{:<2} 0x{:016x}", write.register, write.new)?;
+        }
+        if let Some(write) = outcome.csr {
+            let name = csr::name(write.csr).unwrap_or("unknown");
+            write!(self.out, " c{}_{name} 0x{:0xlen$x}", write.csr, write.new)?;
+        }
+        match outcome.memory {
+            Some(MemoryAccess::Load { address, .. }) => {
+                write!(self.out, " mem 0x{address:0xlen$x}")?
+            }
+            Some(MemoryAccess::Store {
+                address, size, new, ..
+            }) => write!(
+                self.out,
+                " mem 0x{address:0xlen$x} 0x{new:0width$x}",
+                width = size as usize * 2
+            )?,
+            // Spike logs the load and then the store
+            Some(MemoryAccess::Amo {
+                address, size, new, ..
+            }) => write!(
+                self.out,
+                " mem 0x{address:0xlen$x} mem 0x{address:0xlen$x} 0x{new:0width$x}",
+                width = size as usize * 2
+            )?,
+            None => {}
+        }
+        writeln!(self.out)
+    }
+}
+
+impl<W: Write + Send> TraceSink for SpikeCommitLog<W> {
+    fn retire(&mut self, outcome: &StepOutcome) {
+        // like the console, the guest can't observe a host I/O error
+        let _ = self.write(outcome);
+    }
+}
@@ -0,0 +1,156 @@
+use crate::arch::{Address, R32I};
+use crate::register::Register;
+use std::fmt::{Display, Formatter};
+
+/// Synchronous exceptions, numbered with the RISC-V `mcause` exception codes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Exception {
+    InstructionAddressMisaligned(Address),
+    InstructionAccessFault(Address),
+    IllegalInstruction(R32I),
+    Breakpoint(Address),
+    LoadAddressMisaligned(Address),
+    LoadAccessFault(Address),
+    StoreAddressMisaligned(Address),
+    StoreAccessFault(Address),
+    EnvironmentCallFromUMode,
+    EnvironmentCallFromSMode,
+    EnvironmentCallFromMMode,
+    InstructionPageFault(Address),
+    LoadPageFault(Address),
+    /// stores and AMOs
+    StorePageFault(Address),
+}
+
+impl Exception {
+    /// the exception code written to `mcause`
+    pub const fn code(&self) -> u32 {
+        match self {
+            Exception::InstructionAddressMisaligned(_) => 0,
+            Exception::InstructionAccessFault(_) => 1,
+            Exception::IllegalInstruction(_) => 2,
+            Exception::Breakpoint(_) => 3,
+            Exception::LoadAddressMisaligned(_) => 4,
+            Exception::LoadAccessFault(_) => 5,
+            Exception::StoreAddressMisaligned(_) => 6,
+            Exception::StoreAccessFault(_) => 7,
+            Exception::EnvironmentCallFromUMode => 8,
+            Exception::EnvironmentCallFromSMode => 9,
+            Exception::EnvironmentCallFromMMode => 11,
+            Exception::InstructionPageFault(_) => 12,
+            Exception::LoadPageFault(_) => 13,
+            Exception::StorePageFault(_) => 15,
+        }
+    }
+
+    /// the value written to `mtval` or `stval`: the faulting address or
+    /// instruction word
+    pub const fn tval(&self) -> Register {
+        match *self {
+            Exception::InstructionAddressMisaligned(address)
+            | Exception::InstructionAccessFault(address)
+            | Exception::Breakpoint(address)
+            | Exception::LoadAddressMisaligned(address)
+            | Exception::LoadAccessFault(address)
+            | Exception::StoreAddressMisaligned(address)
+            | Exception::StoreAccessFault(address)
+            | Exception::InstructionPageFault(address)
+            | Exception::LoadPageFault(address)
+            | Exception::StorePageFault(address) => address,
+            Exception::IllegalInstruction(instruction) => instruction as Register,
+            Exception::EnvironmentCallFromUMode
+            | Exception::EnvironmentCallFromSMode
+            | Exception::EnvironmentCallFromMMode => 0,
+        }
+    }
+}
+
+impl Display for Exception {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Exception::InstructionAddressMisaligned(a) => {
+                write!(f, "instruction address misaligned at {:#010x}", a)
+            }
+            Exception::InstructionAccessFault(a) => {
+                write!(f, "instruction access fault at {:#010x}", a)
+            }
+            Exception::IllegalInstruction(i) => write!(f, "illegal instruction {:#010x}", i),
+            Exception::Breakpoint(a) => write!(f, "breakpoint at {:#010x}", a),
+            Exception::LoadAddressMisaligned(a) => {
+                write!(f, "load address misaligned at {:#010x}", a)
+            }
+            Exception::LoadAccessFault(a) => write!(f, "load access fault at {:#010x}", a),
+            Exception::StoreAddressMisaligned(a) => {
+                write!(f, "store address misaligned at {:#010x}", a)
+            }
+            Exception::StoreAccessFault(a) => write!(f, "store access fault at {:#010x}", a),
+            Exception::EnvironmentCallFromUMode => f.write_str("environment call from U-mode"),
+            Exception::EnvironmentCallFromSMode => f.write_str("environment call from S-mode"),
+            Exception::EnvironmentCallFromMMode => f.write_str("environment call from M-mode"),
+            Exception::InstructionPageFault(a) => {
+                write!(f, "instruction page fault at {:#010x}", a)
+            }
+            Exception::LoadPageFault(a) => write!(f, "load page fault at {:#010x}", a),
+            Exception::StorePageFault(a) => write!(f, "store page fault at {:#010x}", a),
+        }
+    }
+}
+
+/// Asynchronous interrupts, numbered with the `mcause` interrupt codes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Interrupt {
+    SupervisorSoftware,
+    MachineSoftware,
+    SupervisorTimer,
+    MachineTimer,
+    SupervisorExternal,
+    MachineExternal,
+}
+
+impl Interrupt {
+    /// highest priority first
+    pub const PRIORITY: [Interrupt; 6] = [
+        Interrupt::MachineExternal,
+        Interrupt::MachineSoftware,
+        Interrupt::MachineTimer,
+        Interrupt::SupervisorExternal,
+        Interrupt::SupervisorSoftware,
+        Interrupt::SupervisorTimer,
+    ];
+
+    pub const fn code(&self) -> u32 {
+        match self {
+            Interrupt::SupervisorSoftware => 1,
+            Interrupt::MachineSoftware => 3,
+            Interrupt::SupervisorTimer => 5,
+            Interrupt::MachineTimer => 7,
+            Interrupt::SupervisorExternal => 9,
+            Interrupt::MachineExternal => 11,
+        }
+    }
+
+    /// the bit of this interrupt in `mip` / `mie`
+    pub const fn mask(&self) -> Register {
+        1 << self.code()
+    }
+}
+
+/// An exception together with the instruction that raised it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Trap {
+    pub pc: Address,
+    pub instruction: R32I,
+    pub exception: Exception,
+}
+
+impl Display for Trap {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} (pc: {:#010x}, instruction: {:#010x})",
+            self.exception, self.pc, self.instruction
+        )
+    }
+}
+
+impl std::error::Error for Trap {}
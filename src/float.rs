@@ -0,0 +1,575 @@
+use std::cmp::Ordering;
+
+/// An IEEE 754 binary format. Values are its bits, in the low end of a `u64`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Format {
+    exponent: u32,
+    fraction: u32,
+}
+
+pub const SINGLE: Format = Format {
+    exponent: 8,
+    fraction: 23,
+};
+pub const DOUBLE: Format = Format {
+    exponent: 11,
+    fraction: 52,
+};
+
+// the fflags bits
+pub const INVALID: u8 = 1 << 4;
+pub const DIVIDE_BY_ZERO: u8 = 1 << 3;
+pub const OVERFLOW: u8 = 1 << 2;
+pub const UNDERFLOW: u8 = 1 << 1;
+pub const INEXACT: u8 = 1;
+
+/// The `rm` field and `frm`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RoundingMode {
+    NearestEven,
+    TowardZero,
+    Down,
+    Up,
+    NearestMaxMagnitude,
+}
+
+/// `rm` = 7 selects `frm`
+pub const DYNAMIC: u32 = 0b111;
+
+impl RoundingMode {
+    /// `None` for the reserved encodings and `DYNAMIC`
+    pub const fn decode(rm: u32) -> Option<Self> {
+        Some(match rm {
+            0b000 => Self::NearestEven,
+            0b001 => Self::TowardZero,
+            0b010 => Self::Down,
+            0b011 => Self::Up,
+            0b100 => Self::NearestMaxMagnitude,
+            _ => return None,
+        })
+    }
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::NearestEven => "rne",
+            Self::TowardZero => "rtz",
+            Self::Down => "rdn",
+            Self::Up => "rup",
+            Self::NearestMaxMagnitude => "rmm",
+        }
+    }
+
+    /// whether a value of `sign` whose last kept bit is `odd` and whose
+    /// dropped bits are `round` (the first one) and `sticky` (any of the
+    /// rest) rounds away from zero
+    const fn increments(self, sign: bool, odd: bool, round: bool, sticky: bool) -> bool {
+        match self {
+            Self::NearestEven => round && (sticky || odd),
+            Self::TowardZero => false,
+            Self::Down => sign && (round || sticky),
+            Self::Up => !sign && (round || sticky),
+            Self::NearestMaxMagnitude => round,
+        }
+    }
+}
+
+impl Format {
+    pub const fn is_single(self) -> bool {
+        self.fraction == SINGLE.fraction
+    }
+
+    const fn width(self) -> u32 {
+        1 + self.exponent + self.fraction
+    }
+
+    const fn bias(self) -> i32 {
+        (1 << (self.exponent - 1)) - 1
+    }
+
+    // the exponent field of infinities and NaNs
+    const fn max_exponent(self) -> u64 {
+        (1 << self.exponent) - 1
+    }
+
+    const fn sign_bit(self) -> u64 {
+        1 << (self.width() - 1)
+    }
+
+    const fn exponent_field(self, x: u64) -> u64 {
+        x >> self.fraction & self.max_exponent()
+    }
+
+    const fn fraction_field(self, x: u64) -> u64 {
+        x & ((1 << self.fraction) - 1)
+    }
+
+    pub const fn sign(self, x: u64) -> bool {
+        x & self.sign_bit() != 0
+    }
+
+    pub const fn negate(self, x: u64) -> u64 {
+        x ^ self.sign_bit()
+    }
+
+    /// `x` with the sign of `sign`
+    pub const fn with_sign(self, x: u64, sign: bool) -> u64 {
+        (x & !self.sign_bit()) | if sign { self.sign_bit() } else { 0 }
+    }
+
+    pub const fn is_nan(self, x: u64) -> bool {
+        self.exponent_field(x) == self.max_exponent() && self.fraction_field(x) != 0
+    }
+
+    pub const fn is_signaling(self, x: u64) -> bool {
+        self.is_nan(x) && x >> (self.fraction - 1) & 1 == 0
+    }
+
+    pub const fn is_infinite(self, x: u64) -> bool {
+        self.exponent_field(x) == self.max_exponent() && self.fraction_field(x) == 0
+    }
+
+    pub const fn is_zero(self, x: u64) -> bool {
+        x & !self.sign_bit() == 0
+    }
+
+    /// the quiet NaN every operation returns in place of a NaN
+    pub const fn canonical_nan(self) -> u64 {
+        self.max_exponent() << self.fraction | 1 << (self.fraction - 1)
+    }
+
+    const fn zero(self, sign: bool) -> u64 {
+        self.with_sign(0, sign)
+    }
+
+    const fn infinity(self, sign: bool) -> u64 {
+        self.with_sign(self.max_exponent() << self.fraction, sign)
+    }
+
+    const fn max_finite(self, sign: bool) -> u64 {
+        self.with_sign(self.infinity(false) - 1, sign)
+    }
+
+    /// a finite `x` as `(sign, exponent, significand)`, the value being
+    /// `significand * 2^exponent`
+    const fn unpack(self, x: u64) -> (bool, i32, u64) {
+        let exponent = self.exponent_field(x) as i32;
+        let fraction = self.fraction_field(x);
+        let sign = self.sign(x);
+        if exponent == 0 {
+            (sign, 1 - self.bias() - self.fraction as i32, fraction)
+        } else {
+            let exponent = exponent - self.bias() - self.fraction as i32;
+            (sign, exponent, fraction | 1 << self.fraction)
+        }
+    }
+
+    /// `fclass`: one of ten bits, from negative infinity up to quiet NaN
+    pub const fn classify(self, x: u64) -> u32 {
+        let sign = self.sign(x);
+        let bit = if self.is_nan(x) {
+            if self.is_signaling(x) { 8 } else { 9 }
+        } else if self.is_infinite(x) {
+            if sign { 0 } else { 7 }
+        } else if self.exponent_field(x) == 0 {
+            match (self.fraction_field(x) == 0, sign) {
+                (true, true) => 3,
+                (true, false) => 4,
+                (false, true) => 2,
+                (false, false) => 5,
+            }
+        } else if sign {
+            1
+        } else {
+            6
+        };
+        1 << bit
+    }
+
+    // orders non-NaN values, -0 just below +0
+    const fn key(self, x: u64) -> i128 {
+        let magnitude = (x & !self.sign_bit()) as i128;
+        if self.sign(x) {
+            -1 - magnitude
+        } else {
+            magnitude
+        }
+    }
+}
+
+// `m` shifted right by `shift`, bit 0 set if anything nonzero was shifted
+// out. A negative `shift` shifts left.
+const fn shift_right_jam(m: u128, shift: i32) -> u128 {
+    if shift <= 0 {
+        m << -shift
+    } else if shift >= 128 {
+        (m != 0) as u128
+    } else {
+        m >> shift | (m & ((1 << shift) - 1) != 0) as u128
+    }
+}
+
+/// The rounding mode operations round with and the flags they raised.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FloatEnv {
+    rounding: RoundingMode,
+    pub flags: u8,
+}
+
+impl FloatEnv {
+    pub const fn new(rounding: RoundingMode) -> Self {
+        Self { rounding, flags: 0 }
+    }
+
+    fn invalid(&mut self, f: Format) -> u64 {
+        self.flags |= INVALID;
+        f.canonical_nan()
+    }
+
+    // the result of an operation with a NaN operand
+    fn propagate(&mut self, f: Format, operands: &[u64]) -> u64 {
+        if operands.iter().any(|x| f.is_signaling(*x)) {
+            self.flags |= INVALID;
+        }
+        f.canonical_nan()
+    }
+
+    fn overflow(&mut self, f: Format, sign: bool) -> u64 {
+        self.flags |= OVERFLOW | INEXACT;
+        let infinite = match self.rounding {
+            RoundingMode::NearestEven | RoundingMode::NearestMaxMagnitude => true,
+            RoundingMode::TowardZero => false,
+            RoundingMode::Down => sign,
+            RoundingMode::Up => !sign,
+        };
+        if infinite {
+            f.infinity(sign)
+        } else {
+            f.max_finite(sign)
+        }
+    }
+
+    /// Rounds `sig * 2^exp` of `sign` to `f`. Bit 0 of `sig` may stand in
+    /// for anything nonzero below it, if `sig` is at least two bits wider
+    /// than `f`'s significand. Tininess is detected after rounding.
+    fn round(&mut self, f: Format, sign: bool, exp: i32, sig: u128) -> u64 {
+        if sig == 0 {
+            return f.zero(sign);
+        }
+        // the leading bit to bit 126
+        let shift = sig.leading_zeros() as i32 - 1;
+        let (sig, exp) = (shift_right_jam(sig, -shift), exp - shift);
+        // the biased exponent if the result is normal
+        let biased = exp + 126 + f.bias();
+        // the dropped bits of a normal result, subnormal ones drop more and
+        // keep no implicit bit
+        let normal_shift = 126 - f.fraction as i32;
+        let (field, shift) = if biased >= 1 {
+            (biased - 1, normal_shift)
+        } else {
+            (0, normal_shift + 1 - biased)
+        };
+        if field as u64 >= f.max_exponent() {
+            return self.overflow(f, sign);
+        }
+        let split = |shift: i32| -> (u128, bool, bool) {
+            if shift >= 128 {
+                return (0, false, true);
+            }
+            let round = sig >> (shift - 1) & 1 != 0;
+            (sig >> shift, round, sig & ((1 << (shift - 1)) - 1) != 0)
+        };
+        let (kept, round, sticky) = split(shift);
+        let up = self.rounding.increments(sign, kept & 1 != 0, round, sticky);
+        let bits = ((field as u64) << f.fraction) + kept as u64 + up as u64;
+        if bits >> f.fraction >= f.max_exponent() {
+            return self.overflow(f, sign);
+        }
+        if round || sticky {
+            self.flags |= INEXACT;
+            // still below the smallest normal once rounded to full precision
+            let tiny = biased < 0
+                || biased == 0 && {
+                    let (kept, round, sticky) = split(normal_shift);
+                    let all_ones = (1 << (f.fraction + 1)) - 1;
+                    kept != all_ones || !self.rounding.increments(sign, true, round, sticky)
+                };
+            if tiny {
+                self.flags |= UNDERFLOW;
+            }
+        }
+        f.with_sign(bits, sign)
+    }
+
+    /// `a + b` for finite `(sign, exponent, significand)` triples, the
+    /// significands below 2^126
+    fn sum(&mut self, f: Format, a: (bool, i32, u128), b: (bool, i32, u128)) -> u64 {
+        let exact_zero = f.zero(self.rounding == RoundingMode::Down);
+        match (a, b) {
+            // +0 unless both are -0, or rounding down
+            ((sa, _, 0), (sb, _, 0)) => return if sa == sb { f.zero(sa) } else { exact_zero },
+            ((_, _, 0), (sign, exp, sig)) | ((sign, exp, sig), (_, _, 0)) => {
+                return self.round(f, sign, exp, sig);
+            }
+            _ => {}
+        }
+        // `a` is the one with the higher leading bit
+        let top = |(_, exp, sig): (bool, i32, u128)| exp - sig.leading_zeros() as i32;
+        let ((sa, ea, ma), (sb, eb, mb)) = if top(a) >= top(b) { (a, b) } else { (b, a) };
+        // the leading bit of `a` to bit 125, leaving room for a carry
+        let shift = ma.leading_zeros() as i32 - 2;
+        let (ma, ea) = (ma << shift, ea - shift);
+        let mb = shift_right_jam(mb, ea - eb);
+        let (sign, sig) = if sa == sb {
+            (sa, ma + mb)
+        } else if ma >= mb {
+            (sa, ma - mb)
+        } else {
+            (sb, mb - ma)
+        };
+        if sig == 0 {
+            return exact_zero;
+        }
+        self.round(f, sign, ea, sig)
+    }
+
+    fn finite(f: Format, x: u64) -> (bool, i32, u128) {
+        let (sign, exp, sig) = f.unpack(x);
+        (sign, exp, sig as u128)
+    }
+
+    pub fn add(&mut self, f: Format, a: u64, b: u64) -> u64 {
+        if f.is_nan(a) || f.is_nan(b) {
+            return self.propagate(f, &[a, b]);
+        }
+        match (f.is_infinite(a), f.is_infinite(b)) {
+            (true, true) if f.sign(a) != f.sign(b) => self.invalid(f),
+            (true, _) => a,
+            (_, true) => b,
+            _ => self.sum(f, Self::finite(f, a), Self::finite(f, b)),
+        }
+    }
+
+    pub fn sub(&mut self, f: Format, a: u64, b: u64) -> u64 {
+        self.add(f, a, f.negate(b))
+    }
+
+    pub fn mul(&mut self, f: Format, a: u64, b: u64) -> u64 {
+        if f.is_nan(a) || f.is_nan(b) {
+            return self.propagate(f, &[a, b]);
+        }
+        let sign = f.sign(a) != f.sign(b);
+        let (infinite, zero) = (
+            f.is_infinite(a) || f.is_infinite(b),
+            f.is_zero(a) || f.is_zero(b),
+        );
+        match (infinite, zero) {
+            (true, true) => self.invalid(f),
+            (true, false) => f.infinity(sign),
+            _ => {
+                let ((_, ea, ma), (_, eb, mb)) = (f.unpack(a), f.unpack(b));
+                self.round(f, sign, ea + eb, ma as u128 * mb as u128)
+            }
+        }
+    }
+
+    /// `a * b + c` rounded once
+    pub fn mul_add(&mut self, f: Format, a: u64, b: u64, c: u64) -> u64 {
+        let sign = f.sign(a) != f.sign(b);
+        let infinite = f.is_infinite(a) || f.is_infinite(b);
+        let zero = f.is_zero(a) || f.is_zero(b);
+        if f.is_nan(a) || f.is_nan(b) || f.is_nan(c) {
+            // infinity times zero is invalid even with a quiet NaN addend
+            if infinite && zero {
+                self.flags |= INVALID;
+            }
+            return self.propagate(f, &[a, b, c]);
+        }
+        if infinite && zero {
+            return self.invalid(f);
+        }
+        if infinite {
+            if f.is_infinite(c) && f.sign(c) != sign {
+                return self.invalid(f);
+            }
+            return f.infinity(sign);
+        }
+        if f.is_infinite(c) {
+            return c;
+        }
+        let ((_, ea, ma), (_, eb, mb)) = (f.unpack(a), f.unpack(b));
+        let product = (sign, ea + eb, ma as u128 * mb as u128);
+        self.sum(f, product, Self::finite(f, c))
+    }
+
+    pub fn div(&mut self, f: Format, a: u64, b: u64) -> u64 {
+        if f.is_nan(a) || f.is_nan(b) {
+            return self.propagate(f, &[a, b]);
+        }
+        let sign = f.sign(a) != f.sign(b);
+        match (f.is_infinite(a), f.is_infinite(b)) {
+            (true, true) => return self.invalid(f),
+            (true, false) => return f.infinity(sign),
+            (false, true) => return f.zero(sign),
+            _ => {}
+        }
+        match (f.is_zero(a), f.is_zero(b)) {
+            (true, true) => return self.invalid(f),
+            (false, true) => {
+                self.flags |= DIVIDE_BY_ZERO;
+                return f.infinity(sign);
+            }
+            (true, false) => return f.zero(sign),
+            _ => {}
+        }
+        let ((_, ea, ma), (_, eb, mb)) = (f.unpack(a), f.unpack(b));
+        // the dividend to bit 125, so the quotient keeps over 70 bits
+        let shift = (ma as u128).leading_zeros() as i32 - 2;
+        let dividend = (ma as u128) << shift;
+        let (quotient, remainder) = (dividend / mb as u128, dividend % mb as u128);
+        let sig = quotient | (remainder != 0) as u128;
+        self.round(f, sign, ea - shift - eb, sig)
+    }
+
+    pub fn sqrt(&mut self, f: Format, a: u64) -> u64 {
+        if f.is_nan(a) {
+            return self.propagate(f, &[a]);
+        }
+        if f.is_zero(a) {
+            return a;
+        }
+        if f.sign(a) {
+            return self.invalid(f);
+        }
+        if f.is_infinite(a) {
+            return a;
+        }
+        let (_, exp, sig) = f.unpack(a);
+        // a wide radicand with an even exponent
+        let mut shift = (sig as u128).leading_zeros() as i32 - 2;
+        if (exp - shift) & 1 != 0 {
+            shift -= 1;
+        }
+        let radicand = (sig as u128) << shift;
+        let root = radicand.isqrt();
+        let sig = root | (root * root != radicand) as u128;
+        self.round(f, false, (exp - shift) / 2, sig)
+    }
+
+    /// `fcvt` from a format to the other
+    pub fn convert(&mut self, from: Format, to: Format, a: u64) -> u64 {
+        if from.is_nan(a) {
+            if from.is_signaling(a) {
+                self.flags |= INVALID;
+            }
+            return to.canonical_nan();
+        }
+        if from.is_infinite(a) {
+            return to.infinity(from.sign(a));
+        }
+        let (sign, exp, sig) = Self::finite(from, a);
+        self.round(to, sign, exp, sig)
+    }
+
+    /// `fcvt` from an integer
+    pub fn int_to_float(&mut self, f: Format, value: i128) -> u64 {
+        self.round(f, value < 0, 0, value.unsigned_abs())
+    }
+
+    /// `fcvt` to an integer in `min..=max`. NaN and values out of range are
+    /// invalid and saturate, NaN to `max`.
+    pub fn float_to_int(&mut self, f: Format, a: u64, min: i128, max: i128) -> i128 {
+        let sign = f.sign(a);
+        let saturated = if sign && !f.is_nan(a) { min } else { max };
+        if f.is_nan(a) || f.is_infinite(a) {
+            self.flags |= INVALID;
+            return saturated;
+        }
+        let (_, exp, sig) = Self::finite(f, a);
+        let (magnitude, inexact) = if exp >= 0 {
+            if exp > 64 {
+                self.flags |= INVALID;
+                return saturated;
+            }
+            (sig << exp, false)
+        } else {
+            let shift = -exp;
+            let (kept, round, sticky) = if shift > 64 {
+                (0, false, sig != 0)
+            } else {
+                let round = sig >> (shift - 1) & 1 != 0;
+                (sig >> shift, round, sig & ((1 << (shift - 1)) - 1) != 0)
+            };
+            let up = self.rounding.increments(sign, kept & 1 != 0, round, sticky);
+            (kept + up as u128, round || sticky)
+        };
+        let value = if sign {
+            -(magnitude as i128)
+        } else {
+            magnitude as i128
+        };
+        if value < min || value > max {
+            self.flags |= INVALID;
+            return saturated;
+        }
+        if inexact {
+            self.flags |= INEXACT;
+        }
+        value
+    }
+
+    /// `feq`, only signaling NaNs are invalid
+    pub fn equal(&mut self, f: Format, a: u64, b: u64) -> bool {
+        if f.is_nan(a) || f.is_nan(b) {
+            self.propagate(f, &[a, b]);
+            return false;
+        }
+        a == b || f.is_zero(a) && f.is_zero(b)
+    }
+
+    /// `flt`, any NaN is invalid
+    pub fn less(&mut self, f: Format, a: u64, b: u64) -> bool {
+        self.compare(f, a, b) == Some(Ordering::Less)
+    }
+
+    /// `fle`, any NaN is invalid
+    pub fn less_equal(&mut self, f: Format, a: u64, b: u64) -> bool {
+        matches!(
+            self.compare(f, a, b),
+            Some(Ordering::Less | Ordering::Equal)
+        )
+    }
+
+    fn compare(&mut self, f: Format, a: u64, b: u64) -> Option<Ordering> {
+        if f.is_nan(a) || f.is_nan(b) {
+            self.flags |= INVALID;
+            return None;
+        }
+        if f.is_zero(a) && f.is_zero(b) {
+            return Some(Ordering::Equal);
+        }
+        Some(f.key(a).cmp(&f.key(b)))
+    }
+
+    /// `fmin`, NaN only if both are, -0 below +0
+    pub fn min(&mut self, f: Format, a: u64, b: u64) -> u64 {
+        self.min_max(f, a, b, Ordering::Less)
+    }
+
+    /// `fmax`, NaN only if both are, +0 above -0
+    pub fn max(&mut self, f: Format, a: u64, b: u64) -> u64 {
+        self.min_max(f, a, b, Ordering::Greater)
+    }
+
+    fn min_max(&mut self, f: Format, a: u64, b: u64, pick: Ordering) -> u64 {
+        if f.is_signaling(a) || f.is_signaling(b) {
+            self.flags |= INVALID;
+        }
+        match (f.is_nan(a), f.is_nan(b)) {
+            (true, true) => f.canonical_nan(),
+            (true, false) => b,
+            (false, true) => a,
+            _ if f.key(a).cmp(&f.key(b)) == pick => a,
+            _ => b,
+        }
+    }
+}
@@ -12,6 +12,19 @@ enum Opcode {
     JType = 0x6F,
     LUI = 0x37,
     AUIPC = 0x17,
+    System = 0x73,
+    AMO = 0x2F,
+    LoadFp = 0x07,
+    StoreFp = 0x27,
+    MAdd = 0x43,
+    MSub = 0x47,
+    NMSub = 0x4B,
+    NMAdd = 0x4F,
+    OpFp = 0x53,
+    MiscMem = 0x0F,
+    // RV64 的 *w 指令
+    ITypeWord = 0x1B,
+    RTypeWord = 0x3B,
 }
 
 pub mod rtype {
@@ -34,7 +47,30 @@ pub mod rtype {
     #[repr(u32)]
     pub enum Funct7 {
         BASE = 0,
+        MULDIV = 1, // RV32M
         ALT = 0x20, // 用于区分 SUB 等指令
+        // Zba/Zbb/Zbs
+        SHADD = 0x10,
+        MINMAX = 0x05,
+        ROTATE = 0x30,
+        ZEXT = 0x04,
+        BSET = 0x14,
+        BCLR = 0x24, // 也用于 BEXT
+        BINV = 0x34,
+    }
+
+    /// RV32M 指令的 funct3
+    #[derive(Debug, Clone, Copy)]
+    #[repr(u32)]
+    pub enum MFunct3 {
+        MUL = 0,
+        MULH = 1,
+        MULHSU = 2,
+        MULHU = 3,
+        DIV = 4,
+        DIVU = 5,
+        REM = 6,
+        REMU = 7,
     }
 
     macro_rules! rtype_instructions {
@@ -45,15 +81,49 @@ pub mod rtype {
         }
     }
 
+    macro_rules! mtype_instructions {
+        ($($name:ident: $funct3:expr),*) => {
+            $(pub const fn $name(rd: u8, rs1: u8, rs2: u8)-> u32 {
+                encode_raw(Funct7::MULDIV, rs2, rs1, $funct3 as u32, rd)
+            })*
+        }
+    }
+
+    // Zba/Zbb/Zbs, funct3 直接写数字
+    macro_rules! zb_instructions {
+        ($($name:ident: ($funct7:expr, $funct3:expr)),*) => {
+            $(pub const fn $name(rd: u8, rs1: u8, rs2: u8)-> u32 {
+                encode_raw($funct7, rs2, rs1, $funct3, rd)
+            })*
+        }
+    }
+
     /// 通用 R-type 指令编码器
     pub const fn encode(funct7: Funct7, rs2: u8, rs1: u8, funct3: RFunct3, rd: u8) -> u32 {
+        encode_raw(funct7, rs2, rs1, funct3 as u32, rd)
+    }
+
+    const fn encode_raw(funct7: Funct7, rs2: u8, rs1: u8, funct3: u32, rd: u8) -> u32 {
         (funct7 as u32) << 25
             | (rs2 as u32 & 0x1F) << 20
             | (rs1 as u32 & 0x1F) << 15
-            | (funct3 as u32) << 12
+            | funct3 << 12
             | (rd as u32 & 0x1F) << 7
             | Opcode::RType as u32
     }
+
+    // RV64 的 *w 指令换成 OP-32 的 opcode, 其余字段一样
+    const fn encode_word(funct7: Funct7, rs2: u8, rs1: u8, funct3: u32, rd: u8) -> u32 {
+        encode_raw(funct7, rs2, rs1, funct3, rd) & !0x7F | Opcode::RTypeWord as u32
+    }
+
+    macro_rules! word_instructions {
+        ($($name:ident: ($funct7:expr, $funct3:expr)),*) => {
+            $(pub const fn $name(rd: u8, rs1: u8, rs2: u8)-> u32 {
+                encode_word($funct7, rs2, rs1, $funct3 as u32, rd)
+            })*
+        }
+    }
     rtype_instructions! {
         add: (Funct7::BASE, RFunct3::AddSub),
         sub:  (Funct7::ALT,   RFunct3::AddSub),
@@ -66,6 +136,66 @@ pub mod rtype {
         or:   (Funct7::BASE,  RFunct3::OR),
         and:  (Funct7::BASE,  RFunct3::AND)
     }
+
+    mtype_instructions! {
+        mul:    MFunct3::MUL,
+        mulh:   MFunct3::MULH,
+        mulhsu: MFunct3::MULHSU,
+        mulhu:  MFunct3::MULHU,
+        div:    MFunct3::DIV,
+        divu:   MFunct3::DIVU,
+        rem:    MFunct3::REM,
+        remu:   MFunct3::REMU
+    }
+
+    zb_instructions! {
+        sh1add: (Funct7::SHADD,  0b010),
+        sh2add: (Funct7::SHADD,  0b100),
+        sh3add: (Funct7::SHADD,  0b110),
+        andn:   (Funct7::ALT,    0b111),
+        orn:    (Funct7::ALT,    0b110),
+        xnor:   (Funct7::ALT,    0b100),
+        min:    (Funct7::MINMAX, 0b100),
+        minu:   (Funct7::MINMAX, 0b101),
+        max:    (Funct7::MINMAX, 0b110),
+        maxu:   (Funct7::MINMAX, 0b111),
+        rol:    (Funct7::ROTATE, 0b001),
+        ror:    (Funct7::ROTATE, 0b101),
+        bset:   (Funct7::BSET,   0b001),
+        bclr:   (Funct7::BCLR,   0b001),
+        binv:   (Funct7::BINV,   0b001),
+        bext:   (Funct7::BCLR,   0b101)
+    }
+
+    /// RV32 上 zext.h 是 rs2 = 0 的 pack, RV64 上换到 OP-32
+    pub const fn zext_h(rd: u8, rs1: u8) -> u32 {
+        encode_raw(Funct7::ZEXT, 0, rs1, 0b100, rd)
+    }
+
+    /// RV64 的 zext.h
+    pub const fn zext_h_64(rd: u8, rs1: u8) -> u32 {
+        encode_word(Funct7::ZEXT, 0, rs1, 0b100, rd)
+    }
+
+    // 以下只在 RV64 上有
+    word_instructions! {
+        addw:      (Funct7::BASE,   RFunct3::AddSub),
+        subw:      (Funct7::ALT,    RFunct3::AddSub),
+        sllw:      (Funct7::BASE,   RFunct3::SLL),
+        srlw:      (Funct7::BASE,   RFunct3::SrlSra),
+        sraw:      (Funct7::ALT,    RFunct3::SrlSra),
+        mulw:      (Funct7::MULDIV, MFunct3::MUL),
+        divw:      (Funct7::MULDIV, MFunct3::DIV),
+        divuw:     (Funct7::MULDIV, MFunct3::DIVU),
+        remw:      (Funct7::MULDIV, MFunct3::REM),
+        remuw:     (Funct7::MULDIV, MFunct3::REMU),
+        rolw:      (Funct7::ROTATE, 0b001),
+        rorw:      (Funct7::ROTATE, 0b101),
+        add_uw:    (Funct7::ZEXT,   0b000),
+        sh1add_uw: (Funct7::SHADD,  0b010),
+        sh2add_uw: (Funct7::SHADD,  0b100),
+        sh3add_uw: (Funct7::SHADD,  0b110)
+    }
 }
 
 pub mod itype {
@@ -78,6 +208,9 @@ pub mod itype {
         LW = 2,
         LBU = 4,
         LHU = 5,
+        // RV64
+        LD = 3,
+        LWU = 6,
     }
 
     #[derive(Debug, Clone, Copy)]
@@ -136,7 +269,9 @@ pub mod itype {
         lbu: ILFunct3::LBU,
         lw: ILFunct3::LW,
         lh: ILFunct3::LH,
-        lhu: ILFunct3::LHU
+        lhu: ILFunct3::LHU,
+        ld: ILFunct3::LD,
+        lwu: ILFunct3::LWU
     }
 
     const fn encode_s(rd: u8, rs1: u8, funct3: u8, imm: u16) -> u32 {
@@ -147,14 +282,89 @@ pub mod itype {
             | Opcode::IType as u32
     }
 
+    // RV64 的 shamt 有 6 位, RV32 上第 6 位为 1 是非法指令
     pub const fn slli(rd: u8, rs1: u8, imm: u8) -> u32 {
-        encode_s(rd, rs1, 1, (imm & 0b11111) as u16)
+        encode_s(rd, rs1, 1, (imm & 0b111111) as u16)
     }
     pub const fn srli(rd: u8, rs1: u8, imm: u8) -> u32 {
-        encode_s(rd, rs1, 5, (imm & 0b11111) as u16)
+        encode_s(rd, rs1, 5, (imm & 0b111111) as u16)
     }
     pub const fn srai(rd: u8, rs1: u8, imm: u8) -> u32 {
-        encode_s(rd, rs1, 5, (imm & 0b11111) as u16 | 0x400)
+        encode_s(rd, rs1, 5, (imm & 0b111111) as u16 | 0x400)
+    }
+
+    // Zbb/Zbs 的移位类立即数指令, 高 7 位区分操作
+    macro_rules! shamt_instructions {
+        ($($name:ident: ($funct3:expr, $high:expr)),*) => {
+            $(pub const fn $name(rd: u8, rs1: u8, imm: u8) -> u32 {
+                encode_s(rd, rs1, $funct3, (imm & 0b111111) as u16 | $high << 5)
+            })*
+        };
+    }
+
+    shamt_instructions! {
+        rori: (5, 0x30),
+        bseti: (1, 0x14),
+        bclri: (1, 0x24),
+        binvi: (1, 0x34),
+        bexti: (5, 0x24)
+    }
+
+    // 单操作数的 Zbb 指令, 立即数固定
+    macro_rules! unary_instructions {
+        ($($name:ident: ($funct3:expr, $imm:expr)),*) => {
+            $(pub const fn $name(rd: u8, rs1: u8) -> u32 {
+                encode_s(rd, rs1, $funct3, $imm)
+            })*
+        };
+    }
+
+    unary_instructions! {
+        clz: (1, 0x600),
+        ctz: (1, 0x601),
+        cpop: (1, 0x602),
+        sext_b: (1, 0x604),
+        sext_h: (1, 0x605),
+        orc_b: (5, 0x287),
+        rev8: (5, 0x698)
+    }
+
+    /// RV64 的 rev8, 立即数和 RV32 不同
+    pub const fn rev8_64(rd: u8, rs1: u8) -> u32 {
+        encode_s(rd, rs1, 5, 0x6B8)
+    }
+
+    // RV64 的 OP-IMM-32
+    const fn encode_w(rd: u8, rs1: u8, funct3: u8, imm: u16) -> u32 {
+        encode_s(rd, rs1, funct3, imm) & !0x7F | Opcode::ITypeWord as u32
+    }
+
+    pub const fn addiw(rd: u8, rs1: u8, imm: i16) -> u32 {
+        encode_w(rd, rs1, 0, imm as u16)
+    }
+    pub const fn slliw(rd: u8, rs1: u8, imm: u8) -> u32 {
+        encode_w(rd, rs1, 1, (imm & 0b11111) as u16)
+    }
+    pub const fn srliw(rd: u8, rs1: u8, imm: u8) -> u32 {
+        encode_w(rd, rs1, 5, (imm & 0b11111) as u16)
+    }
+    pub const fn sraiw(rd: u8, rs1: u8, imm: u8) -> u32 {
+        encode_w(rd, rs1, 5, (imm & 0b11111) as u16 | 0x400)
+    }
+    pub const fn roriw(rd: u8, rs1: u8, imm: u8) -> u32 {
+        encode_w(rd, rs1, 5, (imm & 0b11111) as u16 | 0x600)
+    }
+    pub const fn slli_uw(rd: u8, rs1: u8, imm: u8) -> u32 {
+        encode_w(rd, rs1, 1, (imm & 0b111111) as u16 | 0x080)
+    }
+    pub const fn clzw(rd: u8, rs1: u8) -> u32 {
+        encode_w(rd, rs1, 1, 0x600)
+    }
+    pub const fn ctzw(rd: u8, rs1: u8) -> u32 {
+        encode_w(rd, rs1, 1, 0x601)
+    }
+    pub const fn cpopw(rd: u8, rs1: u8) -> u32 {
+        encode_w(rd, rs1, 1, 0x602)
     }
 
     // I型指令辅助宏
@@ -177,6 +387,7 @@ pub mod stype {
         SB = 0,
         SH = 1,
         SW = 2,
+        SD = 3,
     }
     const fn encode(rs1: u8, rs2: u8, funct3: Funct3, imm: i16) -> u32 {
         let imm11_5 = (imm >> 5) as u32 & 0x7F;
@@ -201,6 +412,11 @@ pub mod stype {
     pub const fn sw(rs2: u8, rs1: u8, imm: i16) -> u32 {
         encode(rs1, rs2, Funct3::SW, imm)
     }
+
+    /// 只在 RV64 上有
+    pub const fn sd(rs2: u8, rs1: u8, imm: i16) -> u32 {
+        encode(rs1, rs2, Funct3::SD, imm)
+    }
 }
 
 pub mod btype {
@@ -296,11 +512,704 @@ pub mod utype {
     }
 }
 
+pub mod csrtype {
+    use crate::instruct_info::Opcode;
+    use crate::register::csr::CsrAddress;
+
+    #[derive(Debug, Clone, Copy)]
+    #[repr(u32)]
+    enum Funct3 {
+        CSRRW = 1,
+        CSRRS = 2,
+        CSRRC = 3,
+        CSRRWI = 5,
+        CSRRSI = 6,
+        CSRRCI = 7,
+    }
+
+    const fn encode(rd: u8, csr: CsrAddress, rs1: u8, funct3: Funct3) -> u32 {
+        (csr as u32 & 0xFFF) << 20
+            | (rs1 as u32 & 0x1F) << 15
+            | (funct3 as u32) << 12
+            | (rd as u32 & 0x1F) << 7
+            | Opcode::System as u32
+    }
+
+    macro_rules! csrtype_instructions {
+        ($($name:ident: $funct3:expr),*) => {
+            $(pub const fn $name(rd: u8, csr: CsrAddress, rs1: u8)-> u32 {
+                encode(rd, csr, rs1, $funct3)
+            })*
+        };
+    }
+
+    // the immediate forms take a 5 bit uimm in place of rs1
+    csrtype_instructions! {
+        csrrw: Funct3::CSRRW,
+        csrrs: Funct3::CSRRS,
+        csrrc: Funct3::CSRRC,
+        csrrwi: Funct3::CSRRWI,
+        csrrsi: Funct3::CSRRSI,
+        csrrci: Funct3::CSRRCI
+    }
+}
+
+/// RV32A 和 RV64A, 汇编里的 `lr.w`、`amoadd.w.aqrl` 对应 `lr_w`、`amoadd_w_aqrl`
+pub mod atype {
+    use crate::instruct_info::Opcode;
+
+    #[derive(Debug, Clone, Copy)]
+    #[repr(u32)]
+    enum Funct5 {
+        LR = 0b00010,
+        SC = 0b00011,
+        AMOSWAP = 0b00001,
+        AMOADD = 0b00000,
+        AMOXOR = 0b00100,
+        AMOAND = 0b01100,
+        AMOOR = 0b01000,
+        AMOMIN = 0b10000,
+        AMOMAX = 0b10100,
+        AMOMINU = 0b11000,
+        AMOMAXU = 0b11100,
+    }
+
+    // aq、rl 两位
+    const AQ: u32 = 0b10;
+    const RL: u32 = 0b01;
+
+    // funct3 是宽度, .w 是 0b010, .d 是 0b011
+    const W: u32 = 0b010;
+    const D: u32 = 0b011;
+
+    const fn encode(funct5: Funct5, width: u32, ordering: u32, rs2: u8, rs1: u8, rd: u8) -> u32 {
+        (funct5 as u32) << 27
+            | ordering << 25
+            | (rs2 as u32 & 0x1F) << 20
+            | (rs1 as u32 & 0x1F) << 15
+            | width << 12
+            | (rd as u32 & 0x1F) << 7
+            | Opcode::AMO as u32
+    }
+
+    macro_rules! lr_instructions {
+        ($width:expr => $($name:ident: $ordering:expr),*) => {
+            $(pub const fn $name(rd: u8, rs1: u8) -> u32 {
+                encode(Funct5::LR, $width, $ordering, 0, rs1, rd)
+            })*
+        };
+    }
+
+    // sc 和 amo 的操作数顺序都是 rd, rs2, (rs1)
+    macro_rules! atype_instructions {
+        ($funct5:expr, $width:expr => $($name:ident: $ordering:expr),*) => {
+            $(pub const fn $name(rd: u8, rs2: u8, rs1: u8) -> u32 {
+                encode($funct5, $width, $ordering, rs2, rs1, rd)
+            })*
+        };
+    }
+
+    lr_instructions! { W => lr_w: 0, lr_w_aq: AQ, lr_w_rl: RL, lr_w_aqrl: AQ | RL }
+    atype_instructions! { Funct5::SC, W => sc_w: 0, sc_w_aq: AQ, sc_w_rl: RL, sc_w_aqrl: AQ | RL }
+    atype_instructions! { Funct5::AMOSWAP, W => amoswap_w: 0, amoswap_w_aq: AQ, amoswap_w_rl: RL, amoswap_w_aqrl: AQ | RL }
+    atype_instructions! { Funct5::AMOADD, W => amoadd_w: 0, amoadd_w_aq: AQ, amoadd_w_rl: RL, amoadd_w_aqrl: AQ | RL }
+    atype_instructions! { Funct5::AMOXOR, W => amoxor_w: 0, amoxor_w_aq: AQ, amoxor_w_rl: RL, amoxor_w_aqrl: AQ | RL }
+    atype_instructions! { Funct5::AMOAND, W => amoand_w: 0, amoand_w_aq: AQ, amoand_w_rl: RL, amoand_w_aqrl: AQ | RL }
+    atype_instructions! { Funct5::AMOOR, W => amoor_w: 0, amoor_w_aq: AQ, amoor_w_rl: RL, amoor_w_aqrl: AQ | RL }
+    atype_instructions! { Funct5::AMOMIN, W => amomin_w: 0, amomin_w_aq: AQ, amomin_w_rl: RL, amomin_w_aqrl: AQ | RL }
+    atype_instructions! { Funct5::AMOMAX, W => amomax_w: 0, amomax_w_aq: AQ, amomax_w_rl: RL, amomax_w_aqrl: AQ | RL }
+    atype_instructions! { Funct5::AMOMINU, W => amominu_w: 0, amominu_w_aq: AQ, amominu_w_rl: RL, amominu_w_aqrl: AQ | RL }
+    atype_instructions! { Funct5::AMOMAXU, W => amomaxu_w: 0, amomaxu_w_aq: AQ, amomaxu_w_rl: RL, amomaxu_w_aqrl: AQ | RL }
+
+    // RV64
+    lr_instructions! { D => lr_d: 0, lr_d_aq: AQ, lr_d_rl: RL, lr_d_aqrl: AQ | RL }
+    atype_instructions! { Funct5::SC, D => sc_d: 0, sc_d_aq: AQ, sc_d_rl: RL, sc_d_aqrl: AQ | RL }
+    atype_instructions! { Funct5::AMOSWAP, D => amoswap_d: 0, amoswap_d_aq: AQ, amoswap_d_rl: RL, amoswap_d_aqrl: AQ | RL }
+    atype_instructions! { Funct5::AMOADD, D => amoadd_d: 0, amoadd_d_aq: AQ, amoadd_d_rl: RL, amoadd_d_aqrl: AQ | RL }
+    atype_instructions! { Funct5::AMOXOR, D => amoxor_d: 0, amoxor_d_aq: AQ, amoxor_d_rl: RL, amoxor_d_aqrl: AQ | RL }
+    atype_instructions! { Funct5::AMOAND, D => amoand_d: 0, amoand_d_aq: AQ, amoand_d_rl: RL, amoand_d_aqrl: AQ | RL }
+    atype_instructions! { Funct5::AMOOR, D => amoor_d: 0, amoor_d_aq: AQ, amoor_d_rl: RL, amoor_d_aqrl: AQ | RL }
+    atype_instructions! { Funct5::AMOMIN, D => amomin_d: 0, amomin_d_aq: AQ, amomin_d_rl: RL, amomin_d_aqrl: AQ | RL }
+    atype_instructions! { Funct5::AMOMAX, D => amomax_d: 0, amomax_d_aq: AQ, amomax_d_rl: RL, amomax_d_aqrl: AQ | RL }
+    atype_instructions! { Funct5::AMOMINU, D => amominu_d: 0, amominu_d_aq: AQ, amominu_d_rl: RL, amominu_d_aqrl: AQ | RL }
+    atype_instructions! { Funct5::AMOMAXU, D => amomaxu_d: 0, amomaxu_d_aq: AQ, amomaxu_d_rl: RL, amomaxu_d_aqrl: AQ | RL }
+}
+
+/// RV32F 和 RV32D, 汇编里的 `fadd.s`、`fcvt.w.d` 对应 `fadd_s`、`fcvt_w_d`。
+/// 带舍入模式的指令默认用 dyn (按 frm 舍入), 写明舍入模式的 `fadd.s ft0, ft1, ft2, rtz`
+/// 对应 `fadd_s_rm(ft0, ft1, ft2, rtz)`。
+pub mod ftype {
+    use crate::instruct_info::Opcode;
+
+    /// 舍入模式
+    #[allow(non_upper_case_globals)]
+    pub const rne: u8 = 0b000;
+    #[allow(non_upper_case_globals)]
+    pub const rtz: u8 = 0b001;
+    #[allow(non_upper_case_globals)]
+    pub const rdn: u8 = 0b010;
+    #[allow(non_upper_case_globals)]
+    pub const rup: u8 = 0b011;
+    #[allow(non_upper_case_globals)]
+    pub const rmm: u8 = 0b100;
+    const DYN: u8 = 0b111;
+
+    #[derive(Debug, Clone, Copy)]
+    #[repr(u32)]
+    enum Fmt {
+        S = 0b00,
+        D = 0b01,
+    }
+
+    #[derive(Debug, Clone, Copy)]
+    #[repr(u32)]
+    enum Funct5 {
+        FADD = 0b00000,
+        FSUB = 0b00001,
+        FMUL = 0b00010,
+        FDIV = 0b00011,
+        FSQRT = 0b01011,
+        FSGNJ = 0b00100,
+        FMINMAX = 0b00101,
+        FCVTFF = 0b01000,
+        FCMP = 0b10100,
+        FCVTWF = 0b11000,
+        FCVTFW = 0b11010,
+        FMVXW = 0b11100,
+        FMVWX = 0b11110,
+    }
+
+    const fn encode(funct5: Funct5, fmt: Fmt, rs2: u8, rs1: u8, funct3: u8, rd: u8) -> u32 {
+        (funct5 as u32) << 27
+            | (fmt as u32) << 25
+            | (rs2 as u32 & 0x1F) << 20
+            | (rs1 as u32 & 0x1F) << 15
+            | (funct3 as u32 & 0b111) << 12
+            | (rd as u32 & 0x1F) << 7
+            | Opcode::OpFp as u32
+    }
+
+    // fmadd 这一类, rs3 占 funct5 的位置
+    const fn encode_r4(opcode: Opcode, fmt: Fmt, rs3: u8, rs2: u8, rs1: u8, rm: u8, rd: u8) -> u32 {
+        (rs3 as u32 & 0x1F) << 27
+            | (fmt as u32) << 25
+            | (rs2 as u32 & 0x1F) << 20
+            | (rs1 as u32 & 0x1F) << 15
+            | (rm as u32 & 0b111) << 12
+            | (rd as u32 & 0x1F) << 7
+            | opcode as u32
+    }
+
+    const fn encode_load(rd: u8, rs1: u8, funct3: u32, imm: i16) -> u32 {
+        (imm as u32 & 0xFFF) << 20
+            | (rs1 as u32 & 0x1F) << 15
+            | funct3 << 12
+            | (rd as u32 & 0x1F) << 7
+            | Opcode::LoadFp as u32
+    }
+
+    const fn encode_store(rs2: u8, rs1: u8, funct3: u32, imm: i16) -> u32 {
+        ((imm >> 5) as u32 & 0x7F) << 25
+            | (rs2 as u32 & 0x1F) << 20
+            | (rs1 as u32 & 0x1F) << 15
+            | funct3 << 12
+            | (imm as u32 & 0x1F) << 7
+            | Opcode::StoreFp as u32
+    }
+
+    pub const fn flw(rd: u8, rs1: u8, imm: i16) -> u32 {
+        encode_load(rd, rs1, 0b010, imm)
+    }
+
+    pub const fn fld(rd: u8, rs1: u8, imm: i16) -> u32 {
+        encode_load(rd, rs1, 0b011, imm)
+    }
+
+    pub const fn fsw(rs2: u8, rs1: u8, imm: i16) -> u32 {
+        encode_store(rs2, rs1, 0b010, imm)
+    }
+
+    pub const fn fsd(rs2: u8, rs1: u8, imm: i16) -> u32 {
+        encode_store(rs2, rs1, 0b011, imm)
+    }
+
+    // 两个操作数, funct3 是舍入模式
+    macro_rules! rounded_instructions {
+        ($($name:ident, $name_rm:ident: ($funct5:expr, $fmt:expr)),*) => {
+            $(pub const fn $name(rd: u8, rs1: u8, rs2: u8) -> u32 {
+                $name_rm(rd, rs1, rs2, DYN)
+            }
+            pub const fn $name_rm(rd: u8, rs1: u8, rs2: u8, rm: u8) -> u32 {
+                encode($funct5, $fmt, rs2, rs1, rm, rd)
+            })*
+        };
+    }
+
+    // 一个操作数, rs2 固定, 用来区分转换的格式
+    macro_rules! rounded_unary_instructions {
+        ($($name:ident, $name_rm:ident: ($funct5:expr, $fmt:expr, $rs2:expr)),*) => {
+            $(pub const fn $name(rd: u8, rs1: u8) -> u32 {
+                $name_rm(rd, rs1, DYN)
+            }
+            pub const fn $name_rm(rd: u8, rs1: u8, rm: u8) -> u32 {
+                encode($funct5, $fmt, $rs2, rs1, rm, rd)
+            })*
+        };
+    }
+
+    // funct3 固定, 不舍入
+    macro_rules! ftype_instructions {
+        ($($name:ident: ($funct5:expr, $fmt:expr, $funct3:expr)),*) => {
+            $(pub const fn $name(rd: u8, rs1: u8, rs2: u8) -> u32 {
+                encode($funct5, $fmt, rs2, rs1, $funct3, rd)
+            })*
+        };
+    }
+
+    macro_rules! unary_instructions {
+        ($($name:ident: ($funct5:expr, $fmt:expr, $funct3:expr)),*) => {
+            $(pub const fn $name(rd: u8, rs1: u8) -> u32 {
+                encode($funct5, $fmt, 0, rs1, $funct3, rd)
+            })*
+        };
+    }
+
+    macro_rules! fused_instructions {
+        ($($name:ident, $name_rm:ident: ($opcode:expr, $fmt:expr)),*) => {
+            $(pub const fn $name(rd: u8, rs1: u8, rs2: u8, rs3: u8) -> u32 {
+                $name_rm(rd, rs1, rs2, rs3, DYN)
+            }
+            pub const fn $name_rm(rd: u8, rs1: u8, rs2: u8, rs3: u8, rm: u8) -> u32 {
+                encode_r4($opcode, $fmt, rs3, rs2, rs1, rm, rd)
+            })*
+        };
+    }
+
+    rounded_instructions! {
+        fadd_s, fadd_s_rm: (Funct5::FADD, Fmt::S),
+        fsub_s, fsub_s_rm: (Funct5::FSUB, Fmt::S),
+        fmul_s, fmul_s_rm: (Funct5::FMUL, Fmt::S),
+        fdiv_s, fdiv_s_rm: (Funct5::FDIV, Fmt::S),
+        fadd_d, fadd_d_rm: (Funct5::FADD, Fmt::D),
+        fsub_d, fsub_d_rm: (Funct5::FSUB, Fmt::D),
+        fmul_d, fmul_d_rm: (Funct5::FMUL, Fmt::D),
+        fdiv_d, fdiv_d_rm: (Funct5::FDIV, Fmt::D)
+    }
+
+    rounded_unary_instructions! {
+        fsqrt_s, fsqrt_s_rm: (Funct5::FSQRT, Fmt::S, 0),
+        fsqrt_d, fsqrt_d_rm: (Funct5::FSQRT, Fmt::D, 0),
+        fcvt_w_s, fcvt_w_s_rm: (Funct5::FCVTWF, Fmt::S, 0),
+        fcvt_wu_s, fcvt_wu_s_rm: (Funct5::FCVTWF, Fmt::S, 1),
+        fcvt_s_w, fcvt_s_w_rm: (Funct5::FCVTFW, Fmt::S, 0),
+        fcvt_s_wu, fcvt_s_wu_rm: (Funct5::FCVTFW, Fmt::S, 1),
+        fcvt_w_d, fcvt_w_d_rm: (Funct5::FCVTWF, Fmt::D, 0),
+        fcvt_wu_d, fcvt_wu_d_rm: (Funct5::FCVTWF, Fmt::D, 1),
+        fcvt_d_w, fcvt_d_w_rm: (Funct5::FCVTFW, Fmt::D, 0),
+        fcvt_d_wu, fcvt_d_wu_rm: (Funct5::FCVTFW, Fmt::D, 1),
+        // RV64 的 64 位整数转换
+        fcvt_l_s, fcvt_l_s_rm: (Funct5::FCVTWF, Fmt::S, 2),
+        fcvt_lu_s, fcvt_lu_s_rm: (Funct5::FCVTWF, Fmt::S, 3),
+        fcvt_s_l, fcvt_s_l_rm: (Funct5::FCVTFW, Fmt::S, 2),
+        fcvt_s_lu, fcvt_s_lu_rm: (Funct5::FCVTFW, Fmt::S, 3),
+        fcvt_l_d, fcvt_l_d_rm: (Funct5::FCVTWF, Fmt::D, 2),
+        fcvt_lu_d, fcvt_lu_d_rm: (Funct5::FCVTWF, Fmt::D, 3),
+        fcvt_d_l, fcvt_d_l_rm: (Funct5::FCVTFW, Fmt::D, 2),
+        fcvt_d_lu, fcvt_d_lu_rm: (Funct5::FCVTFW, Fmt::D, 3),
+        // rs2 是转换前的格式
+        fcvt_s_d, fcvt_s_d_rm: (Funct5::FCVTFF, Fmt::S, 1),
+        fcvt_d_s, fcvt_d_s_rm: (Funct5::FCVTFF, Fmt::D, 0)
+    }
+
+    ftype_instructions! {
+        fsgnj_s: (Funct5::FSGNJ, Fmt::S, 0b000),
+        fsgnjn_s: (Funct5::FSGNJ, Fmt::S, 0b001),
+        fsgnjx_s: (Funct5::FSGNJ, Fmt::S, 0b010),
+        fmin_s: (Funct5::FMINMAX, Fmt::S, 0b000),
+        fmax_s: (Funct5::FMINMAX, Fmt::S, 0b001),
+        feq_s: (Funct5::FCMP, Fmt::S, 0b010),
+        flt_s: (Funct5::FCMP, Fmt::S, 0b001),
+        fle_s: (Funct5::FCMP, Fmt::S, 0b000),
+        fsgnj_d: (Funct5::FSGNJ, Fmt::D, 0b000),
+        fsgnjn_d: (Funct5::FSGNJ, Fmt::D, 0b001),
+        fsgnjx_d: (Funct5::FSGNJ, Fmt::D, 0b010),
+        fmin_d: (Funct5::FMINMAX, Fmt::D, 0b000),
+        fmax_d: (Funct5::FMINMAX, Fmt::D, 0b001),
+        feq_d: (Funct5::FCMP, Fmt::D, 0b010),
+        flt_d: (Funct5::FCMP, Fmt::D, 0b001),
+        fle_d: (Funct5::FCMP, Fmt::D, 0b000)
+    }
+
+    // fmv.x.d 和 fmv.d.x 只在 RV64 上有
+    unary_instructions! {
+        fmv_x_w: (Funct5::FMVXW, Fmt::S, 0b000),
+        fmv_w_x: (Funct5::FMVWX, Fmt::S, 0b000),
+        fmv_x_d: (Funct5::FMVXW, Fmt::D, 0b000),
+        fmv_d_x: (Funct5::FMVWX, Fmt::D, 0b000),
+        fclass_s: (Funct5::FMVXW, Fmt::S, 0b001),
+        fclass_d: (Funct5::FMVXW, Fmt::D, 0b001)
+    }
+
+    fused_instructions! {
+        fmadd_s, fmadd_s_rm: (Opcode::MAdd, Fmt::S),
+        fmsub_s, fmsub_s_rm: (Opcode::MSub, Fmt::S),
+        fnmsub_s, fnmsub_s_rm: (Opcode::NMSub, Fmt::S),
+        fnmadd_s, fnmadd_s_rm: (Opcode::NMAdd, Fmt::S),
+        fmadd_d, fmadd_d_rm: (Opcode::MAdd, Fmt::D),
+        fmsub_d, fmsub_d_rm: (Opcode::MSub, Fmt::D),
+        fnmsub_d, fnmsub_d_rm: (Opcode::NMSub, Fmt::D),
+        fnmadd_d, fnmadd_d_rm: (Opcode::NMAdd, Fmt::D)
+    }
+}
+
+/// RV32C 和 RV64C, 汇编里的 `c.addi` 对应 `c_addi`, 每条指令只占半个字。
+/// 寄存器写成 x8..x15 之外的 rd'/rs1'/rs2' 时只取低 3 位。
+/// 跳转和分支的偏移和 `jal` 一样以半字为单位。
+pub mod ctype {
+    /// `value` 的 `hi..=lo` 位, 移到 `to` 开始的位置
+    const fn bits(value: i32, hi: u32, lo: u32, to: u32) -> u16 {
+        (((value as u32 >> lo) & ((1 << (hi - lo + 1)) - 1)) << to) as u16
+    }
+
+    // rd'/rs1'/rs2'
+    const fn short(register: u8) -> u16 {
+        (register as u16 - 8) & 0b111
+    }
+
+    const fn ci(funct3: u16, rd: u8, imm: i32, op: u16) -> u16 {
+        funct3 << 13 | bits(imm, 5, 5, 12) | (rd as u16 & 0x1F) << 7 | bits(imm, 4, 0, 2) | op
+    }
+
+    const fn cl(funct3: u16, rd: u8, rs1: u8, imm: u8) -> u16 {
+        let imm = imm as i32;
+        funct3 << 13
+            | bits(imm, 5, 3, 10)
+            | short(rs1) << 7
+            | bits(imm, 2, 2, 6)
+            | bits(imm, 6, 6, 5)
+            | short(rd) << 2
+    }
+
+    const fn cj(funct3: u16, imm: i16) -> u16 {
+        let imm = (imm as i32) << 1;
+        funct3 << 13
+            | bits(imm, 11, 11, 12)
+            | bits(imm, 4, 4, 11)
+            | bits(imm, 9, 8, 9)
+            | bits(imm, 10, 10, 8)
+            | bits(imm, 6, 6, 7)
+            | bits(imm, 7, 7, 6)
+            | bits(imm, 3, 1, 3)
+            | bits(imm, 5, 5, 2)
+            | 0b01
+    }
+
+    const fn cb(funct3: u16, rs1: u8, imm: i16) -> u16 {
+        let imm = (imm as i32) << 1;
+        funct3 << 13
+            | bits(imm, 8, 8, 12)
+            | bits(imm, 4, 3, 10)
+            | short(rs1) << 7
+            | bits(imm, 7, 6, 5)
+            | bits(imm, 2, 1, 3)
+            | bits(imm, 5, 5, 2)
+            | 0b01
+    }
+
+    // c.sub/c.xor/c.or/c.and
+    const fn ca(funct2: u16, rd: u8, rs2: u8) -> u16 {
+        0b100 << 13 | 0b11 << 10 | short(rd) << 7 | funct2 << 5 | short(rs2) << 2 | 0b01
+    }
+
+    // c.jr/c.mv/c.ebreak/c.jalr/c.add
+    const fn cr(funct4: u16, rs1: u8, rs2: u8) -> u16 {
+        funct4 << 12 | (rs1 as u16 & 0x1F) << 7 | (rs2 as u16 & 0x1F) << 2 | 0b10
+    }
+
+    pub const fn c_addi4spn(rd: u8, _sp: u8, imm: i16) -> u16 {
+        let imm = imm as i32;
+        bits(imm, 5, 4, 11) | bits(imm, 9, 6, 7) | bits(imm, 2, 2, 6) | bits(imm, 3, 3, 5) | short(rd) << 2
+    }
+
+    pub const fn c_lw(rd: u8, rs1: u8, imm: u8) -> u16 {
+        cl(0b010, rd, rs1, imm)
+    }
+
+    pub const fn c_sw(rs2: u8, rs1: u8, imm: u8) -> u16 {
+        cl(0b110, rs2, rs1, imm)
+    }
+
+    pub const fn c_nop() -> u16 {
+        ci(0b000, 0, 0, 0b01)
+    }
+
+    pub const fn c_addi(rd: u8, imm: i8) -> u16 {
+        ci(0b000, rd, imm as i32, 0b01)
+    }
+
+    pub const fn c_jal(imm: i16) -> u16 {
+        cj(0b001, imm)
+    }
+
+    pub const fn c_li(rd: u8, imm: i8) -> u16 {
+        ci(0b010, rd, imm as i32, 0b01)
+    }
+
+    pub const fn c_addi16sp(_sp: u8, imm: i16) -> u16 {
+        let imm = imm as i32;
+        0b011 << 13
+            | bits(imm, 9, 9, 12)
+            | 2 << 7
+            | bits(imm, 4, 4, 6)
+            | bits(imm, 6, 6, 5)
+            | bits(imm, 8, 7, 3)
+            | bits(imm, 5, 5, 2)
+            | 0b01
+    }
+
+    /// 和 `lui` 一样, `imm` 是高位的值
+    pub const fn c_lui(rd: u8, imm: i8) -> u16 {
+        ci(0b011, rd, imm as i32, 0b01)
+    }
+
+    // shamt 的第 6 位只在 RV64 上用
+    pub const fn c_srli(rd: u8, shamt: u8) -> u16 {
+        0b100 << 13 | bits(shamt as i32, 5, 5, 12) | short(rd) << 7 | (shamt as u16 & 0x1F) << 2 | 0b01
+    }
+
+    pub const fn c_srai(rd: u8, shamt: u8) -> u16 {
+        0b100 << 13
+            | bits(shamt as i32, 5, 5, 12)
+            | 0b01 << 10
+            | short(rd) << 7
+            | (shamt as u16 & 0x1F) << 2
+            | 0b01
+    }
+
+    pub const fn c_andi(rd: u8, imm: i8) -> u16 {
+        let imm = imm as i32;
+        0b100 << 13 | bits(imm, 5, 5, 12) | 0b10 << 10 | short(rd) << 7 | bits(imm, 4, 0, 2) | 0b01
+    }
+
+    pub const fn c_sub(rd: u8, rs2: u8) -> u16 {
+        ca(0b00, rd, rs2)
+    }
+
+    pub const fn c_xor(rd: u8, rs2: u8) -> u16 {
+        ca(0b01, rd, rs2)
+    }
+
+    pub const fn c_or(rd: u8, rs2: u8) -> u16 {
+        ca(0b10, rd, rs2)
+    }
+
+    pub const fn c_and(rd: u8, rs2: u8) -> u16 {
+        ca(0b11, rd, rs2)
+    }
+
+    pub const fn c_j(imm: i16) -> u16 {
+        cj(0b101, imm)
+    }
+
+    pub const fn c_beqz(rs1: u8, imm: i16) -> u16 {
+        cb(0b110, rs1, imm)
+    }
+
+    pub const fn c_bnez(rs1: u8, imm: i16) -> u16 {
+        cb(0b111, rs1, imm)
+    }
+
+    pub const fn c_slli(rd: u8, shamt: u8) -> u16 {
+        ci(0b000, rd, shamt as i32 & 0x3F, 0b10)
+    }
+
+    // c.lwsp/c.flwsp
+    const fn ci_word_sp(funct3: u16, rd: u8, imm: u8) -> u16 {
+        let imm = imm as i32;
+        funct3 << 13
+            | bits(imm, 5, 5, 12)
+            | (rd as u16 & 0x1F) << 7
+            | bits(imm, 4, 2, 4)
+            | bits(imm, 7, 6, 2)
+            | 0b10
+    }
+
+    // c.swsp/c.fswsp
+    const fn css_word(funct3: u16, rs2: u8, imm: u8) -> u16 {
+        let imm = imm as i32;
+        funct3 << 13 | bits(imm, 5, 2, 9) | bits(imm, 7, 6, 7) | (rs2 as u16 & 0x1F) << 2 | 0b10
+    }
+
+    pub const fn c_lwsp(rd: u8, _sp: u8, imm: u8) -> u16 {
+        ci_word_sp(0b010, rd, imm)
+    }
+
+    pub const fn c_swsp(rs2: u8, _sp: u8, imm: u8) -> u16 {
+        css_word(0b110, rs2, imm)
+    }
+
+    pub const fn c_jr(rs1: u8) -> u16 {
+        cr(0b1000, rs1, 0)
+    }
+
+    pub const fn c_mv(rd: u8, rs2: u8) -> u16 {
+        cr(0b1000, rd, rs2)
+    }
+
+    pub const fn c_ebreak() -> u16 {
+        cr(0b1001, 0, 0)
+    }
+
+    pub const fn c_jalr(rs1: u8) -> u16 {
+        cr(0b1001, rs1, 0)
+    }
+
+    pub const fn c_add(rd: u8, rs2: u8) -> u16 {
+        cr(0b1001, rd, rs2)
+    }
+
+    // 以下只在 RV32 上有, RV64 上这些编码是 c.ld/c.sd/c.ldsp/c.sdsp
+    pub const fn c_flw(rd: u8, rs1: u8, imm: u8) -> u16 {
+        cl(0b011, rd, rs1, imm)
+    }
+
+    pub const fn c_fsw(rs2: u8, rs1: u8, imm: u8) -> u16 {
+        cl(0b111, rs2, rs1, imm)
+    }
+
+    pub const fn c_flwsp(rd: u8, _sp: u8, imm: u8) -> u16 {
+        ci_word_sp(0b011, rd, imm)
+    }
+
+    pub const fn c_fswsp(rs2: u8, _sp: u8, imm: u8) -> u16 {
+        css_word(0b111, rs2, imm)
+    }
+
+    // RV32 和 RV64 都有
+    pub const fn c_fld(rd: u8, rs1: u8, imm: u8) -> u16 {
+        cl_double(0b001, rd, rs1, imm)
+    }
+
+    pub const fn c_fsd(rs2: u8, rs1: u8, imm: u8) -> u16 {
+        cl_double(0b101, rs2, rs1, imm)
+    }
+
+    pub const fn c_fldsp(rd: u8, _sp: u8, imm: u16) -> u16 {
+        ci_double_sp(0b001, rd, imm)
+    }
+
+    pub const fn c_fsdsp(rs2: u8, _sp: u8, imm: u16) -> u16 {
+        css_double(0b101, rs2, imm)
+    }
+
+    // 以下只在 RV64 上有, c.addiw 占了 c.jal 的编码
+    const fn cl_double(funct3: u16, rd: u8, rs1: u8, imm: u8) -> u16 {
+        let imm = imm as i32;
+        funct3 << 13 | bits(imm, 5, 3, 10) | short(rs1) << 7 | bits(imm, 7, 6, 5) | short(rd) << 2
+    }
+
+    pub const fn c_ld(rd: u8, rs1: u8, imm: u8) -> u16 {
+        cl_double(0b011, rd, rs1, imm)
+    }
+
+    pub const fn c_sd(rs2: u8, rs1: u8, imm: u8) -> u16 {
+        cl_double(0b111, rs2, rs1, imm)
+    }
+
+    pub const fn c_addiw(rd: u8, imm: i8) -> u16 {
+        ci(0b001, rd, imm as i32, 0b01)
+    }
+
+    pub const fn c_subw(rd: u8, rs2: u8) -> u16 {
+        1 << 12 | ca(0b00, rd, rs2)
+    }
+
+    pub const fn c_addw(rd: u8, rs2: u8) -> u16 {
+        1 << 12 | ca(0b01, rd, rs2)
+    }
+
+    // c.ldsp/c.fldsp
+    const fn ci_double_sp(funct3: u16, rd: u8, imm: u16) -> u16 {
+        let imm = imm as i32;
+        funct3 << 13
+            | bits(imm, 5, 5, 12)
+            | (rd as u16 & 0x1F) << 7
+            | bits(imm, 4, 3, 5)
+            | bits(imm, 8, 6, 2)
+            | 0b10
+    }
+
+    // c.sdsp/c.fsdsp
+    const fn css_double(funct3: u16, rs2: u8, imm: u16) -> u16 {
+        let imm = imm as i32;
+        funct3 << 13 | bits(imm, 5, 3, 10) | bits(imm, 8, 6, 7) | (rs2 as u16 & 0x1F) << 2 | 0b10
+    }
+
+    pub const fn c_ldsp(rd: u8, _sp: u8, imm: u16) -> u16 {
+        ci_double_sp(0b011, rd, imm)
+    }
+
+    pub const fn c_sdsp(rs2: u8, _sp: u8, imm: u16) -> u16 {
+        css_double(0b111, rs2, imm)
+    }
+}
+
+pub mod system {
+    use crate::instruct_info::Opcode;
+
+    pub const fn ecall() -> u32 {
+        Opcode::System as u32
+    }
+
+    pub const fn ebreak() -> u32 {
+        1 << 20 | Opcode::System as u32
+    }
+
+    pub const fn sret() -> u32 {
+        0x102 << 20 | Opcode::System as u32
+    }
+
+    pub const fn mret() -> u32 {
+        0x302 << 20 | Opcode::System as u32
+    }
+
+    /// flushes the translations of the address in `rs1` and the ASID in `rs2`
+    pub const fn sfence_vma(rs1: u8, rs2: u8) -> u32 {
+        0b0001001 << 25 | (rs2 as u32) << 20 | (rs1 as u32) << 15 | Opcode::System as u32
+    }
+
+    pub const fn wfi() -> u32 {
+        0x105 << 20 | Opcode::System as u32
+    }
+
+    /// `fence iorw, iorw`
+    pub const fn fence() -> u32 {
+        0x0FF << 20 | Opcode::MiscMem as u32
+    }
+
+    pub const fn fence_tso() -> u32 {
+        0x833 << 20 | Opcode::MiscMem as u32
+    }
+
+    /// `fence w, 0`
+    pub const fn pause() -> u32 {
+        0x010 << 20 | Opcode::MiscMem as u32
+    }
+
+    pub const fn fence_i() -> u32 {
+        1 << 12 | Opcode::MiscMem as u32
+    }
+}
+
 pub mod pseudo {
+    use crate::instruct_info::csrtype::{csrrc, csrrci, csrrs, csrrsi, csrrw, csrrwi};
+    use crate::instruct_info::ftype::{fsgnj_d, fsgnj_s, fsgnjn_d, fsgnjn_s, fsgnjx_d, fsgnjx_s};
     use crate::instruct_info::jtype::jal;
-    use crate::instruct_info::itype::{addi, jalr};
+    use crate::instruct_info::itype::{addi, addiw, jalr};
     use crate::instruct_info::utype::lui;
     use crate::register::alias::ra;
+    use crate::register::csr::CsrAddress;
+    use crate::register::csr::{FCSR, FFLAGS, FRM};
 
     pub const fn j(imm: i32) -> u32 {
         jal(0, imm)
@@ -319,6 +1228,10 @@ pub mod pseudo {
         addi(rd, rs, 0)
     }
 
+    pub const fn sext_w(rd: u8, rs: u8) -> u32 {
+        addiw(rd, rs, 0)
+    }
+
     pub const fn nop() -> u32 {
         addi(0, 0, 0)
     }
@@ -327,6 +1240,82 @@ pub mod pseudo {
         0
     }
 
+    pub const fn csrr(rd: u8, csr: CsrAddress) -> u32 {
+        csrrs(rd, csr, 0)
+    }
+
+    pub const fn csrw(csr: CsrAddress, rs1: u8) -> u32 {
+        csrrw(0, csr, rs1)
+    }
+
+    pub const fn csrs(csr: CsrAddress, rs1: u8) -> u32 {
+        csrrs(0, csr, rs1)
+    }
+
+    pub const fn csrc(csr: CsrAddress, rs1: u8) -> u32 {
+        csrrc(0, csr, rs1)
+    }
+
+    pub const fn csrwi(csr: CsrAddress, uimm: u8) -> u32 {
+        csrrwi(0, csr, uimm)
+    }
+
+    pub const fn csrsi(csr: CsrAddress, uimm: u8) -> u32 {
+        csrrsi(0, csr, uimm)
+    }
+
+    pub const fn csrci(csr: CsrAddress, uimm: u8) -> u32 {
+        csrrci(0, csr, uimm)
+    }
+
+    pub const fn fmv_s(rd: u8, rs: u8) -> u32 {
+        fsgnj_s(rd, rs, rs)
+    }
+
+    pub const fn fneg_s(rd: u8, rs: u8) -> u32 {
+        fsgnjn_s(rd, rs, rs)
+    }
+
+    pub const fn fabs_s(rd: u8, rs: u8) -> u32 {
+        fsgnjx_s(rd, rs, rs)
+    }
+
+    pub const fn fmv_d(rd: u8, rs: u8) -> u32 {
+        fsgnj_d(rd, rs, rs)
+    }
+
+    pub const fn fneg_d(rd: u8, rs: u8) -> u32 {
+        fsgnjn_d(rd, rs, rs)
+    }
+
+    pub const fn fabs_d(rd: u8, rs: u8) -> u32 {
+        fsgnjx_d(rd, rs, rs)
+    }
+
+    pub const fn frcsr(rd: u8) -> u32 {
+        csrr(rd, FCSR)
+    }
+
+    pub const fn fscsr(rs1: u8) -> u32 {
+        csrw(FCSR, rs1)
+    }
+
+    pub const fn frrm(rd: u8) -> u32 {
+        csrr(rd, FRM)
+    }
+
+    pub const fn fsrm(rs1: u8) -> u32 {
+        csrw(FRM, rs1)
+    }
+
+    pub const fn frflags(rd: u8) -> u32 {
+        csrr(rd, FFLAGS)
+    }
+
+    pub const fn fsflags(rs1: u8) -> u32 {
+        csrw(FFLAGS, rs1)
+    }
+
     // TODO:
     pub const fn li(rd: u8, imm: i32) -> u32 {
         if -2048 <= imm && imm < 2048 {
@@ -347,5 +1336,10 @@ pub mod prelude {
     pub use crate::instruct_info::utype::*;
     pub use crate::instruct_info::btype::*;
     pub use crate::instruct_info::jtype::*;
+    pub use crate::instruct_info::csrtype::*;
+    pub use crate::instruct_info::atype::*;
+    pub use crate::instruct_info::ftype::*;
+    pub use crate::instruct_info::ctype::*;
+    pub use crate::instruct_info::system::*;
     pub use crate::instruct_info::pseudo::*;
 }
\ No newline at end of file
@@ -1,88 +1,383 @@
 #![allow(dead_code)]
 
-use crate::arch::{Byte, R32I};
+use crate::alu::FpOp;
+use crate::arch::{Address, Byte, PC_STEP, R32I, Xlen};
+use crate::compressed;
+use crate::float::RoundingMode;
 use crate::mask;
-use crate::opcode::{AUIPC, B_TYPE, I_TYPE, JALR, J_TYPE, LUI, NOP, RI_TYPE, R_TYPE, S_TYPE};
+use crate::opcode::{
+    AMO, AUIPC, B_TYPE, I_TYPE, JALR, J_TYPE, LOAD_FP, LUI, MADD, MISC_MEM, MSUB, NMADD, NMSUB,
+    NOP, OP_FP, RI_TYPE, RI_TYPE_32, R_TYPE, R_TYPE_32, STORE_FP, SYSTEM, S_TYPE,
+};
+use crate::register::{abi_name, float_abi_name};
+use crate::register::csr::{self, CsrAddress};
 use std::fmt::{Display, Formatter};
 
-#[derive(Copy, Clone, Default)]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
 pub struct Instruction(pub R32I);
 
+/// `pc + 8` / `pc - 8`, the way Spike prints branch and jump targets
+struct PcOffset(i32);
+
+impl Display for PcOffset {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.0 < 0 {
+            write!(f, "pc - {}", self.0.unsigned_abs())
+        } else {
+            write!(f, "pc + {}", self.0)
+        }
+    }
+}
+
+fn csr(csr: u32) -> String {
+    match csr::name(csr as CsrAddress) {
+        Some(name) => name.to_string(),
+        None => format!("{:#x}", csr),
+    }
+}
+
+/// An instruction with its compressed form expanded for an XLEN, see
+/// `Instruction::display`.
+pub struct Disassembly(Instruction, Xlen);
+
+impl Display for Disassembly {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.0.expanded(self.1).fmt(f)
+    }
+}
+
+/// Disassembles in the objdump / Spike style, e.g. `addi    a0, a0, 1`.
+/// Compressed instructions print as what they expand to on RV32, like
+/// objdump does. The RV64-only encodings print the same either way.
+/// Words that don't decode print as `unknown`.
 impl Display for Instruction {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        // f.write_fmt(format_args!("instructions: {:032b}\n", self.0))?;
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.is_compressed() {
+            return self.expanded(Xlen::X32).fmt(f);
+        }
         match self.opcode() as Byte {
-            I_TYPE => write!(f, "{}", self.as_i())?,
-            RI_TYPE => write!(f, "{}", self.as_i())?,
-            R_TYPE => write!(f, "{}", self.as_r())?,
-            S_TYPE => write!(f, "{}", self.as_s())?,
-            B_TYPE => write!(f, "{}", self.as_b())?,
+            I_TYPE | RI_TYPE | RI_TYPE_32 => write!(f, "{}", self.as_i()),
+            R_TYPE | R_TYPE_32 => write!(f, "{}", self.as_r()),
+            S_TYPE => write!(f, "{}", self.as_s()),
+            B_TYPE => write!(f, "{}", self.as_b()),
             J_TYPE => {
                 let j = self.as_j();
-                write!(f, "j: rd: {}, imm: {}", j.rd(), j.imm())?;
+                write!(
+                    f,
+                    "{:<7} {}, {}",
+                    "jal",
+                    abi_name(j.rd()),
+                    PcOffset(j.imm())
+                )
             }
             JALR => {
                 let i = self.as_i();
-                write!(f, "jalr: rs1: {}, imm: {}", i.rs1(), i.imm())?;
+                let (rd, rs1) = (abi_name(i.rd()), abi_name(i.rs1()));
+                write!(f, "{:<7} {}, {}({})", "jalr", rd, i.imm(), rs1)
             }
-            LUI => {
+            LUI | AUIPC => {
                 let u = self.as_u();
-                write!(f, "lui: rd: {}, imm: {}", u.rd(), u.high_imm())?;
+                let op = if self.opcode() as Byte == LUI {
+                    "lui"
+                } else {
+                    "auipc"
+                };
+                write!(
+                    f,
+                    "{:<7} {}, {:#x}",
+                    op,
+                    abi_name(u.rd()),
+                    u.high_imm() >> 12
+                )
             }
-            AUIPC => {
-                let u = self.as_u();
-                write!(f, "lui: rd: {}, imm: {}", u.rd(), u.high_imm())?;
+            SYSTEM => {
+                let i = self.as_i();
+                let op = match i.funct3() {
+                    0b001 => "csrrw",
+                    0b010 => "csrrs",
+                    0b011 => "csrrc",
+                    0b101 => "csrrwi",
+                    0b110 => "csrrsi",
+                    0b111 => "csrrci",
+                    0b000 if i.umm() == 0 => return f.write_str("ecall"),
+                    0b000 if i.umm() == 1 => return f.write_str("ebreak"),
+                    0b000 if i.umm() == 0x102 => return f.write_str("sret"),
+                    0b000 if i.umm() == 0x302 => return f.write_str("mret"),
+                    0b000 if i.umm() == 0x105 => return f.write_str("wfi"),
+                    0b000 if i.umm() >> 5 == 0b0001001 => {
+                        let rs2 = self.as_r().rs2();
+                        return write!(f, "sfence.vma {}, {}", abi_name(i.rs1()), abi_name(rs2));
+                    }
+                    _ => return f.write_str("unknown"),
+                };
+                write!(f, "{:<7} {}, {}, ", op, abi_name(i.rd()), csr(i.umm()))?;
+                if i.funct3() & 0b100 == 0 {
+                    f.write_str(abi_name(i.rs1()))
+                } else {
+                    write!(f, "{}", i.rs1())
+                }
+            }
+            AMO => {
+                let r = self.as_r();
+                let op = match r.funct7() >> 2 {
+                    0b00010 => "lr",
+                    0b00011 => "sc",
+                    0b00001 => "amoswap",
+                    0b00000 => "amoadd",
+                    0b00100 => "amoxor",
+                    0b01100 => "amoand",
+                    0b01000 => "amoor",
+                    0b10000 => "amomin",
+                    0b10100 => "amomax",
+                    0b11000 => "amominu",
+                    0b11100 => "amomaxu",
+                    _ => return f.write_str("unknown"),
+                };
+                let width = match r.funct3() {
+                    0b010 => "w",
+                    0b011 => "d",
+                    _ => return f.write_str("unknown"),
+                };
+                let ordering = match r.funct7() & 0b11 {
+                    0b00 => "",
+                    0b01 => ".rl",
+                    0b10 => ".aq",
+                    _ => ".aqrl",
+                };
+                let op = format!("{}.{}{}", op, width, ordering);
+                let (rd, rs1, rs2) = (abi_name(r.rd()), abi_name(r.rs1()), abi_name(r.rs2()));
+                if r.funct7() >> 2 == 0b00010 {
+                    write!(f, "{:<7} {}, ({})", op, rd, rs1)
+                } else {
+                    write!(f, "{:<7} {}, {}, ({})", op, rd, rs2, rs1)
+                }
+            }
+            LOAD_FP => {
+                let i = self.as_i();
+                let op = match i.funct3() {
+                    0b010 => "flw",
+                    0b011 => "fld",
+                    _ => return f.write_str("unknown"),
+                };
+                let (rd, rs1) = (float_abi_name(i.rd()), abi_name(i.rs1()));
+                write!(f, "{:<7} {}, {}({})", op, rd, i.imm(), rs1)
+            }
+            STORE_FP => {
+                let s = self.as_s();
+                let op = match s.funct3() {
+                    0b010 => "fsw",
+                    0b011 => "fsd",
+                    _ => return f.write_str("unknown"),
+                };
+                let (rs1, rs2) = (abi_name(s.rs1()), float_abi_name(s.rs2()));
+                write!(f, "{:<7} {}, {}({})", op, rs2, s.imm(), rs1)
+            }
+            OP_FP | MADD | MSUB | NMSUB | NMADD => self.fmt_float(f),
+            MISC_MEM => {
+                let i = self.as_i();
+                match (i.funct3(), i.umm()) {
+                    (0b000, 0x0FF) => f.write_str("fence"),
+                    (0b000, 0x833) => f.write_str("fence.tso"),
+                    (0b000, 0x010) => f.write_str("pause"),
+                    (0b000, umm) => {
+                        // the i, o, r and w bits of pred and succ
+                        let set = |bits: u32| {
+                            "iorw"
+                                .chars()
+                                .enumerate()
+                                .filter(|(n, _)| bits & (0b1000 >> n) != 0)
+                                .map(|(_, c)| c)
+                                .collect::<String>()
+                        };
+                        write!(f, "{:<7} {}, {}", "fence", set(umm >> 4 & 0xF), set(umm & 0xF))
+                    }
+                    (0b001, _) => f.write_str("fence.i"),
+                    _ => f.write_str("unknown"),
+                }
+            }
+            NOP => f.write_str("stop"),
+            _ => f.write_str("unknown"),
+        }
+    }
+}
+
+impl Instruction {
+    // `fadd.s  ft0, ft1, ft2`, with the rounding mode last unless it's dyn
+    fn fmt_float(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let Some((op, format)) = FpOp::decode(self, Xlen::X64) else {
+            return f.write_str("unknown");
+        };
+        let r = self.as_r();
+        let suffix = if format.is_single() { "s" } else { "d" };
+        let other = if format.is_single() { "d" } else { "s" };
+        let name = match op {
+            FpOp::Add => "fadd",
+            FpOp::Sub => "fsub",
+            FpOp::Mul => "fmul",
+            FpOp::Div => "fdiv",
+            FpOp::Sqrt => "fsqrt",
+            FpOp::MulAdd => "fmadd",
+            FpOp::MulSub => "fmsub",
+            FpOp::NegMulSub => "fnmsub",
+            FpOp::NegMulAdd => "fnmadd",
+            FpOp::SignInject => "fsgnj",
+            FpOp::SignInjectNeg => "fsgnjn",
+            FpOp::SignInjectXor => "fsgnjx",
+            FpOp::Min => "fmin",
+            FpOp::Max => "fmax",
+            FpOp::Eq => "feq",
+            FpOp::Lt => "flt",
+            FpOp::Le => "fle",
+            FpOp::Class => "fclass",
+            FpOp::Convert => "fcvt",
+            FpOp::ToInt { .. } | FpOp::FromInt { .. } => "fcvt",
+            FpOp::MoveToInt => "fmv.x",
+            FpOp::MoveFromInt => "fmv",
+        };
+        let integer = match op {
+            FpOp::ToInt { signed, long } | FpOp::FromInt { signed, long } => {
+                match (long, signed) {
+                    (false, true) => "w",
+                    (false, false) => "wu",
+                    (true, true) => "l",
+                    (true, false) => "lu",
+                }
             }
-            NOP => f.write_str("stop")?,
-            _ => unimplemented!(),
+            _ => "",
+        };
+        // fmv.x.w rather than fmv.x.s
+        let bits = if format.is_single() { "w" } else { "d" };
+        let name = match op {
+            FpOp::Convert => format!("{}.{}.{}", name, suffix, other),
+            FpOp::ToInt { .. } => format!("{}.{}.{}", name, integer, suffix),
+            FpOp::FromInt { .. } => format!("{}.{}.{}", name, suffix, integer),
+            FpOp::MoveToInt => format!("{}.{}", name, bits),
+            FpOp::MoveFromInt => format!("{}.{}.x", name, bits),
+            _ => format!("{}.{}", name, suffix),
+        };
+        let (rd, rs1, rs2) = (r.rd(), r.rs1(), r.rs2());
+        let (rd, rs1) = match op {
+            FpOp::ToInt { .. }
+            | FpOp::MoveToInt
+            | FpOp::Class
+            | FpOp::Eq
+            | FpOp::Lt
+            | FpOp::Le => (abi_name(rd), float_abi_name(rs1)),
+            FpOp::FromInt { .. } | FpOp::MoveFromInt => (float_abi_name(rd), abi_name(rs1)),
+            _ => (float_abi_name(rd), float_abi_name(rs1)),
+        };
+        write!(f, "{:<7} {}, {}", name, rd, rs1)?;
+        match op {
+            FpOp::MulAdd | FpOp::MulSub | FpOp::NegMulSub | FpOp::NegMulAdd => {
+                let rs3 = r.funct7() >> 2;
+                write!(f, ", {}, {}", float_abi_name(rs2), float_abi_name(rs3))?
+            }
+            FpOp::Sqrt
+            | FpOp::Convert
+            | FpOp::ToInt { .. }
+            | FpOp::FromInt { .. }
+            | FpOp::MoveToInt
+            | FpOp::MoveFromInt
+            | FpOp::Class => {}
+            _ => write!(f, ", {}", float_abi_name(rs2))?,
+        }
+        // dyn decodes to `None`
+        match RoundingMode::decode(r.funct3() as u32) {
+            Some(rounding) if op.rounds() => write!(f, ", {}", rounding.name()),
+            _ => Ok(()),
         }
-        Ok(())
     }
 }
 
 impl Display for IInstruction<'_> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        let op = match self.0.opcode() as Byte {
-            RI_TYPE => match self.funct3() {
-                0b000 => "addi",
-                0b010 => "slti",
-                0b011 => "sltiu",
-                0b100 => "xori",
-                0b110 => "ori",
-                0b111 => "andi",
-                0b001 => "slli",
-                0b101 => {
-                    if self.imm() >> 5 == 0 {
-                        "srli"
-                    } else {
-                        "srai"
-                    }
-                }
-                _ => unreachable!(),
-            },
-            I_TYPE => match self.funct3() {
+        let (rd, rs1) = (abi_name(self.rd()), abi_name(self.rs1()));
+        if self.0.opcode() as Byte == I_TYPE {
+            let op = match self.funct3() {
                 0b000 => "lb",
                 0b001 => "lh",
                 0b010 => "lw",
+                0b011 => "ld",
                 0b100 => "lbu",
                 0b101 => "lhu",
-                _ => unreachable!(),
-            },
-            _ => unreachable!(),
+                0b110 => "lwu",
+                _ => return f.write_str("unknown"),
+            };
+            return write!(f, "{:<7} {}, {}({})", op, rd, self.imm(), rs1);
+        }
+        if self.0.opcode() as Byte == RI_TYPE_32 {
+            let op = match (self.funct3(), self.umm() >> 5) {
+                (0b000, _) => "addiw",
+                (0b001, 0) => "slliw",
+                (0b101, 0) => "srliw",
+                (0b101, 0x20) => "sraiw",
+                (0b101, 0x30) => "roriw",
+                (0b001, _) if self.umm() >> 6 == 0b000010 => {
+                    let shamt = self.umm() & 0x3F;
+                    return write!(f, "{:<7} {}, {}, {}", "slli.uw", rd, rs1, shamt);
+                }
+                (0b001, _) => {
+                    let op = match self.umm() {
+                        0x600 => "clzw",
+                        0x601 => "ctzw",
+                        0x602 => "cpopw",
+                        _ => return f.write_str("unknown"),
+                    };
+                    return write!(f, "{:<7} {}, {}", op, rd, rs1);
+                }
+                _ => return f.write_str("unknown"),
+            };
+            let imm = match self.funct3() {
+                0b000 => self.imm(),
+                _ => self.imm() & 0b11111,
+            };
+            return write!(f, "{:<7} {}, {}, {}", op, rd, rs1, imm);
+        }
+        // the unary Zbb ops, rs2 picks the operation
+        let unary = match (self.funct3(), self.umm()) {
+            (0b001, 0x600) => Some("clz"),
+            (0b001, 0x601) => Some("ctz"),
+            (0b001, 0x602) => Some("cpop"),
+            (0b001, 0x604) => Some("sext.b"),
+            (0b001, 0x605) => Some("sext.h"),
+            (0b101, 0x287) => Some("orc.b"),
+            (0b101, 0x698 | 0x6B8) => Some("rev8"),
+            _ => None,
         };
-        write!(
-            f,
-            "{}, rd: {}, rs1: {}, imm: {}",
-            op,
-            self.rd(),
-            self.rs1(),
-            self.imm()
-        )
+        if let Some(op) = unary {
+            return write!(f, "{:<7} {}, {}", op, rd, rs1);
+        }
+        // the shifts by six bits, shamt[5] is always 0 on RV32
+        let op = match (self.funct3(), self.umm() >> 6) {
+            (0b000, _) => "addi",
+            (0b010, _) => "slti",
+            (0b011, _) => "sltiu",
+            (0b100, _) => "xori",
+            (0b110, _) => "ori",
+            (0b111, _) => "andi",
+            (0b001, 0) => "slli",
+            (0b101, 0) => "srli",
+            (0b101, 0x10) => "srai",
+            (0b101, 0x18) => "rori",
+            (0b001, 0x0A) => "bseti",
+            (0b001, 0x12) => "bclri",
+            (0b001, 0x1A) => "binvi",
+            (0b101, 0x12) => "bexti",
+            _ => return f.write_str("unknown"),
+        };
+        let imm = match self.funct3() {
+            0b001 | 0b101 => self.imm() & 0b111111,
+            _ => self.imm(),
+        };
+        write!(f, "{:<7} {}, {}, {}", op, rd, rs1, imm)
     }
 }
 
 impl Display for RInstruction<'_> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.0.opcode() as Byte == R_TYPE_32 {
+            return self.fmt_word(f);
+        }
         let op = match (self.funct3(), self.funct7()) {
             (0b000, 0) => "add",
             (0b000, 0x20) => "sub",
@@ -94,18 +389,77 @@ impl Display for RInstruction<'_> {
             (0b101, 0x20) => "sra",
             (0b110, 0) => "or",
             (0b111, 0) => "and",
-            _ => {
-                panic!("no such instruction");
+            (0b000, 1) => "mul",
+            (0b001, 1) => "mulh",
+            (0b010, 1) => "mulhsu",
+            (0b011, 1) => "mulhu",
+            (0b100, 1) => "div",
+            (0b101, 1) => "divu",
+            (0b110, 1) => "rem",
+            (0b111, 1) => "remu",
+            (0b010, 0x10) => "sh1add",
+            (0b100, 0x10) => "sh2add",
+            (0b110, 0x10) => "sh3add",
+            (0b111, 0x20) => "andn",
+            (0b110, 0x20) => "orn",
+            (0b100, 0x20) => "xnor",
+            (0b100, 0x05) => "min",
+            (0b101, 0x05) => "minu",
+            (0b110, 0x05) => "max",
+            (0b111, 0x05) => "maxu",
+            (0b001, 0x30) => "rol",
+            (0b101, 0x30) => "ror",
+            (0b001, 0x14) => "bset",
+            (0b001, 0x24) => "bclr",
+            (0b001, 0x34) => "binv",
+            (0b101, 0x24) => "bext",
+            (0b100, 0x04) if self.rs2() == 0 => {
+                let (rd, rs1) = (abi_name(self.rd()), abi_name(self.rs1()));
+                return write!(f, "{:<7} {}, {}", "zext.h", rd, rs1);
             }
+            _ => return f.write_str("unknown"),
         };
-        write!(
-            f,
-            "{}: rd: {}, rs1: {}, rs2: {}",
-            op,
-            self.rd(),
-            self.rs1(),
-            self.rs2()
-        )
+        let (rd, rs1, rs2) = (
+            abi_name(self.rd()),
+            abi_name(self.rs1()),
+            abi_name(self.rs2()),
+        );
+        write!(f, "{:<7} {}, {}, {}", op, rd, rs1, rs2)
+    }
+}
+
+impl RInstruction<'_> {
+    // OP-32, the `*w` and `*.uw` ops of RV64
+    fn fmt_word(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let op = match (self.funct3(), self.funct7()) {
+            (0b000, 0) => "addw",
+            (0b000, 0x20) => "subw",
+            (0b001, 0) => "sllw",
+            (0b101, 0) => "srlw",
+            (0b101, 0x20) => "sraw",
+            (0b000, 1) => "mulw",
+            (0b100, 1) => "divw",
+            (0b101, 1) => "divuw",
+            (0b110, 1) => "remw",
+            (0b111, 1) => "remuw",
+            (0b001, 0x30) => "rolw",
+            (0b101, 0x30) => "rorw",
+            (0b000, 0x04) => "add.uw",
+            (0b010, 0x10) => "sh1add.uw",
+            (0b100, 0x10) => "sh2add.uw",
+            (0b110, 0x10) => "sh3add.uw",
+            (0b100, 0x04) if self.rs2() == 0 => {
+                let (rd, rs1) = (abi_name(self.rd()), abi_name(self.rs1()));
+                return write!(f, "{:<7} {}, {}", "zext.h", rd, rs1);
+            }
+            _ => return f.write_str("unknown"),
+        };
+        let (rd, rs1, rs2) = (
+            abi_name(self.rd()),
+            abi_name(self.rs1()),
+            abi_name(self.rs2()),
+        );
+        write!(f, "{:<7} {}, {}, {}", op, rd, rs1, rs2)
     }
 }
 
@@ -118,16 +472,10 @@ impl Display for BInstruction<'_> {
             0b101 => "bge",
             0b110 => "bltu",
             0b111 => "bgeu",
-            _ => panic!("branch unknown funct3"),
+            _ => return f.write_str("unknown"),
         };
-        write!(
-            f,
-            "{}: rs1: {}, rs2: {}, imm: {}",
-            op,
-            self.rs1(),
-            self.rs2(),
-            self.imm()
-        )
+        let (rs1, rs2) = (abi_name(self.rs1()), abi_name(self.rs2()));
+        write!(f, "{:<7} {}, {}, {}", op, rs1, rs2, PcOffset(self.imm()))
     }
 }
 
@@ -137,16 +485,11 @@ impl Display for SInstruction<'_> {
             0b000 => "sb",
             0b001 => "sh",
             0b010 => "sw",
-            _ => unreachable!(),
+            0b011 => "sd",
+            _ => return f.write_str("unknown"),
         };
-        write!(
-            f,
-            "{}: rs1: {}, rs2: {}, imm: {}",
-            op,
-            self.rs1(),
-            self.rs2(),
-            self.imm()
-        )
+        let (rs1, rs2) = (abi_name(self.rs1()), abi_name(self.rs2()));
+        write!(f, "{:<7} {}, {}({})", op, rs2, self.imm(), rs1)
     }
 }
 
@@ -154,6 +497,35 @@ impl Instruction {
     pub const fn from(data: R32I) -> Self {
         Self(data)
     }
+
+    /// `Display` with compressed instructions expanded the `xlen` way
+    pub const fn display(self, xlen: Xlen) -> Disassembly {
+        Disassembly(self, xlen)
+    }
+
+    /// RV32C, a halfword whose low two bits aren't `0b11`. The stop word
+    /// counts as a full word.
+    pub const fn is_compressed(&self) -> bool {
+        self.0 != 0 && self.0 & 0b11 != 0b11
+    }
+
+    /// 2 or 4 bytes
+    pub const fn len(&self) -> Address {
+        if self.is_compressed() { 2 } else { PC_STEP }
+    }
+
+    /// The 32-bit instruction a compressed one stands for, others as they
+    /// are. Reserved compressed encodings become all ones, which doesn't
+    /// decode either.
+    pub const fn expanded(&self, xlen: Xlen) -> Instruction {
+        if !self.is_compressed() {
+            return *self;
+        }
+        match compressed::expand(self.0 as u16, xlen) {
+            Some(word) => Instruction(word),
+            None => Instruction(!0),
+        }
+    }
 }
 pub struct RInstruction<'a>(pub &'a Instruction);
 pub struct IInstruction<'a>(pub &'a Instruction);
@@ -168,27 +540,27 @@ impl Instruction {
         self.0 & mask::OPCODE
     }
 
-    pub const fn as_r(&self) -> RInstruction {
+    pub const fn as_r(&self) -> RInstruction<'_> {
         RInstruction(self)
     }
 
-    pub const fn as_i(&self) -> IInstruction {
+    pub const fn as_i(&self) -> IInstruction<'_> {
         IInstruction(self)
     }
 
-    pub const fn as_j(&self) -> JInstruction {
+    pub const fn as_j(&self) -> JInstruction<'_> {
         JInstruction(self)
     }
 
-    pub const fn as_b(&self) -> BInstruction {
+    pub const fn as_b(&self) -> BInstruction<'_> {
         BInstruction(self)
     }
 
-    pub const fn as_u(&self) -> UInstruction {
+    pub const fn as_u(&self) -> UInstruction<'_> {
         UInstruction(self)
     }
 
-    pub const fn as_s(&self) -> SInstruction {
+    pub const fn as_s(&self) -> SInstruction<'_> {
         SInstruction(self)
     }
     pub const fn mask(&self, mask: u32) -> u32 {
@@ -244,7 +616,7 @@ impl IInstruction<'_> {
         self.0.mask(mask::IMM11_0) >> 20
     }
 
-    pub const fn as_s(&self) -> ISInstruction {
+    pub const fn as_s(&self) -> ISInstruction<'_> {
         ISInstruction(self.0)
     }
 }
@@ -1,14 +1,20 @@
-#![feature(io_const_error)]
 #![allow(dead_code)]
+// types are named after the ISA mnemonics they encode (ADD, ALU, ...)
+#![allow(clippy::upper_case_acronyms)]
 mod alu;
 mod arch;
+mod compressed;
 mod const_emulator;
+mod decode;
+mod device;
 mod emulator;
 mod exception;
+mod float;
 mod instruct_info;
 mod instruction_type;
 mod mask;
 mod memory;
+mod mmu;
 mod opcode;
 mod register;
 mod test_code;
@@ -125,7 +131,7 @@ fn main() {
         };
     let nums = [18, 46, 62, 59, 78, 71, 7, 99, 18, 28];
     context.set_data_segment(&nums).set_code_segment(QUICK_SORT);
-    context.run_with_thread();
+    context.run_with_thread().expect("quick_sort trapped");
     #[allow(long_running_const_eval)]
     const RES: u32 = ConstantEmulator::run_loop(riscv_asm! {
         main:
@@ -148,23 +154,11 @@ fn main() {
         li a0, 1;
         Loop:
         beq s2, zero, End;
-        mv a1, s2;
-        call mul;
+        mul a0, a0, s2;
         addi s2, s2, -1;
         j Loop;
         End:
         stop;
-        mul:
-        mv t0, a0;
-        mv t1, a1;
-        li a0, 0;
-        mul_loop:
-        beq t1, zero, mul_end;
-        add a0, a0, t0;
-        addi t1, t1, -1;
-        j mul_loop;
-        mul_end:
-        ret;
     });
 
 
@@ -30,7 +30,7 @@ const _: () = assert!(range_mask(7, 7) == 0b10000000);
 
 
 pub fn range(range: RangeInclusive<u8>) -> u32 {
-    ((!0_u32) >> (32 - *range.end()) + *range.start()) << range.start()
+    ((!0_u32) >> ((32 - *range.end()) + *range.start())) << range.start()
 }
 
 #[cfg(test)]
@@ -1,11 +1,21 @@
-use crate::arch::{Address, PC_DEFAULT_ADDRESS};
-use crate::instruction_type::{IInstruction, SInstruction};
-use crate::mask::{BYTE_MASK, HALF_WORD_MASK, WORD_MASK};
-use crate::register::Registers;
+use crate::arch::{Address, IALIGN, PC_DEFAULT_ADDRESS, Privilege, Xlen};
+use crate::decode::{DecodeCache, Decoded};
+use crate::device::Device;
+use crate::device::clint::{CLINT_BASE, CLINT_SIZE, Clint};
+use crate::exception::Exception;
+use crate::instruction_type::Instruction;
+use crate::mask::{BYTE_MASK, HALF_WORD_MASK};
+use crate::mmu::{
+    Access, PAGE_SHIFT, PTE_A, PTE_D, PTE_R, PTE_V, PTE_W, PTE_X, Tlb, TlbEntry, Translation,
+    leaf_page, page_offset, pte_address, pte_target,
+};
+use crate::register::Register;
 
 const CODE_DEFAULT_OFFSET: usize = PC_DEFAULT_ADDRESS as usize;
 const STACK_BOTTOM_DEFAULT_OFFSET: Address = 0xF0000;
 const STACK_DEFAULT_SIZE: usize = 0x100;
+// accesses at or above this byte address fault instead of growing the memory
+const MEMORY_DEFAULT_SIZE: Address = 1 << 24;
 
 pub trait RandomAccess {
     type Output;
@@ -57,170 +67,774 @@ impl RandomAccess for MemorySegments {
     }
 }
 
-#[derive(Default)]
+/// The load or store the last instruction made, at the physical address.
+/// Values are zero-extended, a double takes all 64 bits.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MemoryAccess {
+    Load {
+        address: Address,
+        size: Address,
+        value: u64,
+    },
+    /// `old` is `None` for device registers, which can't be read back without
+    /// side effects
+    Store {
+        address: Address,
+        size: Address,
+        old: Option<u64>,
+        new: u64,
+    },
+    /// an AMO, a load of `old` and a store of `new` in one
+    Amo {
+        address: Address,
+        size: Address,
+        old: u64,
+        new: u64,
+    },
+}
+
+/// A device and the address range it answers to.
+struct MappedDevice {
+    base: Address,
+    size: Address,
+    device: Box<dyn Device>,
+}
+
+impl MappedDevice {
+    const fn contains(&self, byte_address: Address) -> bool {
+        byte_address >= self.base && byte_address - self.base < self.size
+    }
+
+    const fn overlaps(&self, base: Address, size: Address) -> bool {
+        base < self.base.saturating_add(self.size) && self.base < base.saturating_add(size)
+    }
+}
+
 pub struct MemoryWrapper {
     segments: MemorySegments,
+    size: Address,
+    // take priority over the RAM they overlap
+    devices: Vec<MappedDevice>,
+    access: Option<MemoryAccess>,
+    decoded: DecodeCache,
+    // bumped whenever a decoded word is dropped
+    code_generation: u64,
+    // the address `lr` reserved, until it's stored to or an `sc` runs
+    reservation: Option<Address>,
+    translation: Translation,
+    tlb: Tlb,
+    // the entries page walks set A or D in and their old value, while the
+    // undo log wants them
+    pte_log: Option<Vec<(Address, u32)>>,
 }
 
+impl Default for MemoryWrapper {
+    fn default() -> Self {
+        let mut memory = Self {
+            segments: MemorySegments::default(),
+            size: MEMORY_DEFAULT_SIZE,
+            devices: Vec::new(),
+            access: None,
+            decoded: DecodeCache::default(),
+            code_generation: 0,
+            reservation: None,
+            translation: Translation::default(),
+            tlb: Tlb::default(),
+            pte_log: None,
+        };
+        memory.map_device(CLINT_BASE, CLINT_SIZE, Box::new(Clint::default()));
+        memory
+    }
+}
 
 impl MemoryWrapper {
     pub fn test_get_memory(&mut self) -> &mut Vec<u32> {
+        self.forget_code();
         &mut self.segments.data
     }
     pub fn append(&mut self, data: &[u32]) {
         self.segments.data.extend_from_slice(data);
     }
-    pub fn read_byte(&self, byte_address: &Address) -> u8 {
-        self.segments
-            .read(byte_address)
-            .copied()
-            .map(|v| {
-                ((v >> match byte_address & 0b11 {
-                    0b00 => 0,
-                    0b01 => 8,
-                    0b10 => 16,
-                    0b11 => 24,
-                    _ => unreachable!(),
-                }) & BYTE_MASK) as u8
-            })
-            .unwrap_or(0)
-    }
-    pub fn read_word(&self, byte_address: &Address) -> u32 {
-        self.segments.read(byte_address).copied().unwrap_or(0)
-    }
-    pub fn read_halfword(&self, byte_address: &Address) -> u16 {
-        self.segments
-            .read(byte_address)
-            .copied()
-            .map(|v| {
-                ((v >> match byte_address & 1 {
-                    0 => 0,
-                    1 => 16,
-                    _ => unreachable!(),
-                }) & HALF_WORD_MASK) as u16
-            })
-            .unwrap_or(0)
+    /// whether the range is backed by RAM
+    pub const fn contains(&self, byte_address: Address, len: Address) -> bool {
+        byte_address < self.size && len <= self.size - byte_address
     }
-    pub fn write_word(&mut self, byte_address: &Address, value: u32) {
-        self.segments.write(byte_address, &value);
+
+    /// Maps `device` at `base..base + size`. A device already mapped at exactly
+    /// that range is replaced; a partial overlap with another device panics.
+    pub fn map_device(&mut self, base: Address, size: Address, device: Box<dyn Device>) {
+        if let Some(mapped) = self
+            .devices
+            .iter_mut()
+            .find(|mapped| mapped.base == base && mapped.size == size)
+        {
+            mapped.device = device;
+            return;
+        }
+        assert!(
+            !self
+                .devices
+                .iter()
+                .any(|mapped| mapped.overlaps(base, size)),
+            "device at {:#010x}..{:#010x} overlaps another device",
+            base,
+            base.wrapping_add(size)
+        );
+        self.devices.push(MappedDevice { base, size, device });
+        // RAM words decoded before now sit under the device
+        self.forget_code();
     }
-    pub fn write_halfword(&mut self, byte_address: &Address, halfword: u16) {
-        self.segments.get_mut(byte_address).map(|v| {
-            *v = *v | {
-                if byte_address & 1 == 0 {
-                    halfword as u32
-                } else {
-                    (halfword as u32) << 16
+
+    /// the device of type `T` mapped at `base`, if that's what is mapped there
+    pub fn device_at<T: Device + 'static>(&mut self, base: Address) -> Option<&mut T> {
+        self.devices
+            .iter_mut()
+            .find(|mapped| mapped.base == base)
+            .and_then(|mapped| mapped.device.as_any_mut())
+            .and_then(|device| device.downcast_mut())
+    }
+
+    /// the device mapped at `byte_address` and the offset into it
+    fn device(&mut self, byte_address: Address) -> Option<(&mut Box<dyn Device>, Address)> {
+        self.devices
+            .iter_mut()
+            .find(|mapped| mapped.contains(byte_address))
+            .map(|mapped| (&mut mapped.device, byte_address - mapped.base))
+    }
+
+    pub fn tick_devices(&mut self) {
+        self.devices
+            .iter_mut()
+            .for_each(|mapped| mapped.device.tick());
+    }
+
+    /// `tick_devices` `ticks` times over
+    pub fn advance_devices(&mut self, ticks: u64) {
+        self.devices
+            .iter_mut()
+            .for_each(|mapped| mapped.device.advance(ticks));
+    }
+
+    /// `(base, size, state)` of every device, for snapshots
+    pub fn save_devices(&self) -> Vec<(Address, Address, Vec<u8>)> {
+        self.devices
+            .iter()
+            .map(|mapped| (mapped.base, mapped.size, mapped.device.save()))
+            .collect()
+    }
+
+    /// Restores what `save_devices` returned. The same devices must be mapped,
+    /// otherwise the base of the first one that doesn't match or whose state
+    /// doesn't parse is returned, and every device is left as it was.
+    pub fn restore_devices(
+        &mut self,
+        states: &[(Address, Address, Vec<u8>)],
+    ) -> Result<(), Address> {
+        if states.len() != self.devices.len() {
+            return Err(states.first().map_or(0, |(base, ..)| *base));
+        }
+        let indices = states
+            .iter()
+            .map(|(base, size, _)| {
+                self.devices
+                    .iter()
+                    .position(|mapped| mapped.base == *base && mapped.size == *size)
+                    .ok_or(*base)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        // a device can only tell whether a state parses by restoring it, so
+        // the ones restored before a bad state are put back
+        let backup = self.save_devices();
+        for ((base, _, state), index) in states.iter().zip(indices) {
+            if self.devices[index].device.restore(state).is_none() {
+                for (mapped, (.., old)) in self.devices.iter_mut().zip(&backup) {
+                    mapped.device.restore(old);
                 }
+                return Err(*base);
             }
+        }
+        Ok(())
+    }
+
+    /// the RAM contents that have been written so far
+    pub fn ram(&self) -> &[u32] {
+        &self.segments.data
+    }
+
+    pub fn restore_ram(&mut self, data: &[u32]) {
+        self.segments.data.clear();
+        self.segments.data.extend_from_slice(data);
+        self.forget_code();
+        self.reservation = None;
+        self.tlb.flush();
+    }
+
+    /// the mip bits driven by all devices
+    pub fn pending_interrupts(&self) -> Register {
+        self.devices
+            .iter()
+            .fold(0, |pending, mapped| pending | mapped.device.pending())
+    }
+
+    /// the address `lr` reserved, if the reservation still holds
+    pub fn reservation(&self) -> Option<Address> {
+        self.reservation
+    }
+
+    /// puts back a reservation `reservation` returned, for the undo log
+    pub fn restore_reservation(&mut self, reservation: Option<Address>) {
+        self.reservation = reservation;
+    }
+
+    /// Starts noting the page table entries walks update, until
+    /// `take_pte_log`.
+    pub fn log_pte_writes(&mut self) {
+        self.pte_log = Some(Vec::new());
+    }
+
+    /// `(address, old)` of every entry updated since `log_pte_writes`,
+    /// oldest first
+    pub fn take_pte_log(&mut self) -> Vec<(Address, u32)> {
+        self.pte_log.take().unwrap_or_default()
+    }
+
+    /// the access made by the last load or store, cleared by reading it
+    pub fn take_access(&mut self) -> Option<MemoryAccess> {
+        self.access.take()
+    }
+
+    /// Reads RAM without going through a device, so without side effects.
+    /// `None` where a device is mapped or outside RAM.
+    pub fn peek(&self, byte_address: Address, len: Address) -> Option<u32> {
+        if !self.contains(byte_address, len)
+            || self
+                .devices
+                .iter()
+                .any(|mapped| mapped.contains(byte_address))
+        {
+            return None;
+        }
+        let word = self.segments.read(&byte_address).copied().unwrap_or(0);
+        let shift = (byte_address & 0b11) << 3;
+        Some(match len {
+            1 => (word >> shift) & BYTE_MASK,
+            2 => (word >> shift) & HALF_WORD_MASK,
+            _ => word,
+        })
+    }
+
+    fn load_access(&mut self, address: Address, size: Address, value: u64) {
+        self.access = Some(MemoryAccess::Load {
+            address,
+            size,
+            value,
         });
     }
-    pub fn write_byte(&mut self, byte_address: &Address, value: u8) {
-        self.segments.get_mut(byte_address).map(|v| {
-            *v = *v | {
-                match byte_address & 0b11 {
-                    0b00 => value as u32,
-                    0b01 => (value as u32) << 8,
-                    0b10 => (value as u32) << 16,
-                    0b11 => (value as u32) << 24,
-                    _ => unreachable!(),
+
+    fn store_access(&mut self, address: Address, size: Address, new: u64) {
+        let old = match size {
+            8 => self
+                .peek(address, 4)
+                .zip(self.peek(address.wrapping_add(4), 4))
+                .map(|(low, high)| low as u64 | (high as u64) << 32),
+            _ => self.peek(address, size).map(u64::from),
+        };
+        self.access = Some(MemoryAccess::Store {
+            address,
+            size,
+            old,
+            new,
+        });
+    }
+
+    /// How the next accesses are translated, the TLB is flushed when the
+    /// page table changes.
+    pub fn set_translation(&mut self, translation: Translation) {
+        if translation.root != self.translation.root {
+            self.tlb.flush();
+        }
+        self.translation = translation;
+    }
+
+    /// Drops every cached translation, for `sfence.vma`.
+    pub fn flush_translations(&mut self) {
+        self.tlb.flush();
+    }
+
+    /// The physical address `address` is accessed at. Under Sv32 the page
+    /// table is walked on a TLB miss, setting the A bit of the leaf and the
+    /// D bit for stores.
+    fn translate(&mut self, address: Address, access: Access) -> Result<Address, Exception> {
+        let Some(privilege) = self.translation.privilege(access) else {
+            return Ok(address);
+        };
+        // a store through an entry that isn't dirty yet walks again to set D
+        if let Some(entry) = self.tlb.get(address)
+            && self.translation.permits(entry.pte, access, privilege)
+            && (access != Access::Store || entry.pte & PTE_D != 0)
+        {
+            return Ok(entry.page | page_offset(address));
+        }
+        let entry = self.walk(address, access, privilege)?;
+        self.tlb.insert(address, entry);
+        Ok(entry.page | page_offset(address))
+    }
+
+    // the two-level Sv32 walk
+    fn walk(
+        &mut self,
+        address: Address,
+        access: Access,
+        privilege: Privilege,
+    ) -> Result<TlbEntry, Exception> {
+        let page_fault = access.page_fault(address);
+        let mut table = self.translation.root.ok_or(page_fault)?;
+        for level in [1, 0] {
+            let entry = pte_address(table, address, level);
+            let pte = self.read_word(&entry).ok_or(access.access_fault(address))?;
+            // W without R is reserved
+            if pte & PTE_V == 0 || (pte & PTE_R == 0 && pte & PTE_W != 0) {
+                return Err(page_fault);
+            }
+            if pte & (PTE_R | PTE_X) == 0 {
+                table = pte_target(pte);
+                continue;
+            }
+            if !self.translation.permits(pte, access, privilege) {
+                return Err(page_fault);
+            }
+            let page = leaf_page(pte, address, level).ok_or(page_fault)?;
+            let dirty = if access == Access::Store { PTE_D } else { 0 };
+            let updated = pte | PTE_A | dirty;
+            if updated != pte {
+                self.write_word(&entry, updated)
+                    .ok_or(access.access_fault(address))?;
+                if let Some(log) = self.pte_log.as_mut() {
+                    log.push((entry, pte));
                 }
             }
-        });
+            return Ok(TlbEntry { pte: updated, page });
+        }
+        // a pointer at level 0
+        Err(page_fault)
     }
 
-    fn lb(&self, registers: &mut Registers, i: IInstruction) {
-        let base = registers.get(i.rs1()) as u32;
-        let offset = i.imm();
-        let data = self.read_byte(&((base as i32 + offset) as u32));
-        *registers.get_mut(i.rd()) = (data as u32) << 24 >> 24;
+    /// The physical address the debugger sees at `address`, translated at
+    /// the privilege the hart runs at (MPRV aside). The page table is only
+    /// peeked at: the TLB and the A and D bits are left alone.
+    pub fn debug_translate(&self, address: Address, access: Access) -> Option<Address> {
+        let translation = Translation {
+            data: self.translation.fetch,
+            ..self.translation
+        };
+        let Some(privilege) = translation.privilege(access) else {
+            return Some(address);
+        };
+        let mut table = translation.root?;
+        for level in [1, 0] {
+            let pte = self.peek(pte_address(table, address, level), 4)?;
+            if pte & PTE_V == 0 || (pte & PTE_R == 0 && pte & PTE_W != 0) {
+                return None;
+            }
+            if pte & (PTE_R | PTE_X) == 0 {
+                table = pte_target(pte);
+                continue;
+            }
+            if !translation.permits(pte, access, privilege) {
+                return None;
+            }
+            return Some(leaf_page(pte, address, level)? | page_offset(address));
+        }
+        None
     }
 
-    fn lbu(&self, registers: &mut Registers, i: IInstruction) {
-        let base = registers.get(i.rs1()) as u32;
-        let offset = i.imm();
-        let data = self.read_byte(&((base as i32 + offset) as u32));
-        *registers.get_mut(i.rd()) = data as u32;
+    /// Whether all of `byte_address..byte_address + len` is RAM once
+    /// translated for `access`, checked a page at a time so a syscall can
+    /// fail before it touches any of it.
+    pub fn accessible(&mut self, byte_address: Address, len: Address, access: Access) -> bool {
+        let Some(end) = byte_address.checked_add(len) else {
+            return false;
+        };
+        let mut address = byte_address;
+        while address < end {
+            let chunk = ((1 << PAGE_SHIFT) - page_offset(address)).min(end - address);
+            match self.translate(address, access) {
+                Ok(physical) if self.contains(physical, chunk) => address += chunk,
+                _ => return false,
+            }
+        }
+        true
     }
 
-    fn lh(&self, registers: &mut Registers, i: IInstruction) {
-        let base = registers.get(i.rs1()) as u32;
-        let offset = i.imm();
-        let data = self.read_halfword(&((base as i32 + offset) as u32));
-        *registers.get_mut(i.rd()) = (data as u32) << 16 >> 16;
+    /// The instruction at `pc`, a compressed one in the low half. A 32-bit
+    /// instruction may straddle two words, or two pages.
+    pub fn fetch(&mut self, pc: Address) -> Result<u32, Exception> {
+        if !pc.is_multiple_of(IALIGN) {
+            return Err(Exception::InstructionAddressMisaligned(pc));
+        }
+        let physical = self.translate(pc, Access::Fetch)?;
+        let low = self
+            .read_halfword(&physical)
+            .ok_or(Exception::InstructionAccessFault(pc))? as u32;
+        if low == 0 || Instruction(low).is_compressed() {
+            return Ok(low);
+        }
+        let high = pc.wrapping_add(2);
+        let physical = self.translate(high, Access::Fetch)?;
+        let high = self
+            .read_halfword(&physical)
+            .ok_or(Exception::InstructionAccessFault(high))? as u32;
+        Ok(low | high << 16)
     }
 
-    fn lhu(&self, registers: &mut Registers, i: IInstruction) {
-        let base = registers.get(i.rs1()) as u32;
-        let offset = i.imm();
-        let data = self.read_halfword(&((base as i32 + offset) as u32));
-        *registers.get_mut(i.rd()) = data as u32;
+    /// Changes whenever RAM that was fetched as code is written, so anything
+    /// built from `fetch_decoded` results knows when to throw them away.
+    pub fn code_generation(&self) -> u64 {
+        self.code_generation
     }
 
-    fn lw(&self, registers: &mut Registers, i: IInstruction) {
-        let base = registers.get(i.rs1()) as u32;
-        let offset = i.imm();
-        *registers.get_mut(i.rd()) = self.read_word(&((base as i32 + offset) as u32));
+    // a RAM store, drops what was decoded or reserved at the word
+    fn written(&mut self, byte_address: Address) {
+        // the whole doubleword, so a `lr.d` reservation goes as well
+        if self
+            .reservation
+            .is_some_and(|reserved| reserved & !0b111 == byte_address & !0b111)
+        {
+            self.reservation = None;
+        }
+        if self.decoded.invalidate(byte_address) {
+            self.code_generation += 1;
+        }
     }
 
-    fn sb(&mut self, registers: &mut Registers, s: SInstruction) {
-        let base = registers.get(s.rs1()) as u32;
-        let offset = s.imm();
-        let target = base as i32 + offset;
-        assert!(target >= 0);
-        self.write_byte(&(target as u32), (registers.get(s.rs2()) & BYTE_MASK) as u8);
+    /// Drops every decoded instruction, for `fence.i`. RAM stores already
+    /// drop the words they overwrite, so guests never see stale code either
+    /// way.
+    pub fn forget_code(&mut self) {
+        self.decoded.clear();
+        self.code_generation += 1;
     }
 
-    fn sw(&mut self, registers: &mut Registers, s: SInstruction) {
-        let base = registers.get(s.rs1()) as u32;
-        let offset = s.imm();
-        let target = base as i32 + offset;
-        assert!(target >= 0);
+    /// `fetch` and decode for a hart with `registers` integer registers. RAM
+    /// instructions are decoded once and cached until written, so the cache
+    /// has to be forgotten when XLEN or the register count changes.
+    pub fn fetch_decoded(
+        &mut self,
+        pc: Address,
+        xlen: Xlen,
+        registers: u8,
+    ) -> Result<Decoded, Exception> {
+        if !pc.is_multiple_of(IALIGN) {
+            return Err(Exception::InstructionAddressMisaligned(pc));
+        }
+        // cached by physical address, as RAM stores invalidate it
+        let physical = self.translate(pc, Access::Fetch)?;
+        if let Some(decoded) = self.decoded.get(physical) {
+            return Ok(decoded);
+        }
+        let decoded = Decoded::new(self.fetch(pc)?, xlen).limit_registers(registers);
+        // a device can answer differently next time, and the high half of
+        // an instruction straddling two pages can be mapped elsewhere
+        let straddles = self.translation.privilege(Access::Fetch).is_some()
+            && page_offset(pc + decoded.len as Address - 1) < page_offset(pc);
+        if self.device(physical).is_none() && !straddles {
+            self.decoded.insert(physical, decoded);
+        }
+        Ok(decoded)
+    }
 
-        // println!("store {} to {}", registers.get(s.rs2()) & WORD_MASK, target);
-        self.write_word(&(target as u32), registers.get(s.rs2()) & WORD_MASK);
+    /// `lb`, `lh`, `lw`, `ld` and the unsigned ones, the value extended to 64
+    /// bits
+    pub fn load(
+        &mut self,
+        address: Address,
+        size: Address,
+        signed: bool,
+    ) -> Result<u64, Exception> {
+        if size == 8 {
+            return self.load_double(address);
+        }
+        // sizes are powers of two, and a mask is much cheaper than `%`
+        if address & (size - 1) != 0 {
+            return Err(Exception::LoadAddressMisaligned(address));
+        }
+        let physical = self.translate(address, Access::Load)?;
+        let data = match size {
+            1 => self.read_byte(&physical).map(|byte| byte as u32),
+            2 => self
+                .read_halfword(&physical)
+                .map(|halfword| halfword as u32),
+            _ => self.read_word(&physical),
+        }
+        .ok_or(Exception::LoadAccessFault(address))?;
+        self.load_access(physical, size, data as u64);
+        Ok(match (size, signed) {
+            (1, true) => data as i8 as u64,
+            (2, true) => data as i16 as u64,
+            (4, true) => data as i32 as u64,
+            _ => data as u64,
+        })
     }
 
-    fn sh(&mut self, registers: &mut Registers, s: SInstruction) {
-        let base = registers.get(s.rs1()) as u32;
-        let offset = s.imm();
-        let target = base as i32 + offset;
-        assert!(target >= 0);
-        self.write_halfword(
-            &(target as u32),
-            (registers.get(s.rs2()) & HALF_WORD_MASK) as u16,
-        );
+    /// `sb`, `sh`, `sw` and `sd`, only the low `size` bytes of `value` are
+    /// stored
+    pub fn store(&mut self, address: Address, size: Address, value: u64) -> Result<(), Exception> {
+        if size == 8 {
+            return self.store_double(address, value);
+        }
+        if address & (size - 1) != 0 {
+            return Err(Exception::StoreAddressMisaligned(address));
+        }
+        let physical = self.translate(address, Access::Store)?;
+        let value = match size {
+            1 => value as u32 & BYTE_MASK,
+            2 => value as u32 & HALF_WORD_MASK,
+            _ => value as u32,
+        };
+        self.store_access(physical, size, value as u64);
+        match size {
+            1 => self.write_byte(&physical, value as u8),
+            2 => self.write_halfword(&physical, value as u16),
+            _ => self.write_word(&physical, value),
+        }
+        .ok_or(Exception::StoreAccessFault(address))
     }
 
-    pub fn load(&self, registers: &mut Registers, i: IInstruction) {
-        match i.funct3() {
-            0b000 => self.lb(registers, i),
-            0b001 => self.lh(registers, i),
-            0b010 => self.lw(registers, i),
-            0b100 => self.lbu(registers, i),
-            0b101 => self.lhu(registers, i),
-            _ => {
-                eprintln!(
-                    "no such instruction {:0X} with funct3 {}",
-                    i.0.0,
-                    i.funct3()
-                );
-            }
+    /// Whether `access` is an aligned untranslated one to RAM rather than a
+    /// device, the only kind `load_ram` and `store_ram` make.
+    pub fn plain_ram(&self, address: Address, size: Address, access: Access) -> bool {
+        address & (size - 1) == 0
+            && self.contains(address, size)
+            && self.translation.privilege(access).is_none()
+            && !self
+                .devices
+                .iter()
+                .any(|mapped| mapped.overlaps(address, size))
+    }
+
+    /// `load` for the block fast path, `plain_ram` must hold. Nothing is
+    /// recorded for `take_access`.
+    pub fn load_ram(&self, address: Address, size: Address, signed: bool) -> u64 {
+        let word = |address: Address| self.segments.read(&address).copied().unwrap_or(0);
+        let shift = (address & 0b11) << 3;
+        match (size, signed) {
+            (1, true) => (word(address) >> shift) as i8 as u64,
+            (1, false) => (word(address) >> shift) as u8 as u64,
+            (2, true) => (word(address) >> shift) as i16 as u64,
+            (2, false) => (word(address) >> shift) as u16 as u64,
+            (4, true) => word(address) as i32 as u64,
+            (4, false) => word(address) as u64,
+            _ => word(address) as u64 | (word(address + 4) as u64) << 32,
         }
     }
 
-    pub fn store(&mut self, registers: &mut Registers, s: SInstruction) {
-        match s.funct3() {
-            0b000 => self.sb(registers, s),
-            0b001 => self.sh(registers, s),
-            0b010 => self.sw(registers, s),
-            _ => {
-                eprintln!(
-                    "no such instruction {:0X} with funct3 {}",
-                    s.0.0,
-                    s.funct3()
-                );
-            }
+    /// the `store` side of `load_ram`
+    pub fn store_ram(&mut self, address: Address, size: Address, value: u64) {
+        match size {
+            1 => self.write_byte(&address, value as u8),
+            2 => self.write_halfword(&address, value as u16),
+            4 => self.write_word(&address, value as u32),
+            _ => self
+                .write_word(&address, value as u32)
+                .and(self.write_word(&(address + 4), (value >> 32) as u32)),
+        };
+    }
+
+    /// `fld` and `ld`, two word reads
+    pub fn load_double(&mut self, address: Address) -> Result<u64, Exception> {
+        if !address.is_multiple_of(8) {
+            return Err(Exception::LoadAddressMisaligned(address));
+        }
+        // aligned, so both words are in the same page
+        let physical = self.translate(address, Access::Load)?;
+        let low = self
+            .read_word(&physical)
+            .ok_or(Exception::LoadAccessFault(address))?;
+        let high = self
+            .read_word(&physical.wrapping_add(4))
+            .ok_or(Exception::LoadAccessFault(address.wrapping_add(4)))?;
+        let value = low as u64 | (high as u64) << 32;
+        self.load_access(physical, 8, value);
+        Ok(value)
+    }
+
+    /// `fsd` and `sd`, two word writes
+    pub fn store_double(&mut self, address: Address, value: u64) -> Result<(), Exception> {
+        if !address.is_multiple_of(8) {
+            return Err(Exception::StoreAddressMisaligned(address));
+        }
+        let physical = self.translate(address, Access::Store)?;
+        self.store_access(physical, 8, value);
+        self.write_word(&physical, value as u32)
+            .ok_or(Exception::StoreAccessFault(address))?;
+        self.write_word(&physical.wrapping_add(4), (value >> 32) as u32)
+            .ok_or(Exception::StoreAccessFault(address.wrapping_add(4)))
+    }
+
+    /// `lr.w` and `lr.d`, reserves the address for the next `sc`. A word is
+    /// sign-extended.
+    pub fn load_reserved(&mut self, address: Address, size: Address) -> Result<u64, Exception> {
+        let value = self.load(address, size, true)?;
+        // the physical address, as stores through another mapping drop it
+        self.reservation = self.access.map(|access| match access {
+            MemoryAccess::Load { address, .. }
+            | MemoryAccess::Store { address, .. }
+            | MemoryAccess::Amo { address, .. } => address,
+        });
+        Ok(value)
+    }
+
+    /// `sc.w` and `sc.d`, `true` if the address was still reserved and has
+    /// been stored. The reservation is gone either way.
+    pub fn store_conditional(
+        &mut self,
+        address: Address,
+        size: Address,
+        value: u64,
+    ) -> Result<bool, Exception> {
+        if address & (size - 1) != 0 {
+            return Err(Exception::StoreAddressMisaligned(address));
+        }
+        let physical = self.translate(address, Access::Store)?;
+        if self.reservation.take() != Some(physical) {
+            return Ok(false);
+        }
+        self.store(address, size, value)?;
+        Ok(true)
+    }
+
+    pub fn clear_reservation(&mut self) {
+        self.reservation = None;
+    }
+
+    /// `amo*.w` and `amo*.d`: stores `op` of the value at `address` and
+    /// returns the value, a word zero-extended. Faults are store faults,
+    /// loading included.
+    pub fn amo(
+        &mut self,
+        address: Address,
+        size: Address,
+        op: impl FnOnce(u64) -> u64,
+    ) -> Result<u64, Exception> {
+        if address & (size - 1) != 0 {
+            return Err(Exception::StoreAddressMisaligned(address));
+        }
+        let physical = self.translate(address, Access::Store)?;
+        let low = self
+            .read_word(&physical)
+            .ok_or(Exception::StoreAccessFault(address))? as u64;
+        let old = if size == 8 {
+            let high = self
+                .read_word(&physical.wrapping_add(4))
+                .ok_or(Exception::StoreAccessFault(address.wrapping_add(4)))?
+                as u64;
+            low | high << 32
+        } else {
+            low
+        };
+        let new = op(old) & u64::MAX >> (64 - size * 8);
+        self.store(address, size, new)?;
+        self.access = Some(MemoryAccess::Amo {
+            address: physical,
+            size,
+            old,
+            new,
+        });
+        Ok(old)
+    }
+
+    // the accessors below return `None` when nothing is mapped at the address
+
+    pub fn read_byte(&mut self, byte_address: &Address) -> Option<u8> {
+        if let Some((device, offset)) = self.device(*byte_address) {
+            return device.read_byte(offset);
+        }
+        if !self.contains(*byte_address, 1) {
+            return None;
+        }
+        let word = self.segments.read(byte_address).copied().unwrap_or(0);
+        Some(((word >> ((byte_address & 0b11) << 3)) & BYTE_MASK) as u8)
+    }
+    pub fn read_word(&mut self, byte_address: &Address) -> Option<u32> {
+        if let Some((device, offset)) = self.device(*byte_address) {
+            return device.read_word(offset);
+        }
+        if !self.contains(*byte_address, 4) {
+            return None;
+        }
+        Some(self.segments.read(byte_address).copied().unwrap_or(0))
+    }
+    pub fn read_halfword(&mut self, byte_address: &Address) -> Option<u16> {
+        if let Some((device, offset)) = self.device(*byte_address) {
+            return device.read_halfword(offset);
+        }
+        if !self.contains(*byte_address, 2) {
+            return None;
+        }
+        let word = self.segments.read(byte_address).copied().unwrap_or(0);
+        Some(((word >> ((byte_address & 0b10) << 3)) & HALF_WORD_MASK) as u16)
+    }
+    pub fn write_word(&mut self, byte_address: &Address, value: u32) -> Option<()> {
+        if let Some((device, offset)) = self.device(*byte_address) {
+            return device.write_word(offset, value);
+        }
+        if !self.contains(*byte_address, 4) {
+            return None;
+        }
+        self.segments.write(byte_address, &value);
+        self.written(*byte_address);
+        Some(())
+    }
+    pub fn write_halfword(&mut self, byte_address: &Address, halfword: u16) -> Option<()> {
+        if let Some((device, offset)) = self.device(*byte_address) {
+            return device.write_halfword(offset, halfword);
+        }
+        if !self.contains(*byte_address, 2) {
+            return None;
+        }
+        let v = self.segments.get_mut(byte_address)?;
+        let shift = (byte_address & 0b10) << 3;
+        *v = (*v & !(HALF_WORD_MASK << shift)) | (halfword as u32) << shift;
+        self.written(*byte_address);
+        Some(())
+    }
+    pub fn write_byte(&mut self, byte_address: &Address, value: u8) -> Option<()> {
+        if let Some((device, offset)) = self.device(*byte_address) {
+            return device.write_byte(offset, value);
+        }
+        if !self.contains(*byte_address, 1) {
+            return None;
+        }
+        let v = self.segments.get_mut(byte_address)?;
+        let shift = (byte_address & 0b11) << 3;
+        *v = (*v & !(BYTE_MASK << shift)) | (value as u32) << shift;
+        self.written(*byte_address);
+        Some(())
+    }
+
+    /// copies guest memory into `buffer`, for the host side of syscalls. The
+    /// addresses are virtual, translated as the hart's loads are.
+    pub fn read_bytes(
+        &mut self,
+        byte_address: Address,
+        buffer: &mut [u8],
+    ) -> Result<(), Exception> {
+        for (address, byte) in (byte_address..).zip(buffer.iter_mut()) {
+            let physical = self.translate(address, Access::Load)?;
+            *byte = self
+                .read_byte(&physical)
+                .ok_or(Exception::LoadAccessFault(address))?;
+        }
+        Ok(())
+    }
+
+    /// the store side of `read_bytes`
+    pub fn write_bytes(&mut self, byte_address: Address, data: &[u8]) -> Result<(), Exception> {
+        for (address, byte) in (byte_address..).zip(data) {
+            let physical = self.translate(address, Access::Store)?;
+            self.write_byte(&physical, *byte)
+                .ok_or(Exception::StoreAccessFault(address))?;
         }
+        Ok(())
     }
 }
@@ -0,0 +1,171 @@
+use crate::arch::{Address, Privilege};
+use crate::exception::Exception;
+use crate::register::Register;
+use std::collections::HashMap;
+
+pub const PAGE_SHIFT: u32 = 12;
+const PAGE_OFFSET: Address = (1 << PAGE_SHIFT) - 1;
+// each Sv32 level translates 10 bits of the virtual page number
+const VPN_BITS: u32 = 10;
+const VPN_MASK: Address = (1 << VPN_BITS) - 1;
+const PTE_SIZE: Address = 4;
+
+// Sv32 page-table entry bits, the PPN starts at bit 10
+pub const PTE_V: u32 = 1 << 0;
+pub const PTE_R: u32 = 1 << 1;
+pub const PTE_W: u32 = 1 << 2;
+pub const PTE_X: u32 = 1 << 3;
+pub const PTE_U: u32 = 1 << 4;
+pub const PTE_A: u32 = 1 << 6;
+pub const PTE_D: u32 = 1 << 7;
+const PTE_PPN_SHIFT: u32 = 10;
+
+// satp on RV32: MODE, ASID and the PPN of the root page table
+pub const SATP_MODE_SV32: Register = 1 << 31;
+pub const SATP_PPN: Register = 0x3F_FFFF;
+
+/// What an address is translated for.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Access {
+    Fetch,
+    Load,
+    /// stores and AMOs
+    Store,
+}
+
+impl Access {
+    pub const fn page_fault(self, address: Address) -> Exception {
+        match self {
+            Access::Fetch => Exception::InstructionPageFault(address),
+            Access::Load => Exception::LoadPageFault(address),
+            Access::Store => Exception::StorePageFault(address),
+        }
+    }
+
+    pub const fn access_fault(self, address: Address) -> Exception {
+        match self {
+            Access::Fetch => Exception::InstructionAccessFault(address),
+            Access::Load => Exception::LoadAccessFault(address),
+            Access::Store => Exception::StoreAccessFault(address),
+        }
+    }
+}
+
+/// How addresses are translated, from satp, mstatus and the privilege level.
+/// The emulator hands it to the memory before each instruction.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Translation {
+    /// the root page table, `None` in Bare mode
+    pub root: Option<Address>,
+    /// the privilege instructions are fetched at
+    pub fetch: Privilege,
+    /// the privilege loads and stores are made at, MPP under mstatus.MPRV
+    pub data: Privilege,
+    /// mstatus.SUM, S-mode may load and store to user pages
+    pub sum: bool,
+    /// mstatus.MXR, pages that are only executable can be loaded from
+    pub mxr: bool,
+}
+
+impl Translation {
+    /// the privilege `access` is translated at, `None` if it isn't
+    pub const fn privilege(&self, access: Access) -> Option<Privilege> {
+        let privilege = match access {
+            Access::Fetch => self.fetch,
+            Access::Load | Access::Store => self.data,
+        };
+        match (self.root, privilege) {
+            (Some(_), Privilege::User | Privilege::Supervisor) => Some(privilege),
+            _ => None,
+        }
+    }
+
+    /// whether any access is translated
+    pub const fn active(&self) -> bool {
+        self.privilege(Access::Fetch).is_some() || self.privilege(Access::Load).is_some()
+    }
+
+    /// whether the leaf `pte` lets `access` through at `privilege`, the A
+    /// and D bits aside
+    pub const fn permits(&self, pte: u32, access: Access, privilege: Privilege) -> bool {
+        let user_page = pte & PTE_U != 0;
+        let privileged = match privilege {
+            Privilege::User => user_page,
+            // user pages are never executed in S-mode, and only loaded and
+            // stored with SUM set
+            Privilege::Supervisor => !user_page || (self.sum && !matches!(access, Access::Fetch)),
+            Privilege::Machine => true,
+        };
+        privileged
+            && match access {
+                Access::Fetch => pte & PTE_X != 0,
+                Access::Load => pte & PTE_R != 0 || (self.mxr && pte & PTE_X != 0),
+                Access::Store => pte & PTE_W != 0,
+            }
+    }
+}
+
+/// A leaf the walk found, with the A and D bits it set.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TlbEntry {
+    pub pte: u32,
+    /// the physical 4 KiB page, within a megapage for a level 1 leaf
+    pub page: Address,
+}
+
+/// Translations by virtual page number, kept until `sfence.vma` or a
+/// change of page table.
+#[derive(Debug, Default)]
+pub struct Tlb {
+    entries: HashMap<Address, TlbEntry>,
+}
+
+impl Tlb {
+    // a full TLB is simply emptied
+    const CAPACITY: usize = 64;
+
+    pub fn get(&self, address: Address) -> Option<TlbEntry> {
+        self.entries.get(&(address >> PAGE_SHIFT)).copied()
+    }
+
+    pub fn insert(&mut self, address: Address, entry: TlbEntry) {
+        if self.entries.len() >= Self::CAPACITY {
+            self.entries.clear();
+        }
+        self.entries.insert(address >> PAGE_SHIFT, entry);
+    }
+
+    pub fn flush(&mut self) {
+        self.entries.clear();
+    }
+}
+
+/// The offset of `address` in its page.
+pub const fn page_offset(address: Address) -> Address {
+    address & PAGE_OFFSET
+}
+
+/// The address of the entry for `address` in the table at `table`, `level`
+/// 1 being the root.
+pub const fn pte_address(table: Address, address: Address, level: u32) -> Address {
+    let vpn = (address >> (PAGE_SHIFT + VPN_BITS * level)) & VPN_MASK;
+    table + vpn * PTE_SIZE
+}
+
+/// The table or page `pte` points to.
+pub const fn pte_target(pte: u32) -> Address {
+    ((pte >> PTE_PPN_SHIFT) as Address) << PAGE_SHIFT
+}
+
+/// The page a leaf at `level` maps `address` to, `None` for a misaligned
+/// megapage.
+pub const fn leaf_page(pte: u32, address: Address, level: u32) -> Option<Address> {
+    let target = pte_target(pte);
+    if level == 0 {
+        return Some(target);
+    }
+    if (target >> PAGE_SHIFT) & VPN_MASK != 0 {
+        return None;
+    }
+    Some(target | (address >> PAGE_SHIFT & VPN_MASK) << PAGE_SHIFT)
+}
@@ -8,6 +8,8 @@ pub const JALR: Byte = 0x67; // Jump and Link Register
 // U 类型指令
 pub const LUI: Byte = 0x37; // Load Upper Immediate
 pub const AUIPC: Byte = 0x17; // Add Upper Immediate to PC
+// 系统指令
+pub const SYSTEM: Byte = 0x73; // ecall, ebreak, csr*
 // 其他指令
 pub const NOP: Byte = 0x00; // No Operation
 
@@ -15,8 +17,21 @@ pub const I_TYPE: Byte = 0x03;
 pub const RI_TYPE:Byte = 0x13;
 pub const S_TYPE: Byte = 0x23;
 pub const R_TYPE: Byte = 0x33;
+// RV64 的 *w 指令
+pub const RI_TYPE_32: Byte = 0x1B; // addiw, slliw, srliw, sraiw
+pub const R_TYPE_32: Byte = 0x3B; // addw, subw, sllw, srlw, sraw
 pub const B_TYPE: Byte = 0x63;
 pub const J_TYPE: Byte = 0x6F;
+pub const AMO: Byte = 0x2F; // lr, sc, amo*
+pub const MISC_MEM: Byte = 0x0F; // fence, fence.i
+// 浮点指令
+pub const LOAD_FP: Byte = 0x07; // flw, fld
+pub const STORE_FP: Byte = 0x27; // fsw, fsd
+pub const MADD: Byte = 0x43;
+pub const MSUB: Byte = 0x47;
+pub const NMSUB: Byte = 0x4B;
+pub const NMADD: Byte = 0x4F;
+pub const OP_FP: Byte = 0x53;
 
 mod b {
     use crate::arch::Byte;
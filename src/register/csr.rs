@@ -0,0 +1,716 @@
+use crate::arch::{Address, Privilege, Xlen};
+use crate::exception::{Exception, Interrupt};
+use crate::instruction_type::IInstruction;
+use crate::mmu::{SATP_MODE_SV32, SATP_PPN, Translation};
+use crate::register::{Register, Registers};
+
+pub type CsrAddress = u16;
+
+pub const FFLAGS: CsrAddress = 0x001;
+pub const FRM: CsrAddress = 0x002;
+pub const FCSR: CsrAddress = 0x003;
+pub const MVENDORID: CsrAddress = 0xF11;
+pub const MARCHID: CsrAddress = 0xF12;
+pub const MIMPID: CsrAddress = 0xF13;
+pub const MHARTID: CsrAddress = 0xF14;
+pub const SSTATUS: CsrAddress = 0x100;
+pub const SIE: CsrAddress = 0x104;
+pub const STVEC: CsrAddress = 0x105;
+pub const SSCRATCH: CsrAddress = 0x140;
+pub const SEPC: CsrAddress = 0x141;
+pub const SCAUSE: CsrAddress = 0x142;
+pub const STVAL: CsrAddress = 0x143;
+pub const SIP: CsrAddress = 0x144;
+pub const SATP: CsrAddress = 0x180;
+pub const MSTATUS: CsrAddress = 0x300;
+pub const MISA: CsrAddress = 0x301;
+pub const MEDELEG: CsrAddress = 0x302;
+pub const MIDELEG: CsrAddress = 0x303;
+pub const MIE: CsrAddress = 0x304;
+pub const MTVEC: CsrAddress = 0x305;
+pub const MSCRATCH: CsrAddress = 0x340;
+pub const MEPC: CsrAddress = 0x341;
+pub const MCAUSE: CsrAddress = 0x342;
+pub const MTVAL: CsrAddress = 0x343;
+pub const MIP: CsrAddress = 0x344;
+
+pub const MSTATUS_SIE: Register = 1 << 1;
+pub const MSTATUS_MIE: Register = 1 << 3;
+pub const MSTATUS_SPIE: Register = 1 << 5;
+pub const MSTATUS_MPIE: Register = 1 << 7;
+pub const MSTATUS_SPP: Register = 1 << 8;
+pub const MSTATUS_MPP: Register = 0b11 << 11;
+const MSTATUS_MPP_SHIFT: u32 = 11;
+const MSTATUS_SPP_SHIFT: u32 = 8;
+// Off, Initial, Clean, Dirty
+pub const MSTATUS_FS: Register = 0b11 << 13;
+const MSTATUS_FS_DIRTY: Register = MSTATUS_FS;
+pub const MSTATUS_MPRV: Register = 1 << 17;
+pub const MSTATUS_SUM: Register = 1 << 18;
+pub const MSTATUS_MXR: Register = 1 << 19;
+// UXL and SXL, only present on RV64 where they read as 64 bit
+const MSTATUS_UXL_SXL_64: Register = 0b1010 << 32;
+// SD (read-only, set while FS is Dirty) and the mcause interrupt flag are
+// the top bit, see `Xlen::sign_bit`
+// MPP is written on its own, it is WARL
+const MSTATUS_WRITABLE: Register = MSTATUS_SIE
+    | MSTATUS_MIE
+    | MSTATUS_SPIE
+    | MSTATUS_MPIE
+    | MSTATUS_SPP
+    | MSTATUS_FS
+    | MSTATUS_MPRV
+    | MSTATUS_SUM
+    | MSTATUS_MXR;
+// the view of mstatus sstatus gives, SD aside
+const SSTATUS_MASK: Register =
+    MSTATUS_SIE | MSTATUS_SPIE | MSTATUS_SPP | MSTATUS_FS | MSTATUS_SUM | MSTATUS_MXR;
+const MACHINE_INTERRUPTS: Register = Interrupt::MachineSoftware.mask()
+    | Interrupt::MachineTimer.mask()
+    | Interrupt::MachineExternal.mask();
+// software may raise these in mip, the devices only drive the M ones
+const SUPERVISOR_INTERRUPTS: Register = Interrupt::SupervisorSoftware.mask()
+    | Interrupt::SupervisorTimer.mask()
+    | Interrupt::SupervisorExternal.mask();
+const MIE_WRITABLE: Register = MACHINE_INTERRUPTS | SUPERVISOR_INTERRUPTS;
+// ecall from M-mode can't be delegated, nor the reserved causes
+const MEDELEG_WRITABLE: Register = 0xB3FF;
+const MIDELEG_WRITABLE: Register = SUPERVISOR_INTERRUPTS;
+
+// MXL, the top two bits: 1 for 32 bit, 2 for 64 bit
+const MISA_MXL_32: Register = 1 << 30;
+const MISA_MXL_64: Register = 2 << 62;
+
+// fcsr is frm above fflags
+const FFLAGS_MASK: Register = 0x1F;
+const FRM_SHIFT: u32 = 5;
+const FCSR_MASK: Register = 0xFF;
+
+/// the assembler name of an implemented CSR
+pub const fn name(csr: CsrAddress) -> Option<&'static str> {
+    Some(match csr {
+        FFLAGS => "fflags",
+        FRM => "frm",
+        FCSR => "fcsr",
+        MVENDORID => "mvendorid",
+        MARCHID => "marchid",
+        MIMPID => "mimpid",
+        MHARTID => "mhartid",
+        SSTATUS => "sstatus",
+        SIE => "sie",
+        STVEC => "stvec",
+        SSCRATCH => "sscratch",
+        SEPC => "sepc",
+        SCAUSE => "scause",
+        STVAL => "stval",
+        SIP => "sip",
+        SATP => "satp",
+        MSTATUS => "mstatus",
+        MISA => "misa",
+        MEDELEG => "medeleg",
+        MIDELEG => "mideleg",
+        MIE => "mie",
+        MTVEC => "mtvec",
+        MSCRATCH => "mscratch",
+        MEPC => "mepc",
+        MCAUSE => "mcause",
+        MTVAL => "mtval",
+        MIP => "mip",
+        _ => return None,
+    })
+}
+
+pub const fn extension(letter: u8) -> Register {
+    1 << (letter - b'A')
+}
+
+/// A write by a CSR instruction, `new` as read back afterwards.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CsrWrite {
+    pub csr: CsrAddress,
+    pub old: Register,
+    pub new: Register,
+}
+
+/// Machine- and supervisor-mode CSR file, with the privilege the hart runs
+/// at. Fields without a legal value for a write keep their old value (WARL),
+/// so every read returns a legal value.
+///
+/// The floating-point unit starts off, mstatus.FS has to be set before
+/// the F and D instructions or the floating-point CSRs can be used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsrRegisters {
+    fcsr: Register,
+    misa: Register,
+    mhartid: Register,
+    mstatus: Register,
+    medeleg: Register,
+    mideleg: Register,
+    mtvec: Register,
+    mscratch: Register,
+    mepc: Register,
+    mcause: Register,
+    mtval: Register,
+    mie: Register,
+    // the M bits are driven by the devices, only the S bits can be written
+    mip: Register,
+    stvec: Register,
+    sscratch: Register,
+    sepc: Register,
+    scause: Register,
+    stval: Register,
+    satp: Register,
+    privilege: Privilege,
+}
+
+impl Default for CsrRegisters {
+    fn default() -> Self {
+        Self::default()
+    }
+}
+
+impl CsrRegisters {
+    pub const fn default() -> Self {
+        Self {
+            fcsr: 0,
+            misa: MISA_MXL_32
+                | extension(b'A')
+                | extension(b'C')
+                | extension(b'D')
+                | extension(b'F')
+                | extension(b'I')
+                | extension(b'M')
+                | extension(b'S')
+                | extension(b'U'),
+            mhartid: 0,
+            mstatus: MSTATUS_MPP,
+            medeleg: 0,
+            mideleg: 0,
+            mtvec: 0,
+            mscratch: 0,
+            mepc: 0,
+            mcause: 0,
+            mtval: 0,
+            mie: 0,
+            mip: 0,
+            stvec: 0,
+            sscratch: 0,
+            sepc: 0,
+            scause: 0,
+            stval: 0,
+            satp: 0,
+            privilege: Privilege::Machine,
+        }
+    }
+
+    /// every field, for snapshots
+    pub(crate) fn save(&self) -> Vec<u8> {
+        self.fields()
+            .iter()
+            .flat_map(|field| field.to_le_bytes())
+            .collect()
+    }
+
+    pub(crate) fn restore(&mut self, state: &[u8]) -> Option<()> {
+        let (words, rest) = state.as_chunks::<8>();
+        if !rest.is_empty() || words.len() != self.fields().len() {
+            return None;
+        }
+        [
+            &mut self.fcsr,
+            &mut self.misa,
+            &mut self.mhartid,
+            &mut self.mstatus,
+            &mut self.medeleg,
+            &mut self.mideleg,
+            &mut self.mtvec,
+            &mut self.mscratch,
+            &mut self.mepc,
+            &mut self.mcause,
+            &mut self.mtval,
+            &mut self.mie,
+            &mut self.mip,
+            &mut self.stvec,
+            &mut self.sscratch,
+            &mut self.sepc,
+            &mut self.scause,
+            &mut self.stval,
+            &mut self.satp,
+        ]
+        .into_iter()
+        .zip(words)
+        .for_each(|(field, word)| *field = Register::from_le_bytes(*word));
+        self.privilege = Privilege::from_bits(Register::from_le_bytes(words[words.len() - 1]));
+        Some(())
+    }
+
+    const fn fields(&self) -> [Register; 20] {
+        [
+            self.fcsr,
+            self.misa,
+            self.mhartid,
+            self.mstatus,
+            self.medeleg,
+            self.mideleg,
+            self.mtvec,
+            self.mscratch,
+            self.mepc,
+            self.mcause,
+            self.mtval,
+            self.mie,
+            self.mip,
+            self.stvec,
+            self.sscratch,
+            self.sepc,
+            self.scause,
+            self.stval,
+            self.satp,
+            self.privilege.bits(),
+        ]
+    }
+
+    /// what misa.MXL says
+    pub const fn xlen(&self) -> Xlen {
+        if self.misa >> 62 == 2 {
+            Xlen::X64
+        } else {
+            Xlen::X32
+        }
+    }
+
+    /// what misa says, E in place of I
+    pub const fn embedded(&self) -> bool {
+        self.misa & extension(b'E') != 0
+    }
+
+    /// Swaps I for E in misa, read-only to the guest as well
+    pub(crate) const fn set_embedded(&mut self, embedded: bool) {
+        let base = extension(b'I') | extension(b'E');
+        self.misa &= !base;
+        self.misa |= if embedded {
+            extension(b'E')
+        } else {
+            extension(b'I')
+        };
+    }
+
+    /// Switches MXL, which is read-only to the guest. The CSRs keep their
+    /// low XLEN bits.
+    pub(crate) const fn set_xlen(&mut self, xlen: Xlen) {
+        let extensions = self.misa & 0x03FF_FFFF;
+        self.misa = match xlen {
+            Xlen::X32 => MISA_MXL_32,
+            Xlen::X64 => MISA_MXL_64,
+        } | extensions;
+        self.mstatus = self.summarize(self.mstatus);
+        self.mtvec = xlen.truncate(self.mtvec);
+        self.mscratch = xlen.truncate(self.mscratch);
+        self.mepc = xlen.truncate(self.mepc);
+        self.mcause = xlen.truncate(self.mcause);
+        self.mtval = xlen.truncate(self.mtval);
+        self.stvec = xlen.truncate(self.stvec);
+        self.sscratch = xlen.truncate(self.sscratch);
+        self.sepc = xlen.truncate(self.sepc);
+        self.scause = xlen.truncate(self.scause);
+        self.stval = xlen.truncate(self.stval);
+        // Sv32 is the only translation implemented, RV64 runs Bare
+        if let Xlen::X64 = xlen {
+            self.satp = 0;
+        }
+    }
+
+    /// the privilege the hart runs at
+    pub const fn privilege(&self) -> Privilege {
+        self.privilege
+    }
+
+    /// how loads, stores and fetches are translated at the current privilege
+    pub const fn translation(&self) -> Translation {
+        let data = if self.mstatus & MSTATUS_MPRV != 0 {
+            Privilege::from_bits(self.mstatus >> MSTATUS_MPP_SHIFT)
+        } else {
+            self.privilege
+        };
+        Translation {
+            root: if self.satp & SATP_MODE_SV32 != 0 {
+                Some((self.satp & SATP_PPN) << 12)
+            } else {
+                None
+            },
+            fetch: self.privilege,
+            data,
+            sum: self.mstatus & MSTATUS_SUM != 0,
+            mxr: self.mstatus & MSTATUS_MXR != 0,
+        }
+    }
+
+    pub const fn is_read_only(csr: CsrAddress) -> bool {
+        csr >> 10 == 0b11
+    }
+
+    /// `None` if the CSR is not implemented
+    pub const fn read(&self, csr: CsrAddress) -> Option<Register> {
+        Some(match csr {
+            FFLAGS | FRM | FCSR if !self.float_enabled() => return None,
+            FFLAGS => self.fcsr & FFLAGS_MASK,
+            FRM => self.fcsr >> FRM_SHIFT,
+            FCSR => self.fcsr,
+            MVENDORID | MARCHID | MIMPID => 0,
+            MHARTID => self.mhartid,
+            SSTATUS => self.mstatus & (SSTATUS_MASK | self.xlen().sign_bit()),
+            SIE => self.mie & self.mideleg,
+            STVEC => self.stvec,
+            SSCRATCH => self.sscratch,
+            SEPC => self.sepc,
+            SCAUSE => self.scause,
+            STVAL => self.stval,
+            SIP => self.mip & self.mideleg,
+            SATP => self.satp,
+            MSTATUS => self.mstatus,
+            MISA => self.misa,
+            MEDELEG => self.medeleg,
+            MIDELEG => self.mideleg,
+            MTVEC => self.mtvec,
+            MSCRATCH => self.mscratch,
+            MEPC => self.mepc,
+            MCAUSE => self.mcause,
+            MTVAL => self.mtval,
+            MIE => self.mie,
+            MIP => self.mip,
+            _ => return None,
+        })
+    }
+
+    /// `None` if the CSR is not implemented or read-only
+    pub const fn write(&mut self, csr: CsrAddress, value: Register) -> Option<()> {
+        if Self::is_read_only(csr) {
+            return None;
+        }
+        let value = self.xlen().truncate(value);
+        match csr {
+            FFLAGS | FRM | FCSR if !self.float_enabled() => return None,
+            FFLAGS => self.fcsr = (self.fcsr & !FFLAGS_MASK) | (value & FFLAGS_MASK),
+            // any frm can be written, the reserved ones trap once used
+            FRM => self.fcsr = (self.fcsr & FFLAGS_MASK) | (value & 0b111) << FRM_SHIFT,
+            FCSR => self.fcsr = value & FCSR_MASK,
+            SSTATUS => {
+                let mstatus = (self.mstatus & !SSTATUS_MASK) | (value & SSTATUS_MASK);
+                self.mstatus = self.summarize(mstatus);
+            }
+            SIE => self.mie = (self.mie & !self.mideleg) | (value & self.mideleg & MIE_WRITABLE),
+            // only SSIP can be raised or cleared, and only once delegated
+            SIP => {
+                let writable = Interrupt::SupervisorSoftware.mask() & self.mideleg;
+                self.mip = (self.mip & !writable) | (value & writable);
+            }
+            STVEC => {
+                if value & 0b11 < 2 {
+                    self.stvec = value;
+                }
+            }
+            SSCRATCH => self.sscratch = value,
+            SEPC => self.sepc = value & !1,
+            SCAUSE => self.scause = value,
+            STVAL => self.stval = value,
+            // Bare or Sv32, the ASID isn't implemented and reads as 0. A
+            // mode RV64 doesn't implement leaves satp as it was.
+            SATP => {
+                if let Xlen::X32 = self.xlen() {
+                    self.satp = value & (SATP_MODE_SV32 | SATP_PPN);
+                }
+            }
+            MSTATUS => {
+                let mut mstatus = (self.mstatus & !MSTATUS_WRITABLE) | (value & MSTATUS_WRITABLE);
+                // MPP is WARL, 2 is reserved
+                if (value & MSTATUS_MPP) >> MSTATUS_MPP_SHIFT != 2 {
+                    mstatus = (mstatus & !MSTATUS_MPP) | (value & MSTATUS_MPP);
+                }
+                self.mstatus = self.summarize(mstatus);
+            }
+            // misa is WARL, the extensions can't be turned off
+            MISA => {}
+            MEDELEG => self.medeleg = value & MEDELEG_WRITABLE,
+            MIDELEG => self.mideleg = value & MIDELEG_WRITABLE,
+            MTVEC => {
+                // MODE >= 2 is reserved
+                if value & 0b11 < 2 {
+                    self.mtvec = value;
+                }
+            }
+            MSCRATCH => self.mscratch = value,
+            // IALIGN is 16 bits with C
+            MEPC => self.mepc = value & !1,
+            MCAUSE => self.mcause = value,
+            MTVAL => self.mtval = value,
+            MIE => self.mie = value & MIE_WRITABLE,
+            MIP => self.mip = (self.mip & !SUPERVISOR_INTERRUPTS) | (value & SUPERVISOR_INTERRUPTS),
+            _ => return None,
+        }
+        if matches!(csr, FFLAGS | FRM | FCSR) {
+            self.set_float_dirty();
+        }
+        Some(())
+    }
+
+    // mstatus with SD following FS, and UXL/SXL on RV64
+    const fn summarize(&self, mstatus: Register) -> Register {
+        let sd = self.xlen().sign_bit();
+        // SD moves with XLEN
+        let mstatus = match self.xlen() {
+            Xlen::X32 => mstatus & 0x7FFF_FFFF,
+            Xlen::X64 => mstatus & 0x7FFF_FFFF | MSTATUS_UXL_SXL_64,
+        };
+        if mstatus & MSTATUS_FS == MSTATUS_FS_DIRTY {
+            mstatus | sd
+        } else {
+            mstatus
+        }
+    }
+
+    /// whether mstatus.FS lets the hart use the floating-point unit
+    pub const fn float_enabled(&self) -> bool {
+        self.mstatus & MSTATUS_FS != 0
+    }
+
+    /// FS to Dirty, after the floating-point state was written
+    pub const fn set_float_dirty(&mut self) {
+        self.mstatus |= MSTATUS_FS_DIRTY | self.xlen().sign_bit();
+    }
+
+    /// frm, the rounding mode of instructions whose rm is dyn
+    pub const fn rounding_mode(&self) -> u32 {
+        (self.fcsr >> FRM_SHIFT) as u32
+    }
+
+    /// raises `flags` in fflags, they stay set until software clears them
+    pub const fn accrue(&mut self, flags: u8) {
+        if flags != 0 {
+            self.fcsr |= flags as Register;
+            self.set_float_dirty();
+        }
+    }
+
+    /// the machine interrupts the devices raise, the supervisor ones are
+    /// left as software wrote them
+    pub const fn set_pending(&mut self, mip: Register) {
+        self.mip = (self.mip & SUPERVISOR_INTERRUPTS) | (mip & !SUPERVISOR_INTERRUPTS);
+    }
+
+    /// the interrupt to take before the next instruction, if any
+    pub const fn pending_interrupt(&self) -> Option<Interrupt> {
+        let pending = self.mip & self.mie;
+        let machine = match self.privilege {
+            Privilege::Machine => self.mstatus & MSTATUS_MIE != 0,
+            _ => true,
+        };
+        let supervisor = match self.privilege {
+            Privilege::Machine => false,
+            Privilege::Supervisor => self.mstatus & MSTATUS_SIE != 0,
+            Privilege::User => true,
+        };
+        let mut i = 0;
+        while i < Interrupt::PRIORITY.len() {
+            let mask = Interrupt::PRIORITY[i].mask();
+            let enabled = if self.mideleg & mask != 0 {
+                supervisor
+            } else {
+                machine
+            };
+            if pending & mask != 0 && enabled {
+                return Some(Interrupt::PRIORITY[i]);
+            }
+            i += 1;
+        }
+        None
+    }
+
+    /// whether any interrupt could be taken, whatever is pending
+    pub const fn interrupts_enabled(&self) -> bool {
+        let enabled = match self.privilege {
+            Privilege::Machine => self.mstatus & MSTATUS_MIE != 0,
+            _ => true,
+        };
+        enabled && self.mie != 0
+    }
+
+    /// whether the trap goes to S-mode, never from M-mode
+    pub const fn delegated(&self, interrupt: bool, code: u32) -> bool {
+        let delegation = if interrupt {
+            self.mideleg
+        } else {
+            self.medeleg
+        };
+        !matches!(self.privilege, Privilege::Machine) && delegation >> code & 1 != 0
+    }
+
+    /// whether the guest has installed the handler the trap would go to
+    pub const fn has_trap_vector(&self, interrupt: bool, code: u32) -> bool {
+        if self.delegated(interrupt, code) {
+            self.stvec != 0
+        } else {
+            self.mtvec != 0
+        }
+    }
+
+    /// Records the trap in mepc/mcause/mtval, or in sepc/scause/stval when it
+    /// is delegated, pushes the interrupt-enable and privilege stack of that
+    /// level and returns the handler address. Only interrupts are vectored.
+    pub const fn trap_enter(
+        &mut self,
+        pc: Address,
+        interrupt: bool,
+        code: u32,
+        tval: Register,
+    ) -> Address {
+        let flag = if interrupt { self.xlen().sign_bit() } else { 0 };
+        let cause = flag | code as Register;
+        let previous = self.privilege.bits();
+        let tvec = if self.delegated(interrupt, code) {
+            self.sepc = pc;
+            self.scause = cause;
+            self.stval = tval;
+            let spie = if self.mstatus & MSTATUS_SIE != 0 {
+                MSTATUS_SPIE
+            } else {
+                0
+            };
+            self.mstatus = (self.mstatus & !(MSTATUS_SIE | MSTATUS_SPIE | MSTATUS_SPP))
+                | spie
+                | previous << MSTATUS_SPP_SHIFT;
+            self.privilege = Privilege::Supervisor;
+            self.stvec
+        } else {
+            self.mepc = pc;
+            self.mcause = cause;
+            self.mtval = tval;
+            let mpie = if self.mstatus & MSTATUS_MIE != 0 {
+                MSTATUS_MPIE
+            } else {
+                0
+            };
+            self.mstatus = (self.mstatus & !(MSTATUS_MIE | MSTATUS_MPIE | MSTATUS_MPP))
+                | mpie
+                | previous << MSTATUS_MPP_SHIFT;
+            self.privilege = Privilege::Machine;
+            self.mtvec
+        };
+        let base = tvec & !0b11;
+        if tvec & 0b11 == 1 && interrupt {
+            base + ((code as Register) << 2)
+        } else {
+            base
+        }
+    }
+
+    /// `mret`: pops the interrupt-enable and privilege stack and returns mepc
+    pub const fn trap_return(&mut self) -> Address {
+        let mie = if self.mstatus & MSTATUS_MPIE != 0 {
+            MSTATUS_MIE
+        } else {
+            0
+        };
+        self.privilege = Privilege::from_bits(self.mstatus >> MSTATUS_MPP_SHIFT);
+        // MPP goes back to the least privileged mode implemented
+        let mut mstatus = (self.mstatus & !(MSTATUS_MIE | MSTATUS_MPP)) | mie | MSTATUS_MPIE;
+        if !matches!(self.privilege, Privilege::Machine) {
+            mstatus &= !MSTATUS_MPRV;
+        }
+        self.mstatus = mstatus;
+        self.mepc
+    }
+
+    /// `sret`: pops the supervisor stack and returns sepc
+    pub const fn supervisor_return(&mut self) -> Address {
+        let sie = if self.mstatus & MSTATUS_SPIE != 0 {
+            MSTATUS_SIE
+        } else {
+            0
+        };
+        self.privilege = Privilege::from_bits(self.mstatus >> MSTATUS_SPP_SHIFT & 1);
+        self.mstatus =
+            (self.mstatus & !(MSTATUS_SIE | MSTATUS_SPP | MSTATUS_MPRV)) | sie | MSTATUS_SPIE;
+        self.sepc
+    }
+
+    /// csrrw, csrrs, csrrc and their immediate forms, and the write they made
+    pub fn execute(
+        &mut self,
+        registers: &mut Registers,
+        i: IInstruction,
+    ) -> Result<Option<CsrWrite>, Exception> {
+        let illegal = Exception::IllegalInstruction(i.0.0);
+        let csr = i.umm() as CsrAddress;
+        // bits 9:8 are the lowest privilege that may access the CSR
+        if (csr >> 8 & 0b11) as u64 > self.privilege.bits() {
+            return Err(illegal);
+        }
+        let source = if i.funct3() & 0b100 == 0 {
+            registers.get(i.rs1())
+        } else {
+            i.rs1() as Register
+        };
+        let old = self.read(csr).ok_or(illegal)?;
+        let new = match i.funct3() & 0b11 {
+            0b01 => Some(source),
+            // csrrs/csrrc with x0 (or uimm 0) don't write, and so are legal on read-only CSRs
+            0b10 if i.rs1() != 0 => Some(old | source),
+            0b11 if i.rs1() != 0 => Some(old & !source),
+            0b10 | 0b11 => None,
+            _ => return Err(illegal),
+        };
+        let written = match new {
+            Some(new) => {
+                self.write(csr, new).ok_or(illegal)?;
+                // what stuck, WARL fields may have kept their old value
+                let new = self.read(csr).unwrap_or(new);
+                Some(CsrWrite { csr, old, new })
+            }
+            None => None,
+        };
+        *registers.get_mut(i.rd()) = old;
+        Ok(written)
+    }
+}
+
+pub mod alias {
+    use super::*;
+
+    macro_rules! csr_alias {
+        ($($name:ident: $csr:expr),*) => {
+            $(
+                #[allow(non_upper_case_globals)]
+                pub const $name: CsrAddress = $csr;
+            )*
+        };
+    }
+
+    csr_alias! {
+        fflags: FFLAGS,
+        frm: FRM,
+        fcsr: FCSR,
+        mvendorid: MVENDORID,
+        marchid: MARCHID,
+        mimpid: MIMPID,
+        mhartid: MHARTID,
+        sstatus: SSTATUS,
+        sie: SIE,
+        stvec: STVEC,
+        sscratch: SSCRATCH,
+        sepc: SEPC,
+        scause: SCAUSE,
+        stval: STVAL,
+        sip: SIP,
+        satp: SATP,
+        mstatus: MSTATUS,
+        misa: MISA,
+        medeleg: MEDELEG,
+        mideleg: MIDELEG,
+        mtvec: MTVEC,
+        mscratch: MSCRATCH,
+        mepc: MEPC,
+        mcause: MCAUSE,
+        mtval: MTVAL,
+        mie: MIE,
+        mip: MIP
+    }
+}
@@ -0,0 +1,171 @@
+use crate::alu::FpOp;
+use crate::arch::RISC_V_32_REGISTERS;
+use crate::decode::Decoded;
+use crate::exception::Exception;
+use crate::float::{DOUBLE, DYNAMIC, FloatEnv, Format, RoundingMode, SINGLE};
+use crate::register::{CsrRegisters, Register, Registers};
+
+/// Wide enough for a double. Singles are NaN-boxed: the upper half is all ones.
+pub type FloatRegister = u64;
+
+const NAN_BOX: FloatRegister = 0xFFFF_FFFF_0000_0000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FloatRegisters {
+    registers: [FloatRegister; RISC_V_32_REGISTERS],
+}
+
+impl Default for FloatRegisters {
+    fn default() -> Self {
+        Self::default()
+    }
+}
+
+impl FloatRegisters {
+    pub const fn default() -> Self {
+        Self {
+            registers: [0; RISC_V_32_REGISTERS],
+        }
+    }
+
+    pub const fn get(&self, i: u8) -> FloatRegister {
+        self.registers[i as usize % RISC_V_32_REGISTERS]
+    }
+
+    pub const fn write(&mut self, i: u8, v: FloatRegister) {
+        self.registers[i as usize % RISC_V_32_REGISTERS] = v;
+    }
+
+    /// the single in `i`, the canonical NaN if it isn't NaN-boxed
+    pub const fn single(&self, i: u8) -> u32 {
+        let v = self.get(i);
+        if v & NAN_BOX == NAN_BOX {
+            v as u32
+        } else {
+            SINGLE.canonical_nan() as u32
+        }
+    }
+
+    pub const fn write_single(&mut self, i: u8, v: u32) {
+        self.write(i, NAN_BOX | v as FloatRegister);
+    }
+
+    // a value of format `f`, in the low bits
+    const fn read(&self, i: u8, f: Format) -> u64 {
+        if f.is_single() {
+            self.single(i) as u64
+        } else {
+            self.get(i)
+        }
+    }
+
+    const fn write_format(&mut self, i: u8, f: Format, v: u64) {
+        if f.is_single() {
+            self.write_single(i, v as u32)
+        } else {
+            self.write(i, v)
+        }
+    }
+
+    /// The OP-FP and fused multiply-add instructions. `frm` picks the
+    /// rounding mode for `rm` = dyn, and the flags raised accrue in `fflags`.
+    pub fn execute(
+        &mut self,
+        registers: &mut Registers,
+        csr: &mut CsrRegisters,
+        op: FpOp,
+        f: Format,
+        d: &Decoded,
+    ) -> Result<(), Exception> {
+        let illegal = Exception::IllegalInstruction(d.word);
+        if !csr.float_enabled() {
+            return Err(illegal);
+        }
+        // rm, and rs3 above it
+        let rm = match d.imm as u32 & 0b111 {
+            DYNAMIC => csr.rounding_mode(),
+            rm => rm,
+        };
+        let rounding = match RoundingMode::decode(rm) {
+            Some(rounding) => rounding,
+            // the operations without a rounding mode use the field for
+            // something else
+            None if op.rounds() => return Err(illegal),
+            None => RoundingMode::NearestEven,
+        };
+        let mut env = FloatEnv::new(rounding);
+        let a = self.read(d.rs1, f);
+        let b = self.read(d.rs2, f);
+        let c = self.read((d.imm >> 3) as u8, f);
+        let result = match op {
+            FpOp::Add => Written::Float(env.add(f, a, b)),
+            FpOp::Sub => Written::Float(env.sub(f, a, b)),
+            FpOp::Mul => Written::Float(env.mul(f, a, b)),
+            FpOp::Div => Written::Float(env.div(f, a, b)),
+            FpOp::Sqrt => Written::Float(env.sqrt(f, a)),
+            FpOp::MulAdd => Written::Float(env.mul_add(f, a, b, c)),
+            FpOp::MulSub => Written::Float(env.mul_add(f, a, b, f.negate(c))),
+            FpOp::NegMulSub => Written::Float(env.mul_add(f, f.negate(a), b, c)),
+            FpOp::NegMulAdd => Written::Float(env.mul_add(f, f.negate(a), b, f.negate(c))),
+            FpOp::SignInject => Written::Float(f.with_sign(a, f.sign(b))),
+            FpOp::SignInjectNeg => Written::Float(f.with_sign(a, !f.sign(b))),
+            FpOp::SignInjectXor => Written::Float(f.with_sign(a, f.sign(a) != f.sign(b))),
+            FpOp::Min => Written::Float(env.min(f, a, b)),
+            FpOp::Max => Written::Float(env.max(f, a, b)),
+            FpOp::Convert => {
+                let from = if f.is_single() { DOUBLE } else { SINGLE };
+                Written::Float(env.convert(from, f, self.read(d.rs1, from)))
+            }
+            FpOp::FromInt { signed, long } => {
+                let value = registers.get(d.rs1);
+                let value = match (signed, long) {
+                    (true, false) => value as i32 as i128,
+                    (false, false) => value as u32 as i128,
+                    (true, true) => value as i64 as i128,
+                    (false, true) => value as i128,
+                };
+                Written::Float(env.int_to_float(f, value))
+            }
+            FpOp::MoveFromInt => Written::Float(registers.get(d.rs1)),
+            FpOp::ToInt { signed, long } => {
+                let (min, max) = match (signed, long) {
+                    (true, false) => (i32::MIN as i128, i32::MAX as i128),
+                    (false, false) => (0, u32::MAX as i128),
+                    (true, true) => (i64::MIN as i128, i64::MAX as i128),
+                    (false, true) => (0, u64::MAX as i128),
+                };
+                let value = env.float_to_int(f, a, min, max);
+                // a word is sign-extended on RV64, unsigned or not
+                Written::Integer(if long {
+                    value as Register
+                } else {
+                    value as u32 as i32 as Register
+                })
+            }
+            // the bits as they are, boxed or not, a single sign-extended
+            FpOp::MoveToInt if f.is_single() => {
+                Written::Integer(self.get(d.rs1) as u32 as i32 as Register)
+            }
+            FpOp::MoveToInt => Written::Integer(self.get(d.rs1)),
+            FpOp::Eq => Written::Integer(env.equal(f, a, b) as Register),
+            FpOp::Lt => Written::Integer(env.less(f, a, b) as Register),
+            FpOp::Le => Written::Integer(env.less_equal(f, a, b) as Register),
+            FpOp::Class => Written::Integer(f.classify(a) as Register),
+        };
+        match result {
+            Written::Float(value) => {
+                self.write_format(d.rd, f, value);
+                csr.set_float_dirty();
+            }
+            Written::Integer(value) => registers.write(d.rd, value),
+        }
+        csr.accrue(env.flags);
+        Ok(())
+    }
+}
+
+// where an OP-FP instruction puts its result
+enum Written {
+    Float(u64),
+    Integer(Register),
+}
@@ -1,13 +1,40 @@
-use crate::arch::{R32I, RISC_V_32_REGISTERS};
+use crate::arch::{RISC_V_32_REGISTERS, RV32E_REGISTERS, Xlen};
+pub mod csr;
+pub mod float;
+
+pub use csr::CsrRegisters;
+pub use float::FloatRegisters;
+
 pub const ZERO: Register = 0;
-pub type Register = R32I;
+/// Wide enough for RV64. On RV32 the upper half stays zero.
+pub type Register = u64;
+
+pub const ABI_NAMES: [&str; RISC_V_32_REGISTERS] = [
+    "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "fp", "s1", "a0", "a1", "a2", "a3", "a4",
+    "a5", "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3", "t4",
+    "t5", "t6",
+];
+
+pub const fn abi_name(i: u8) -> &'static str {
+    ABI_NAMES[i as usize]
+}
+
+pub const FLOAT_ABI_NAMES: [&str; RISC_V_32_REGISTERS] = [
+    "ft0", "ft1", "ft2", "ft3", "ft4", "ft5", "ft6", "ft7", "fs0", "fs1", "fa0", "fa1", "fa2",
+    "fa3", "fa4", "fa5", "fa6", "fa7", "fs2", "fs3", "fs4", "fs5", "fs6", "fs7", "fs8", "fs9",
+    "fs10", "fs11", "ft8", "ft9", "ft10", "ft11",
+];
 
-// TODO: Add CsrRegisters
+pub const fn float_abi_name(i: u8) -> &'static str {
+    FLOAT_ABI_NAMES[i as usize]
+}
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone)]
 pub struct Registers {
     registers: [Register; RISC_V_32_REGISTERS],
     dirty: Register,
+    xlen: Xlen,
+    embedded: bool,
 }
 
 impl Registers {
@@ -15,8 +42,42 @@ impl Registers {
         Self {
             registers: [0; RISC_V_32_REGISTERS],
             dirty: 0,
+            xlen: Xlen::X32,
+            embedded: false,
+        }
+    }
+    pub const fn xlen(&self) -> Xlen {
+        self.xlen
+    }
+    /// Only the emulator calls this, along with the misa it keeps in step.
+    pub(crate) const fn set_xlen(&mut self, xlen: Xlen) {
+        self.xlen = xlen;
+    }
+    /// 16 on RV32E, 32 otherwise
+    pub const fn count(&self) -> u8 {
+        if self.embedded {
+            RV32E_REGISTERS as u8
+        } else {
+            RISC_V_32_REGISTERS as u8
         }
     }
+    /// Only the emulator calls this, along with misa. x16..x31 are cleared,
+    /// nothing can reach them on RV32E.
+    pub(crate) const fn set_embedded(&mut self, embedded: bool) {
+        self.embedded = embedded;
+        if embedded {
+            let mut i = RV32E_REGISTERS;
+            while i < RISC_V_32_REGISTERS {
+                self.registers[i] = 0;
+                i += 1;
+            }
+        }
+    }
+    /// The registers as an array, x0 included. Whoever writes through it has
+    /// to put x0 back to zero, and keep the values within XLEN.
+    pub(crate) const fn file_mut(&mut self) -> &mut [Register; RISC_V_32_REGISTERS] {
+        &mut self.registers
+    }
     pub const fn get(&self, i: u8) -> Register {
         assert!(i < RISC_V_32_REGISTERS as u8);
         self.registers[i as usize]
@@ -28,12 +89,14 @@ impl Registers {
         assert!(i < RISC_V_32_REGISTERS as u8);
         &mut self.registers[i as usize]
     }
-    pub const fn write(&mut self, i: u8, v: u32) {
+    /// Writes `v` cut to XLEN, so a sign-extended 64-bit result lands right
+    /// on RV32 as well.
+    pub const fn write(&mut self, i: u8, v: Register) {
         assert!(i < RISC_V_32_REGISTERS as u8);
         if i == 0 {
             return;
         }
-        self.registers[i as usize] = v;
+        self.registers[i as usize] = self.xlen.truncate(v);
     }
 }
 
@@ -132,6 +195,42 @@ pub mod alias {
         t6: 31
     }
 
+    // f0..f31 come from register_aliases
+    register_alias! {
+        ft0: 0,
+        ft1: 1,
+        ft2: 2,
+        ft3: 3,
+        ft4: 4,
+        ft5: 5,
+        ft6: 6,
+        ft7: 7,
+        fs0: 8,
+        fs1: 9,
+        fa0: 10,
+        fa1: 11,
+        fa2: 12,
+        fa3: 13,
+        fa4: 14,
+        fa5: 15,
+        fa6: 16,
+        fa7: 17,
+        fs2: 18,
+        fs3: 19,
+        fs4: 20,
+        fs5: 21,
+        fs6: 22,
+        fs7: 23,
+        fs8: 24,
+        fs9: 25,
+        fs10: 26,
+        fs11: 27,
+        ft8: 28,
+        ft9: 29,
+        ft10: 30,
+        ft11: 31
+    }
+
     pub const fn t(n: u8) -> u8 {
         if n <= 2 { n + 5 } else { n + 25 }
     }
@@ -143,4 +242,48 @@ pub mod alias {
     pub const fn a(n: u8) -> u8 {
         n + 10
     }
+
+    /// The ilp32e names `rv32e_asm!` assembles with: only x0..x15, so a0..a5,
+    /// s0..s1 and t0..t2. The ABI names of those registers don't change.
+    pub mod ilp32e {
+        // the whole set, guest code only names some of them
+        #[allow(unused_imports)]
+        pub use super::{
+            a0, a1, a2, a3, a4, a5, fp, gp, ra, s0, s1, sp, t0, t1, t2, tp, x0, x1, x2, x3, x4, x5,
+            x6, x7, x8, x9, x10, x11, x12, x13, x14, x15, zero,
+        };
+
+        /// What the names of x16..x31 are here. They shadow the full set
+        /// where that is imported too, so naming one doesn't assemble.
+        pub struct NotInIlp32e;
+
+        macro_rules! not_in_ilp32e {
+            ($($name:ident),*) => {
+                $(
+                    #[allow(non_upper_case_globals)]
+                    pub const $name: NotInIlp32e = NotInIlp32e;
+                )*
+            };
+        }
+
+        not_in_ilp32e!(
+            x16, x17, x18, x19, x20, x21, x22, x23, x24, x25, x26, x27, x28, x29, x30, x31, a6, a7,
+            s2, s3, s4, s5, s6, s7, s8, s9, s10, s11, t3, t4, t5, t6
+        );
+
+        pub const fn t(n: u8) -> u8 {
+            assert!(n <= 2, "ilp32e has t0..t2");
+            super::t(n)
+        }
+
+        pub const fn s(n: u8) -> u8 {
+            assert!(n <= 1, "ilp32e has s0..s1");
+            super::s(n)
+        }
+
+        pub const fn a(n: u8) -> u8 {
+            assert!(n <= 5, "ilp32e has a0..a5");
+            super::a(n)
+        }
+    }
 }
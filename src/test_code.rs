@@ -1,8 +1,28 @@
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod test_code {
     use crate::EmulatorContext;
-    use r32i_asm::riscv_asm;
+    use crate::const_emulator::ConstantEmulator;
+    use std::time::{Duration, Instant};
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use crate::arch::{Address, Privilege, Xlen};
+    use crate::device::Device;
+    use crate::device::clint::ClockSource;
+    use crate::emulator::block::ExecutionMode;
+    use crate::emulator::debug::{StoppedAt, WatchKind};
+    use crate::emulator::snapshot::{Snapshot, SnapshotError};
+    use crate::emulator::trace::SpikeCommitLog;
+    use crate::instruction_type::Instruction;
+    use crate::emulator::step::{RegisterWrite, StepStatus};
+    use crate::memory::MemoryAccess;
+    use crate::device::uart::{BufferBackend, UART_BASE, UART_SIZE, Uart};
+    use crate::emulator::syscall::HostSyscalls;
+    use crate::exception::Exception;
+    use r32i_asm::{riscv_asm, rv32e_asm};
+    use crate::register::alias;
     use crate::register::alias::*;
+    use crate::register::csr::{SATP, SEPC};
 
     #[test]
     fn test_j() {
@@ -17,7 +37,7 @@ mod test_code {
             addi a1, a1, 2;
             stop;
         };
-        c.set_code_segment(code).run();
+        c.set_code_segment(code).run().unwrap();
         println!("a0: {}, a1: {}", c.registers.get(a0), c.registers.get(a1));
         assert!(c.registers.a(0) == &0 && c.registers.a(1) == &2)
     }
@@ -27,14 +47,14 @@ mod test_code {
         let mut c = EmulatorContext::default();
         let code = riscv_asm! {
         _start:
-            addi ra, ra, L_return;
+            addi ra, zero, 12;    // byte address of L_return
             jr ra;
             li a0, 1;
         L_return:
             li a0, 42;
             stop;
         };
-        c.set_code_segment(code).run();
+        c.set_code_segment(code).run().unwrap();
         println!("a0: {}", c.registers.get(a0));
         assert_eq!(c.registers.a(0), &42)
     }
@@ -53,7 +73,7 @@ mod test_code {
             stop;
         };
 
-        c.set_code_segment(code).run();
+        c.set_code_segment(code).run().unwrap();
         println!(
             "t0: {}, t1: {}, t2: {}",
             c.registers.get(t0),
@@ -73,7 +93,7 @@ mod test_code {
             // 预期结果：a1=4
             stop;
         };
-        c.set_code_segment(code).run();
+        c.set_code_segment(code).run().unwrap();
         assert_eq!(c.registers.a(1), &4)
     }
 
@@ -92,7 +112,7 @@ mod test_code {
         L_end:
             stop;
         };
-        c.set_code_segment(code).run();
+        c.set_code_segment(code).run().unwrap();
         assert_eq!(c.registers.a(2), &1)
     }
 
@@ -110,7 +130,7 @@ mod test_code {
             li a2, 1;
         L_end:
         };
-        c.set_code_segment(code).run();
+        c.set_code_segment(code).run().unwrap();
         assert_eq!(c.registers.a(2), &1)
     }
 
@@ -131,7 +151,2220 @@ mod test_code {
             // 预期结果：a0=2
             stop;
         };
-        c.set_code_segment(code).run();
+        c.set_code_segment(code).run().unwrap();
         assert_eq!(c.registers.a(0), &2)
     }
+
+    #[test]
+    fn test_illegal_instruction_trap() {
+        let mut c = EmulatorContext::default();
+        let code = riscv_asm! {
+        _start:
+            li a0, 1;
+            0xFFFFFFFF;
+            li a0, 2;
+            stop;
+        };
+        let trap = c.set_code_segment(code).run().unwrap_err();
+        assert_eq!(trap.pc, 4);
+        assert_eq!(trap.instruction, 0xFFFFFFFF);
+        assert_eq!(trap.exception, Exception::IllegalInstruction(0xFFFFFFFF));
+        assert_eq!(c.registers.a(0), &1)
+    }
+
+    #[test]
+    fn test_memory_faults() {
+        let mut c = EmulatorContext::default();
+        let code = riscv_asm! {
+        _start:
+            li t0, 2;
+            lw t1, 0(t0);      // 未对齐
+            stop;
+        };
+        let trap = c.set_code_segment(code).run().unwrap_err();
+        assert_eq!(trap.exception, Exception::LoadAddressMisaligned(2));
+        assert_eq!(trap.exception.code(), 4);
+
+        let mut c = EmulatorContext::default();
+        let code = riscv_asm! {
+        _start:
+            lui t0, 0x80000;
+            sw t1, 0(t0);      // 超出内存范围
+            stop;
+        };
+        let trap = c.set_code_segment(code).run().unwrap_err();
+        assert_eq!(trap.pc, 4);
+        assert_eq!(trap.exception, Exception::StoreAccessFault(0x80000000));
+    }
+
+    #[test]
+    fn test_csr() {
+        let mut c = EmulatorContext::default();
+        let code = riscv_asm! {
+        _start:
+            li t0, 42;
+            csrw mscratch, t0;
+            csrrs a0, mscratch, zero;   // a0 = 42
+            csrrsi a1, mscratch, 1;     // a1 = 42, mscratch = 43
+            csrr a1, mscratch;          // a1 = 43
+            li t0, -1;
+            csrw mstatus, t0;
+            csrr a2, mstatus;           // MPP/MPRV/SUM/MXR 和 S 态的位可写, SD 只读
+            li t0, 0x103;
+            csrw mepc, t0;
+            csrr a3, mepc;              // 最低位为 0
+            csrr a4, misa;
+            csrr a5, mhartid;
+            stop;
+        };
+        c.set_code_segment(code).run().unwrap();
+        assert_eq!(c.registers.get(a0), 42);
+        assert_eq!(c.registers.get(a1), 43);
+        assert_eq!(c.registers.get(a2), 0x800E_79AA);
+        assert_eq!(c.registers.get(a3), 0x102);
+        assert_eq!(c.registers.get(a4), 0x4014_112D);
+        assert_eq!(c.registers.get(a5), 0);
+    }
+
+    #[test]
+    fn test_csr_illegal() {
+        let code = riscv_asm! {
+        _start:
+            csrw mhartid, t0;           // 只读
+            stop;
+        };
+        let trap = EmulatorContext::default()
+            .set_code_segment(code)
+            .run()
+            .unwrap_err();
+        assert_eq!(trap.exception, Exception::IllegalInstruction(code[0]));
+
+        let code = riscv_asm! {
+        _start:
+            csrr a0, 0x7C0;             // 未实现
+            stop;
+        };
+        let trap = EmulatorContext::default()
+            .set_code_segment(code)
+            .run()
+            .unwrap_err();
+        assert_eq!(trap.exception, Exception::IllegalInstruction(code[0]));
+    }
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_host_syscalls() {
+        let mut c = EmulatorContext::default();
+        let stdout = SharedBuffer::default();
+        let code = riscv_asm! {
+        _start:
+            li a0, 0;
+            li a1, 0x100;
+            li a2, 3;
+            li a7, 63;
+            ecall;              // read(0, 0x100, 3)
+            li a0, 1;
+            li a1, 0x100;
+            li a2, 3;
+            li a7, 64;
+            ecall;              // write(1, 0x100, 3)
+            li a0, 1;
+            li a1, 0;
+            li a2, 3;
+            li a7, 64;
+            ecall;              // write(1, "hi\n", 3)
+            li a0, 7;
+            li a7, 93;
+            ecall;              // exit(7)
+            li a0, 0;
+            stop;
+        };
+        c.set_data_segment(&[0x000A6968])
+            .set_code_segment(code)
+            .set_syscall_handler(HostSyscalls::new(
+                &b"abc"[..],
+                stdout.clone(),
+                std::io::sink(),
+            ))
+            .run()
+            .unwrap();
+        assert_eq!(c.exit_code(), Some(7));
+        assert_eq!(c.registers.a(0), &7);
+        assert_eq!(&stdout.0.lock().unwrap()[..], b"abchi\n");
+    }
+
+    #[test]
+    fn test_ecall_without_handler() {
+        let code = riscv_asm! {
+        _start:
+            li a0, 1;
+            ebreak;
+            stop;
+        };
+        let mut c = EmulatorContext::default();
+        let trap = c.set_code_segment(code).run().unwrap_err();
+        assert_eq!(trap.exception, Exception::Breakpoint(4));
+        assert_eq!(c.exit_code(), None);
+
+        let code = riscv_asm! {
+        _start:
+            ecall;
+        };
+        let trap = EmulatorContext::default()
+            .set_code_segment(code)
+            .run()
+            .unwrap_err();
+        assert_eq!(trap.exception, Exception::EnvironmentCallFromMMode);
+        assert_eq!(trap.exception.code(), 11);
+    }
+
+    #[test]
+    fn test_trap_handler() {
+        let mut c = EmulatorContext::default();
+        let code = riscv_asm! {
+        _start:
+            li t0, 32;              // handler 的地址
+            csrw mtvec, t0;
+            csrsi mstatus, 8;       // MIE = 1
+            li a0, 1;
+            ecall;
+            addi a0, a0, 10;        // mret 返回到这里
+            csrr a4, mstatus;
+            stop;
+        handler:
+            csrr a1, mcause;
+            csrr a2, mepc;
+            csrr a3, mstatus;
+            addi a2, a2, 4;
+            csrw mepc, a2;
+            mret;
+        };
+        c.set_code_segment(code).run().unwrap();
+        assert_eq!(c.registers.get(a0), 11);
+        assert_eq!(c.registers.get(a1), 11);
+        assert_eq!(c.registers.get(a2), 20);
+        assert_eq!(c.registers.get(a3), 0x1880); // MPIE = 1, MIE = 0
+        assert_eq!(c.registers.get(a4), 0x88); // MIE 恢复, MPP 回到 U
+    }
+
+    #[test]
+    fn test_trap_handler_illegal_instruction() {
+        let mut c = EmulatorContext::default();
+        let code = riscv_asm! {
+        _start:
+            li t0, 21;              // 向量模式, base = 20
+            csrw mtvec, t0;
+            0xFFFFFFFF;
+            stop;
+            nop;
+        handler:
+            csrr a1, mcause;
+            csrr a2, mtval;
+            csrr a3, mepc;
+            stop;
+        };
+        c.set_code_segment(code).run().unwrap();
+        // 异常总是跳到 base
+        assert_eq!(c.registers.get(a1), 2);
+        assert_eq!(c.registers.get(a2), 0xFFFFFFFF);
+        assert_eq!(c.registers.get(a3), 8);
+    }
+
+    #[test]
+    fn test_timer_interrupt() {
+        let mut c = EmulatorContext::default();
+        let code = riscv_asm! {
+        _start:
+            li t0, 64;              // handler 的地址
+            csrw mtvec, t0;
+            lui t2, 0x200C;
+            lw t3, -8(t2);          // mtime
+            addi t3, t3, 20;
+            lui t1, 0x2004;         // mtimecmp
+            sw zero, 4(t1);
+            sw t3, 0(t1);           // mtimecmp = mtime + 20
+            li t0, 128;
+            csrw mie, t0;           // MTIE
+            csrsi mstatus, 8;       // MIE
+        L_wait:
+            addi a0, a0, 1;
+            beq s1, zero, L_wait;
+            stop;
+            nop;
+            nop;
+        handler:
+            lw a2, -8(t2);          // 进入 handler 时的 mtime
+            csrr a1, mcause;
+            li t0, -1;
+            sw t0, 4(t1);           // 关闭定时器
+            li s1, 1;
+            mret;
+        };
+        c.set_code_segment(code).run().unwrap();
+        assert_eq!(c.registers.get(a1), 0x8000_0007);
+        // 每条指令一个 tick, 结果是确定的
+        assert_eq!(c.registers.get(a2), 23);
+        assert_eq!(c.registers.get(a0), 7);
+    }
+
+    #[test]
+    fn test_clock_source() {
+        let mut c = EmulatorContext::default();
+        let code = riscv_asm! {
+        _start:
+            lui t1, 0x2000;
+            li t0, 1;
+            sw t0, 0(t1);           // msip = 1
+            lw a0, 0(t1);
+            lui t2, 0x200C;
+            lw a1, -8(t2);          // mtime
+            stop;
+        };
+        c.set_code_segment(code);
+        for _ in 0..3 {
+            c.step();
+        }
+        // 换时钟源不会丢掉 CLINT 的状态
+        c.set_clock_source(ClockSource::Instructions);
+        c.run().unwrap();
+        assert_eq!(c.registers.get(a0), 1);
+        assert_eq!(c.registers.get(a1), 5);
+    }
+
+    #[test]
+    fn test_software_interrupt() {
+        let mut c = EmulatorContext::default();
+        let code = riscv_asm! {
+        _start:
+            li t0, 32;
+            csrw mtvec, t0;
+            li t0, 8;
+            csrw mie, t0;           // MSIE
+            csrsi mstatus, 8;
+            lui t1, 0x2000;
+            li t0, 1;
+            sw t0, 0(t1);           // msip = 1
+        handler:
+            csrr a1, mcause;
+            csrr a2, mip;
+            stop;
+        };
+        c.set_code_segment(code).run().unwrap();
+        assert_eq!(c.registers.get(a1), 0x8000_0003);
+        assert_eq!(c.registers.get(a2), 8);
+    }
+
+    #[test]
+    fn test_sub_word_loads() {
+        let mut c = EmulatorContext::default();
+        let code = riscv_asm! {
+        _start:
+            li t0, 256;
+            lui t1, 0x80817;
+            addi t1, t1, 0x701;     // 0x80817701
+            sw t1, 0(t0);
+            lb a0, 2(t0);
+            lbu a1, 2(t0);
+            lh a2, 2(t0);
+            lhu a3, 2(t0);
+            lh a4, 0(t0);
+            sh t1, 2(t0);           // 高半字 = 0x7701
+            lw a5, 0(t0);
+            stop;
+        };
+        c.set_code_segment(code).run().unwrap();
+        assert_eq!(c.registers.get(a0), 0xFFFF_FF81);
+        assert_eq!(c.registers.get(a1), 0x81);
+        assert_eq!(c.registers.get(a2), 0xFFFF_8081);
+        assert_eq!(c.registers.get(a3), 0x8081);
+        assert_eq!(c.registers.get(a4), 0x7701);
+        assert_eq!(c.registers.get(a5), 0x7701_7701);
+    }
+
+    #[test]
+    fn test_mul_div() {
+        let code = riscv_asm! {
+        _start:
+            li t0, -7;
+            li t1, 2;
+            lui t2, 0x80000;        // i32::MIN
+            li t3, -1;
+            mul a0, t0, t1;
+            div a1, t0, t1;         // 向零取整
+            rem a2, t0, t1;
+            divu a3, t0, zero;      // 除以 0 不陷入
+            rem a4, t0, zero;
+            div a5, t2, t3;         // 溢出
+            rem a6, t2, t3;
+            mulh a7, t2, t2;
+            mulhu s2, t3, t3;
+            mulhsu s3, t3, t3;
+            divu s4, t0, t1;
+            remu s5, t0, t1;
+            stop;
+        };
+        // 两种执行方式结果一致
+        for mode in [ExecutionMode::Interpreter, ExecutionMode::Blocks] {
+            let mut c = EmulatorContext::default();
+            c.set_execution_mode(mode).set_code_segment(code).run().unwrap();
+            assert_eq!(c.registers.get(a0), -14i32 as u32 as u64);
+            assert_eq!(c.registers.get(a1), -3i32 as u32 as u64);
+            assert_eq!(c.registers.get(a2), -1i32 as u32 as u64);
+            assert_eq!(c.registers.get(a3), u32::MAX as u64);
+            assert_eq!(c.registers.get(a4), -7i32 as u32 as u64);
+            assert_eq!(c.registers.get(a5), 0x8000_0000);
+            assert_eq!(c.registers.get(a6), 0);
+            assert_eq!(c.registers.get(a7), 0x4000_0000);
+            assert_eq!(c.registers.get(s2), 0xFFFF_FFFE);
+            assert_eq!(c.registers.get(s3), 0xFFFF_FFFF);
+            assert_eq!(c.registers.get(s4), 0x7FFF_FFFC);
+            assert_eq!(c.registers.get(s5), 1);
+        }
+    }
+
+    #[test]
+    fn test_atomics() {
+        let code = riscv_asm! {
+        _start:
+            li t0, 256;
+            li t1, 5;
+            sw t1, 0(t0);
+            lr.w a0, (t0);              // a0 = 5
+            addi t2, a0, 1;
+            sc.w a1, t2, (t0);          // 成功, a1 = 0, 内存 = 6
+            sc.w a2, t2, (t0);          // 保留已失效, a2 = 1
+            lr.w t3, (t0);
+            sw zero, 0(t0);             // 写入使保留失效
+            sc.w a3, t2, (t0);          // a3 = 1, 内存 = 0
+            li t1, -3;
+            amoadd.w a4, t1, (t0);      // a4 = 0, 内存 = -3
+            li t1, 2;
+            amomin.w a5, t1, (t0);      // a5 = -3, 内存 = -3
+            amominu.w a6, t1, (t0);     // a6 = -3, 内存 = 2
+            li t1, 12;
+            amoor.w.aqrl a7, t1, (t0);  // a7 = 2, 内存 = 14
+            amoswap.w s2, zero, (t0);   // s2 = 14, 内存 = 0
+            lw s3, 0(t0);
+            stop;
+        };
+        for mode in [ExecutionMode::Interpreter, ExecutionMode::Blocks] {
+            let mut c = EmulatorContext::default();
+            c.set_execution_mode(mode).set_code_segment(code).run().unwrap();
+            assert_eq!(c.registers.get(a0), 5);
+            assert_eq!(c.registers.get(a1), 0);
+            assert_eq!(c.registers.get(a2), 1);
+            assert_eq!(c.registers.get(a3), 1);
+            assert_eq!(c.registers.get(a4), 0);
+            assert_eq!(c.registers.get(a5), -3i32 as u32 as u64);
+            assert_eq!(c.registers.get(a6), -3i32 as u32 as u64);
+            assert_eq!(c.registers.get(a7), 2);
+            assert_eq!(c.registers.get(s2), 14);
+            assert_eq!(c.registers.get(s3), 0);
+        }
+        assert_eq!(Instruction(code[16]).to_string(), "amoor.w.aqrl a7, t1, (t0)");
+        assert_eq!(Instruction(code[3]).to_string(), "lr.w    a0, (t0)");
+
+        // amo 的错误按 store 报告
+        let code = riscv_asm! {
+        _start:
+            li t0, 258;
+            amoadd.w a0, zero, (t0);
+            stop;
+        };
+        let trap = EmulatorContext::default()
+            .set_code_segment(code)
+            .run()
+            .unwrap_err();
+        assert_eq!(trap.exception, Exception::StoreAddressMisaligned(258));
+
+        // amo 既读又写, 读观察点和写观察点都要停下
+        let code = riscv_asm! {
+        _start:
+            li t0, 256;
+            li t1, 5;
+            amoadd.w a0, t1, (t0);
+            stop;
+        };
+        for kind in [WatchKind::Read, WatchKind::Write] {
+            let mut c = EmulatorContext::default();
+            c.set_code_segment(code).set_watchpoint(256..260, kind);
+            assert_eq!(
+                c.run(),
+                Ok(StoppedAt::Watch {
+                    kind,
+                    addr: 256,
+                    old: Some(0),
+                    new: 5
+                })
+            );
+        }
+        let mut c = EmulatorContext::default();
+        c.set_code_segment(code);
+        c.step();
+        c.step();
+        let amo = MemoryAccess::Amo {
+            address: 256,
+            size: 4,
+            old: 0,
+            new: 5,
+        };
+        assert_eq!(c.step().memory, Some(amo));
+    }
+
+    #[test]
+    fn test_compressed() {
+        let code = riscv_asm! {
+        _start:
+            c.li a0, 0;
+            li a1, 10;                  // 跨两个字
+        L_loop:
+            c.add a0, a1;
+            c.addi a1, -1;
+            c.bnez a1, L_loop;
+            c.mv a2, a0;
+            c.slli a2, 2;
+            addi sp, sp, -16;
+            c.swsp a2, 4(sp);
+            c.lwsp a3, 4(sp);
+            c.jal L_func;
+            c.j L_end;
+        L_func:
+            c.lui a4, 1;
+            c.srli a4, 4;
+            c.jr ra;
+        L_end:
+            c.ebreak;
+        };
+        let mut snapshots = Vec::new();
+        for mode in [ExecutionMode::Interpreter, ExecutionMode::Blocks] {
+            let mut c = EmulatorContext::default();
+            let trap = c.set_execution_mode(mode).set_code_segment(code).run().unwrap_err();
+            assert_eq!(trap.exception, Exception::Breakpoint(34));
+            assert_eq!(trap.instruction, 0x9002);
+            assert_eq!(c.registers.get(a0), 55);
+            assert_eq!(c.registers.get(a1), 0);
+            assert_eq!(c.registers.get(a2), 220);
+            assert_eq!(c.registers.get(a3), 220);
+            assert_eq!(c.registers.get(a4), 0x100);
+            assert_eq!(c.registers.get(ra), 26);
+            snapshots.push(c.snapshot());
+        }
+        assert_eq!(snapshots[0], snapshots[1]);
+        // 按展开后的指令反汇编
+        assert_eq!(Instruction(code[0] & 0xFFFF).to_string(), "addi    a0, zero, 0");
+        assert_eq!(Instruction(0x9002).to_string(), "ebreak");
+
+        // c.andi 的立即数是符号扩展的
+        let code = riscv_asm! {
+        _start:
+            li a0, -1;
+            li a1, 127;
+            c.andi a0, -4;              // a0 = 0xFFFF_FFFC
+            c.andi a1, -32;             // a1 = 0x60
+            stop;
+        };
+        for mode in [ExecutionMode::Interpreter, ExecutionMode::Blocks] {
+            let mut c = EmulatorContext::default();
+            c.set_execution_mode(mode).set_code_segment(code).run().unwrap();
+            assert_eq!(c.registers.get(a0), 0xFFFF_FFFC);
+            assert_eq!(c.registers.get(a1), 0x60);
+        }
+        assert_eq!(Instruction(code[2] & 0xFFFF).to_string(), "andi    a0, a0, -4");
+
+        // 保留的编码是非法指令
+        let code = riscv_asm! {
+        _start:
+            c.nop;
+            c.nop;
+            0x00000004;                 // c.addi4spn, 立即数为 0
+        };
+        let trap = EmulatorContext::default()
+            .set_code_segment(code)
+            .run()
+            .unwrap_err();
+        assert_eq!(trap.pc, 4);
+        assert_eq!(trap.exception, Exception::IllegalInstruction(0x0004));
+
+        // 浮点的压缩访存指令
+        let code = riscv_asm! {
+        _start:
+            lui t0, 0x2;
+            csrs mstatus, t0;           // 打开 FPU
+            li s0, 256;
+            li a0, 7;
+            sw a0, 0(s0);
+            sw a0, 4(s0);
+            c.flw fa0, 0(s0);
+            c.fsw fa0, 8(s0);
+            c.fld fa1, 0(s0);
+            c.fsd fa1, 16(s0);
+            addi sp, sp, -16;
+            c.fswsp fa0, 0(sp);
+            c.flwsp fa2, 0(sp);
+            c.fsdsp fa1, 8(sp);
+            c.fldsp fa3, 8(sp);
+            fsd fa3, 24(s0);
+            lw a1, 8(s0);               // a1 = 7
+            lw a2, 20(s0);              // a2 = 7
+            fmv.x.w a3, fa2;            // a3 = 7
+            lw a4, 28(s0);              // a4 = 7
+            stop;
+        };
+        for mode in [ExecutionMode::Interpreter, ExecutionMode::Blocks] {
+            let mut c = EmulatorContext::default();
+            c.set_execution_mode(mode).set_code_segment(code).run().unwrap();
+            for register in [a1, a2, a3, a4] {
+                assert_eq!(c.registers.get(register), 7);
+            }
+        }
+        assert_eq!(Instruction(code[7] & 0xFFFF).to_string(), "fld     fa1, 0(fp)");
+    }
+
+    #[test]
+    fn test_bitmanip() {
+        let code = riscv_asm! {
+        _start:
+            li a0, 5;
+            li a1, 100;
+            sh2add s2, a0, a1;          // s2 = 120
+            andn s3, a1, a0;            // s3 = 96
+            clz s4, a1;                 // s4 = 25
+            ctz s5, a1;                 // s5 = 2
+            cpop s6, a1;                // s6 = 3
+            li t1, -8;
+            min s7, t1, a0;             // s7 = -8
+            minu s8, t1, a0;            // s8 = 5
+            li t2, 0x80;
+            sext.b s9, t2;              // s9 = 0xFFFFFF80
+            zext.h s10, t1;             // s10 = 0xFFF8
+            rori s11, a0, 1;            // s11 = 0x80000002
+            rol t4, a0, a0;             // t4 = 160
+            orc.b a2, a1;               // a2 = 0xFF
+            lui t3, 0x12345;
+            rev8 a3, t3;                // a3 = 0x00503412
+            bseti a4, zero, 31;         // a4 = 0x80000000
+            bext a5, a1, a0;            // a5 = 1
+            binvi a6, a1, 2;            // a6 = 96
+            xnor a7, a0, a0;            // a7 = -1
+            stop;
+        };
+        let mut snapshots = Vec::new();
+        for mode in [ExecutionMode::Interpreter, ExecutionMode::Blocks] {
+            let mut c = EmulatorContext::default();
+            c.set_execution_mode(mode).set_code_segment(code).run().unwrap();
+            assert_eq!(c.registers.get(s2), 120);
+            assert_eq!(c.registers.get(s3), 96);
+            assert_eq!(c.registers.get(s4), 25);
+            assert_eq!(c.registers.get(s5), 2);
+            assert_eq!(c.registers.get(s6), 3);
+            assert_eq!(c.registers.get(s7), -8i32 as u32 as u64);
+            assert_eq!(c.registers.get(s8), 5);
+            assert_eq!(c.registers.get(s9), 0xFFFF_FF80);
+            assert_eq!(c.registers.get(s10), 0xFFF8);
+            assert_eq!(c.registers.get(s11), 0x8000_0002);
+            assert_eq!(c.registers.get(t4), 160);
+            assert_eq!(c.registers.get(a2), 0xFF);
+            assert_eq!(c.registers.get(a3), 0x0050_3412);
+            assert_eq!(c.registers.get(a4), 0x8000_0000);
+            assert_eq!(c.registers.get(a5), 1);
+            assert_eq!(c.registers.get(a6), 96);
+            assert_eq!(c.registers.get(a7), u32::MAX as u64);
+            snapshots.push(c.snapshot());
+        }
+        assert_eq!(snapshots[0], snapshots[1]);
+        assert_eq!(Instruction(code[2]).to_string(), "sh2add  s2, a0, a1");
+        assert_eq!(Instruction(code[12]).to_string(), "zext.h  s10, t1");
+        assert_eq!(Instruction(code[13]).to_string(), "rori    s11, a0, 1");
+        assert_eq!(Instruction(code[15]).to_string(), "orc.b   a2, a1");
+    }
+
+    #[test]
+    fn test_float() {
+        let code = riscv_asm! {
+        _start:
+            lui t0, 0x2;
+            csrs mstatus, t0;           // 打开 FPU
+            li t1, 7;
+            li t2, 2;
+            fcvt.s.w ft0, t1;           // 7.0
+            fcvt.s.w ft1, t2;           // 2.0
+            fdiv.s ft2, ft0, ft1;       // 3.5
+            fcvt.w.s a0, ft2, rtz;      // a0 = 3
+            fcvt.w.s a1, ft2;           // 按 frm (rne), a1 = 4
+            frflags a2;                 // 不精确, a2 = 1
+            fsflags zero;
+            fmadd.s ft3, ft0, ft1, ft1; // 16.0
+            fcvt.w.s a3, ft3;           // a3 = 16
+            fsub.s ft4, ft1, ft0;       // -5.0
+            fcvt.w.s a4, ft4, rdn;      // a4 = -5
+            flt.s a5, ft4, ft1;         // a5 = 1
+            fclass.s a6, ft4;           // 负的规格化数, a6 = 2
+            fcvt.d.s fa0, ft2;          // 3.5
+            fmul.d fa1, fa0, fa0;       // 12.25
+            fcvt.w.d a7, fa1, rup;      // a7 = 13, 不精确
+            fmv.x.w s2, fa1;            // 低 32 位, s2 = 0
+            fadd.s ft5, fa1, ft1;       // fa1 没有 NaN-boxing, 当作 NaN
+            fmv.x.w s3, ft5;            // s3 = 0x7fc00000
+            li t0, 256;
+            fsd fa1, 0(t0);
+            lw s4, 4(t0);               // s4 = 0x40288000
+            fsw ft2, 8(t0);
+            lw s5, 8(t0);               // s5 = 0x40600000
+            fld fa2, 0(t0);
+            feq.d s6, fa1, fa2;         // s6 = 1
+            flw ft6, 8(t0);
+            fmv.w.x ft7, zero;          // +0.0
+            fdiv.s ft8, ft6, ft7;       // 除以零
+            frflags s7;                 // 不精确 | 除以零, s7 = 9
+            li t1, 1;
+            fsrm t1;                    // rtz
+            fcvt.w.s s8, ft2;           // s8 = 3
+            csrr s9, fcsr;              // s9 = 0x29
+            csrr s10, mstatus;          // FS 为 Dirty, SD 置位
+            stop;
+        };
+        let mut snapshots = Vec::new();
+        for mode in [ExecutionMode::Interpreter, ExecutionMode::Blocks] {
+            let mut c = EmulatorContext::default();
+            c.set_execution_mode(mode).set_code_segment(code).run().unwrap();
+            assert_eq!(c.registers.get(a0), 3);
+            assert_eq!(c.registers.get(a1), 4);
+            assert_eq!(c.registers.get(a2), 1);
+            assert_eq!(c.registers.get(a3), 16);
+            assert_eq!(c.registers.get(a4), -5i32 as u32 as u64);
+            assert_eq!(c.registers.get(a5), 1);
+            assert_eq!(c.registers.get(a6), 2);
+            assert_eq!(c.registers.get(a7), 13);
+            assert_eq!(c.registers.get(s2), 0);
+            assert_eq!(c.registers.get(s3), 0x7fc0_0000);
+            assert_eq!(c.registers.get(s4), 0x4028_8000);
+            assert_eq!(c.registers.get(s5), 0x4060_0000);
+            assert_eq!(c.registers.get(s6), 1);
+            assert_eq!(c.registers.get(s7), 9);
+            assert_eq!(c.registers.get(s8), 3);
+            assert_eq!(c.registers.get(s9), 0x29);
+            assert_eq!(c.registers.get(s10), 0x8000_7800);
+            assert_eq!(c.float.get(ft8), 0xFFFF_FFFF_7F80_0000); // +inf
+            snapshots.push(c.snapshot());
+        }
+        assert_eq!(snapshots[0], snapshots[1]);
+        assert_eq!(Instruction(code[7]).to_string(), "fcvt.w.s a0, ft2, rtz");
+        assert_eq!(Instruction(code[11]).to_string(), "fmadd.s ft3, ft0, ft1, ft1");
+        assert_eq!(Instruction(code[28]).to_string(), "fld     fa2, 0(t0)");
+        assert_eq!(Instruction(code[26]).to_string(), "fsw     ft2, 8(t0)");
+
+        // FPU 关闭时浮点指令和 fcsr 都是非法指令
+        let code = riscv_asm! {
+        _start:
+            fadd.s ft0, ft0, ft0;
+            stop;
+        };
+        let trap = EmulatorContext::default()
+            .set_code_segment(code)
+            .run()
+            .unwrap_err();
+        assert_eq!(trap.exception, Exception::IllegalInstruction(code[0]));
+
+        let code = riscv_asm! {
+        _start:
+            frflags a0;
+            stop;
+        };
+        let trap = EmulatorContext::default()
+            .set_code_segment(code)
+            .run()
+            .unwrap_err();
+        assert_eq!(trap.exception, Exception::IllegalInstruction(code[0]));
+    }
+
+    struct Scratch([u32; 2]);
+
+    impl Device for Scratch {
+        fn read_word(&mut self, offset: Address) -> Option<u32> {
+            self.0.get(offset as usize >> 2).copied()
+        }
+
+        fn write_word(&mut self, offset: Address, value: u32) -> Option<()> {
+            *self.0.get_mut(offset as usize >> 2)? = value;
+            Some(())
+        }
+    }
+
+    #[test]
+    fn test_device_bus() {
+        let mut c = EmulatorContext::default();
+        let code = riscv_asm! {
+        _start:
+            lui t0, 0x10000;        // Scratch 的基地址
+            li t1, 0x55;
+            sw t1, 0(t0);
+            sb t1, 5(t0);
+            lw a0, 0(t0);
+            lbu a1, 5(t0);
+            lw a2, 4(t0);
+            lw a3, 8(t0);           // 超出设备范围
+            stop;
+        };
+        let trap = c
+            .map_device(0x1000_0000, 8, Scratch([0; 2]))
+            .set_code_segment(code)
+            .run()
+            .unwrap_err();
+        assert_eq!(c.registers.get(a0), 0x55);
+        assert_eq!(c.registers.get(a1), 0x55);
+        assert_eq!(c.registers.get(a2), 0x5500);
+        assert_eq!(trap.pc, 28);
+        assert_eq!(trap.exception, Exception::LoadAccessFault(0x1000_0008));
+    }
+
+    #[test]
+    fn test_uart_echo() {
+        let mut c = EmulatorContext::default();
+        let console = BufferBackend::default();
+        console.push_input(b"ok");
+        let code = riscv_asm! {
+        _start:
+            lui s0, 0x10000;        // UART 基地址
+            li s1, 2;               // 回显两个字节
+        L_wait_rx:
+            lbu t0, 5(s0);          // LSR
+            andi t0, t0, 1;         // DR
+            beq t0, zero, L_wait_rx;
+            lbu t1, 0(s0);          // RBR
+        L_wait_tx:
+            lbu t0, 5(s0);
+            andi t0, t0, 0x20;      // THRE
+            beq t0, zero, L_wait_tx;
+            sb t1, 0(s0);           // THR
+            addi s1, s1, -1;
+            bne s1, zero, L_wait_rx;
+            lbu a0, 5(s0);          // 输入已读完
+            stop;
+        };
+        c.map_device(UART_BASE, UART_SIZE, Uart::new(console.clone()))
+            .set_code_segment(code)
+            .run()
+            .unwrap();
+        assert_eq!(console.output(), b"ok");
+        assert_eq!(c.registers.get(a0), 0x60);
+    }
+
+    #[test]
+    fn test_uart_rx_interrupt() {
+        let mut c = EmulatorContext::default();
+        let console = BufferBackend::default();
+        let code = riscv_asm! {
+        _start:
+            li t0, 44;
+            csrw mtvec, t0;
+            lui s0, 0x10000;
+            li t0, 1;
+            sb t0, 1(s0);           // IER: 接收中断
+            li t0, 2047;
+            addi t0, t0, 1;         // 0x800, MEIE
+            csrw mie, t0;
+            csrsi mstatus, 8;
+        L_spin:
+            j L_spin;
+            nop;
+        handler:
+            csrr a1, mcause;
+            lbu a2, 2(s0);          // IIR
+            lbu a0, 0(s0);          // RBR
+            stop;
+        };
+        console.push_input(b"x");
+        c.map_device(UART_BASE, UART_SIZE, Uart::new(console))
+            .set_code_segment(code)
+            .run()
+            .unwrap();
+        assert_eq!(c.registers.get(a1), 0x8000_000B);
+        assert_eq!(c.registers.get(a2), 0x04);
+        assert_eq!(c.registers.get(a0), b'x' as u64);
+    }
+
+    #[test]
+    fn test_step() {
+        let mut c = EmulatorContext::default();
+        let code = riscv_asm! {
+        _start:
+            li t0, 256;
+            li t1, -3;
+            sw t1, 4(t0);
+            lb a0, 4(t0);
+            lw a1, 2(t0);           // 未对齐
+        };
+        c.set_code_segment(code);
+
+        let outcome = c.step();
+        assert_eq!((outcome.pc, outcome.status), (0, StepStatus::Retired));
+        let write = RegisterWrite {
+            register: t0,
+            old: 0,
+            new: 256,
+        };
+        assert_eq!(outcome.register, Some(write));
+        assert_eq!(outcome.memory, None);
+
+        c.step();
+        let outcome = c.step();
+        assert_eq!(outcome.register, None);
+        let store = MemoryAccess::Store {
+            address: 260,
+            size: 4,
+            old: Some(0),
+            new: 0xFFFF_FFFD,
+        };
+        assert_eq!(outcome.memory, Some(store));
+
+        let outcome = c.step();
+        assert_eq!(outcome.register.map(|w| (w.register, w.new)), Some((a0, 0xFFFF_FFFD)));
+        let load = MemoryAccess::Load {
+            address: 260,
+            size: 1,
+            value: 0xFD,
+        };
+        assert_eq!(outcome.memory, Some(load));
+
+        let outcome = c.step();
+        assert_eq!(outcome.pc, 16);
+        assert_eq!(outcome.register, None);
+        let StepStatus::Trap(trap) = outcome.status else {
+            panic!("expected a trap, got {:?}", outcome.status);
+        };
+        assert_eq!(trap.exception, Exception::LoadAddressMisaligned(258));
+    }
+
+    #[test]
+    fn test_step_halted() {
+        let mut c = EmulatorContext::default();
+        let code = riscv_asm! {
+        _start:
+            stop;
+        };
+        c.set_code_segment(code);
+        assert_eq!(c.step().status, StepStatus::Halted);
+        let outcome = c.step();
+        assert_eq!(outcome.status, StepStatus::Halted);
+        assert_eq!(outcome.instruction.0, 0);
+        assert_eq!(outcome.pc, 4);
+    }
+
+    #[test]
+    fn test_breakpoint() {
+        let mut c = EmulatorContext::default();
+        let code = riscv_asm! {
+        _start:
+            li a0, 0;
+        L_loop:
+            addi a0, a0, 1;
+            li t0, 3;
+            blt a0, t0, L_loop;
+            stop;
+        };
+        c.set_code_segment(code).set_breakpoint(4);
+        assert_eq!(c.run(), Ok(StoppedAt::Breakpoint(4)));
+        assert_eq!(c.registers.get(a0), 0);
+        // 从断点处继续执行
+        assert_eq!(c.run(), Ok(StoppedAt::Breakpoint(4)));
+        assert_eq!(c.registers.get(a0), 1);
+        assert!(c.remove_breakpoint(4));
+        assert_eq!(c.run(), Ok(StoppedAt::Halted));
+        assert_eq!(c.registers.get(a0), 3);
+
+        // 第一条指令上的断点在执行前就停下
+        let mut c = EmulatorContext::default();
+        c.set_code_segment(code).set_breakpoint(0);
+        assert_eq!(c.run(), Ok(StoppedAt::Breakpoint(0)));
+        assert_eq!(c.retired(), 0);
+        assert_eq!(c.run(), Ok(StoppedAt::Halted));
+        assert_eq!(c.registers.get(a0), 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "x32 is not an integer register")]
+    fn test_watch_register_out_of_range() {
+        let mut c = EmulatorContext::default();
+        // 超出 x31 的取消监视什么也不做
+        c.unwatch_register(40);
+        c.watch_register(32);
+    }
+
+    #[test]
+    fn test_watchpoint() {
+        let mut c = EmulatorContext::default();
+        let code = riscv_asm! {
+        _start:
+            li t0, 256;
+            li t1, 7;
+            sw t1, 0(t0);
+            sw t1, 8(t0);
+            lhu a0, 10(t0);
+            li t1, 9;
+            sb t1, 9(t0);
+            stop;
+        };
+        c.set_code_segment(code)
+            .set_watchpoint(264..266, WatchKind::Write);
+        let stopped = c.run();
+        assert_eq!(
+            stopped,
+            Ok(StoppedAt::Watch {
+                kind: WatchKind::Write,
+                addr: 264,
+                old: Some(0),
+                new: 7
+            })
+        );
+        // 读不触发写观察点
+        let stopped = c.run();
+        assert_eq!(
+            stopped,
+            Ok(StoppedAt::Watch {
+                kind: WatchKind::Write,
+                addr: 265,
+                old: Some(0),
+                new: 9
+            })
+        );
+
+        let mut c = EmulatorContext::default();
+        c.set_code_segment(code)
+            .set_watchpoint(266..268, WatchKind::Read)
+            .watch_register(t1);
+        let stopped = c.run();
+        assert_eq!(
+            stopped,
+            Ok(StoppedAt::RegisterWatch {
+                register: t1,
+                old: 0,
+                new: 7
+            })
+        );
+        c.unwatch_register(t1);
+        let stopped = c.run();
+        assert_eq!(
+            stopped,
+            Ok(StoppedAt::Watch {
+                kind: WatchKind::Read,
+                addr: 266,
+                old: Some(0),
+                new: 0
+            })
+        );
+        assert_eq!(c.run(), Ok(StoppedAt::Halted));
+    }
+
+    fn gdb_request(stream: &mut TcpStream, packet: &str) -> String {
+        let sum = packet.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+        write!(stream, "${packet}#{sum:02x}").unwrap();
+        let mut reply = Vec::new();
+        let mut byte = [0];
+        // 跳过 ack
+        while byte[0] != b'$' {
+            stream.read_exact(&mut byte).unwrap();
+        }
+        loop {
+            stream.read_exact(&mut byte).unwrap();
+            if byte[0] == b'#' {
+                break;
+            }
+            reply.push(byte[0]);
+        }
+        stream.read_exact(&mut [0; 2]).unwrap();
+        stream.write_all(b"+").unwrap();
+        String::from_utf8(reply).unwrap()
+    }
+
+    #[test]
+    fn test_gdb_server() {
+        const CODE: &[u32] = riscv_asm! {
+        _start:
+            li a0, 0;
+        L_loop:
+            addi a0, a0, 1;
+            li t0, 3;
+            blt a0, t0, L_loop;
+            li a1, 256;
+            sw a0, 0(a1);
+            lw a2, 0(a1);
+            stop;
+        };
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let mut c = EmulatorContext::default();
+            c.set_code_segment(CODE).set_undo_depth(16).serve_gdb(listener)
+        });
+        let mut gdb = TcpStream::connect(address).unwrap();
+        gdb.set_nodelay(true).unwrap();
+        let mut request = |packet: &str| gdb_request(&mut gdb, packet);
+
+        let supported = request("qSupported:swbreak+");
+        assert!(supported.contains("qXfer:features:read+"));
+        assert!(supported.contains("ReverseContinue+"));
+        let xml = request("qXfer:features:read:target.xml:0,ffff");
+        assert!(xml.starts_with("l<?xml") && xml.contains("riscv:rv32"));
+        assert_eq!(request("?"), "S05");
+
+        assert_eq!(request("Z0,8,4"), "OK");
+        assert_eq!(request("c"), "S05");
+        assert_eq!(request("p20"), "08000000");   // pc
+        assert_eq!(request("pa"), "01000000");    // a0
+        assert_eq!(request("z0,8,4"), "OK");
+
+        assert_eq!(request("Z2,100,4"), "OK");
+        assert_eq!(request("c"), "T05watch:100;");
+        assert_eq!(request("m100,4"), "03000000");
+        assert_eq!(request("M100,4:2a000000"), "OK");
+        assert_eq!(request("m100,4"), "2a000000");
+
+        assert_eq!(request("Pa=07000000"), "OK");
+        let registers = request("g");
+        assert_eq!(registers.len(), 33 * 8);
+        assert_eq!(&registers[80..88], "07000000");
+        assert_eq!(&registers[256..], "18000000");
+
+        // Z2/Z3/Z4 各自对应 watch/rwatch/awatch
+        assert_eq!(request("z2,100,4"), "OK");
+        assert_eq!(request("Z3,100,4"), "OK");
+        assert_eq!(request("c"), "T05rwatch:100;");
+        assert_eq!(request("z3,100,4"), "OK");
+        assert_eq!(request("s"), "W00");
+        // 改写寄存器清空了撤销日志，只剩最后两步
+        assert_eq!(request("bs"), "S05");
+        assert_eq!(request("p20"), "1c000000");
+        assert_eq!(request("Z4,100,4"), "OK");
+        assert_eq!(request("bc"), "T05awatch:100;");
+        assert_eq!(request("z4,100,4"), "OK");
+        assert_eq!(request("bc"), "T05replaylog:begin;");
+        assert_eq!(request("s"), "S05");
+        assert_eq!(request("s"), "W00");
+        assert_eq!(request("D"), "OK");
+        server.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_spike_commit_log() {
+        let mut c = EmulatorContext::default();
+        let log = SharedBuffer::default();
+        let code = riscv_asm! {
+        _start:
+            li a0, 1;
+            sw a0, 4(sp);
+            lw a1, 4(sp);
+            stop;
+        };
+        c.set_code_segment(code)
+            .set_trace_sink(SpikeCommitLog::new(log.clone()).with_disassembly())
+            .run()
+            .unwrap();
+        let log = String::from_utf8(log.0.lock().unwrap().clone()).unwrap();
+        let expected = [
+            "core   0: 0x00000000 (0x00100513) addi    a0, zero, 1",
+            "core   0: 3 0x00000000 (0x00100513) x10 0x00000001",
+            "core   0: 0x00000004 (0x00a12223) sw      a0, 4(sp)",
+            "core   0: 3 0x00000004 (0x00a12223) mem 0x00000404 0x00000001",
+            "core   0: 0x00000008 (0x00412583) lw      a1, 4(sp)",
+            "core   0: 3 0x00000008 (0x00412583) x11 0x00000001 mem 0x00000404",
+            "core   0: 0x0000000c (0x00000000) stop",
+            "core   0: 3 0x0000000c (0x00000000)",
+        ];
+        assert_eq!(log.lines().collect::<Vec<_>>(), expected);
+
+        // CSR 和浮点寄存器的写入, 和 Spike 一样
+        let mut c = EmulatorContext::default();
+        let log = SharedBuffer::default();
+        let code = riscv_asm! {
+        _start:
+            lui t0, 0x2;
+            csrw mstatus, t0;           // 打开 FPU
+            li a0, 7;
+            csrrw a1, mscratch, a0;
+            fmv.w.x fa0, a0;
+            csrr a2, mscratch;          // 只读不写, 不记录
+            stop;
+        };
+        c.set_code_segment(code)
+            .set_trace_sink(SpikeCommitLog::new(log.clone()))
+            .run()
+            .unwrap();
+        let log = String::from_utf8(log.0.lock().unwrap().clone()).unwrap();
+        let expected = [
+            "core   0: 3 0x00000000 (0x000022b7) x5  0x00002000",
+            "core   0: 3 0x00000004 (0x30029073) c768_mstatus 0x00002000",
+            "core   0: 3 0x00000008 (0x00700513) x10 0x00000007",
+            "core   0: 3 0x0000000c (0x340515f3) x11 0x00000000 c832_mscratch 0x00000007",
+            "core   0: 3 0x00000010 (0xf0050553) f10 0xffffffff00000007",
+            "core   0: 3 0x00000014 (0x34002673) x12 0x00000007",
+            "core   0: 3 0x00000018 (0x00000000)",
+        ];
+        assert_eq!(log.lines().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn test_disassembly() {
+        let code = riscv_asm! {
+        _start:
+            lui a0, 0x80000;
+            srai t1, t0, 3;
+            sub s1, a2, a3;
+            bne a0, zero, _start;
+            jal ra, _start;
+            jalr zero, 0(ra);
+            lhu a4, -2(sp);
+            csrrw t0, mscratch, t0;
+            csrrsi zero, mstatus, 8;
+            ecall;
+        };
+        let expected = [
+            "lui     a0, 0x80000",
+            "srai    t1, t0, 3",
+            "sub     s1, a2, a3",
+            "bne     a0, zero, pc - 12",
+            "jal     ra, pc - 16",
+            "jalr    zero, 0(ra)",
+            "lhu     a4, -2(sp)",
+            "csrrw   t0, mscratch, t0",
+            "csrrsi  zero, mstatus, 8",
+            "ecall",
+        ];
+        let disassembly = code.map(|word| Instruction::from(word).to_string());
+        assert_eq!(disassembly, expected);
+        assert_eq!(Instruction::from(0xFFFF_FFFF).to_string(), "unknown");
+    }
+
+    #[test]
+    fn test_run_for() {
+        let mut c = EmulatorContext::default();
+        let code = riscv_asm! {
+        _start:
+            li a0, 0;
+        L_spin:
+            addi a0, a0, 1;
+            j L_spin;           // 永不停止
+        };
+        let report = c.set_code_segment(code).run_for(1001);
+        assert_eq!(report.stopped, Ok(StoppedAt::InstructionLimit));
+        assert_eq!(report.retired, 1001);
+        assert_eq!(c.registers.get(a0), 500);
+
+        let report = c.run_until(Instant::now() + Duration::from_millis(10));
+        assert_eq!(report.stopped, Ok(StoppedAt::Deadline));
+        assert!(report.retired > 0);
+        assert_eq!(c.retired(), 1001 + report.retired);
+
+        let mut c = EmulatorContext::default();
+        let code = riscv_asm! {
+        _start:
+            li a0, 1;
+            stop;
+        };
+        let report = c.set_code_segment(code).run_for(100);
+        assert_eq!(report.stopped, Ok(StoppedAt::Halted));
+        assert_eq!(report.retired, 2);
+    }
+
+    #[test]
+    fn test_const_step_limit() {
+        const SUM: u32 = ConstantEmulator::run_loop_limited::<_, 64>(riscv_asm! {
+            li a0, 0;
+            li a1, 10;
+        L_loop:
+            add a0, a0, a1;
+            addi a1, a1, -1;
+            bne a1, zero, L_loop;
+            stop;
+        });
+        assert_eq!(SUM, 55);
+    }
+
+    #[test]
+    fn test_lui() {
+        // lui 覆盖整个寄存器, 不保留原来的低 12 位
+        const A0: u32 = ConstantEmulator::run_loop(riscv_asm! {
+            li a0, 0x123;
+            lui a0, 0x1;
+            stop;
+        });
+        assert_eq!(A0, 0x1000);
+        let mut c = EmulatorContext::default();
+        let code = riscv_asm! {
+        _start:
+            li a0, 0x123;
+            lui a0, 0x1;
+            stop;
+        };
+        c.set_code_segment(code).run().unwrap();
+        assert_eq!(c.registers.get(a0), 0x1000);
+    }
+
+    #[test]
+    fn test_negative_immediate() {
+        // I 型立即数都要符号扩展, sltiu/xori/ori/andi 也一样
+        const ANDI: u32 = ConstantEmulator::run_loop(riscv_asm! {
+            li a1, -1;
+            andi a0, a1, -16;
+            stop;
+        });
+        assert_eq!(ANDI, 0xFFFF_FFF0);
+        let code = riscv_asm! {
+        _start:
+            li a0, -1;
+            andi a1, a0, -16;           // 0xFFFFFFF0
+            xori a2, zero, -1;          // -1
+            ori a3, zero, -2048;        // 0xFFFFF800
+            sltiu a4, a0, -1;           // 0, 两边都是全 1
+            sltiu a5, zero, -1;         // 1
+            stop;
+        };
+        for mode in [ExecutionMode::Interpreter, ExecutionMode::Blocks] {
+            let mut c = EmulatorContext::default();
+            c.set_execution_mode(mode).set_code_segment(code).run().unwrap();
+            assert_eq!(c.registers.get(a1), 0xFFFF_FFF0);
+            assert_eq!(c.registers.get(a2), 0xFFFF_FFFF);
+            assert_eq!(c.registers.get(a3), 0xFFFF_F800);
+            assert_eq!(c.registers.get(a4), 0);
+            assert_eq!(c.registers.get(a5), 1);
+
+            let mut c = EmulatorContext::default();
+            c.set_xlen(Xlen::X64).set_execution_mode(mode).set_code_segment(code).run().unwrap();
+            assert_eq!(c.registers.get(a1), 0xFFFF_FFFF_FFFF_FFF0);
+            assert_eq!(c.registers.get(a2), u64::MAX);
+            assert_eq!(c.registers.get(a3), 0xFFFF_FFFF_FFFF_F800);
+            assert_eq!(c.registers.get(a4), 0);
+            assert_eq!(c.registers.get(a5), 1);
+        }
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let mut c = EmulatorContext::default();
+        let code = riscv_asm! {
+        _start:
+            li t0, 256;
+            li a0, 5;
+            csrw mscratch, a0;      // 在此之后保存快照
+            lw a1, 0(t0);
+            add a1, a1, a0;
+            sw a1, 0(t0);
+            csrr a2, mscratch;
+            stop;
+        };
+        c.set_code_segment(code);
+        assert_eq!(c.run_for(3).stopped, Ok(StoppedAt::InstructionLimit));
+        let snapshot = c.snapshot();
+
+        for _ in 0..3 {
+            c.run().unwrap();
+            assert_eq!(c.registers.get(a1), 5);
+            assert_eq!(c.registers.get(a2), 5);
+            c.restore(&snapshot).unwrap();
+        }
+
+        let mut file = Vec::new();
+        snapshot.write_to(&mut file).unwrap();
+        let loaded = Snapshot::read_from(&mut file.as_slice()).unwrap();
+        assert_eq!(loaded, snapshot);
+
+        let mut fresh = EmulatorContext::default();
+        fresh.restore(&loaded).unwrap();
+        fresh.run().unwrap();
+        assert_eq!(fresh.registers.get(a1), 5);
+
+        let mut uart = EmulatorContext::default();
+        uart.map_device(UART_BASE, UART_SIZE, Uart::new(BufferBackend::default()));
+        assert!(matches!(
+            uart.restore(&snapshot),
+            Err(SnapshotError::DeviceMismatch(_))
+        ));
+        // 第二个设备的状态不对时, 已恢复的 CLINT 也要还原
+        let with_uart = uart.snapshot();
+        let mut scratch = EmulatorContext::default();
+        scratch
+            .map_device(UART_BASE, UART_SIZE, Scratch([0; 2]))
+            .set_code_segment(code);
+        scratch.run_for(3);
+        let before = scratch.snapshot();
+        assert!(matches!(
+            scratch.restore(&with_uart),
+            Err(SnapshotError::DeviceMismatch(UART_BASE))
+        ));
+        assert_eq!(scratch.snapshot(), before);
+        assert!(matches!(
+            Snapshot::read_from(&mut &file[..file.len() - 1]),
+            Err(SnapshotError::Corrupt)
+        ));
+        file[0] = b'X';
+        assert!(matches!(
+            Snapshot::read_from(&mut file.as_slice()),
+            Err(SnapshotError::BadMagic)
+        ));
+    }
+
+    #[test]
+    fn test_reverse_execution() {
+        let code = riscv_asm! {
+        _start:
+            li t0, 256;
+        L_loop:
+            lw a0, 0(t0);
+            addi a0, a0, 1;
+            sw a0, 0(t0);
+            csrw mscratch, a0;
+            li t1, 5;
+            blt a0, t1, L_loop;
+            csrr a1, mscratch;
+            stop;
+        };
+        let mut c = EmulatorContext::default();
+        c.set_code_segment(code).set_undo_depth(64);
+        assert_eq!(c.run(), Ok(StoppedAt::Halted));
+        assert_eq!(c.retired(), 33);
+        // 撤销 stop 和 csrr，再重新执行 csrr
+        assert!(c.step_back());
+        assert!(c.step_back());
+        assert_eq!(c.retired(), 31);
+        assert_eq!(c.registers.get(a1), 0);
+        assert_eq!(c.step().pc, 28);
+        assert_eq!(c.registers.get(a1), 5);
+
+        c.set_breakpoint(12);
+        assert_eq!(c.reverse_continue(), StoppedAt::Breakpoint(12));
+        assert_eq!(c.registers.get(a1), 0);
+        c.remove_breakpoint(12);
+        c.set_watchpoint(256..260, WatchKind::Write);
+        let stopped = StoppedAt::Watch {
+            kind: WatchKind::Write,
+            addr: 256,
+            old: Some(3),
+            new: 4,
+        };
+        assert_eq!(c.reverse_continue(), stopped);
+        assert_eq!(c.registers.get(a0), 4);
+        c.remove_watchpoint(256..260, WatchKind::Write);
+        assert_eq!(c.reverse_continue(), StoppedAt::HistoryStart);
+        assert_eq!(c.retired(), 0);
+        assert_eq!(c.registers.get(t0), 0);
+        assert_eq!(c.registers.get(a0), 0);
+
+        // 内存也已回滚，重新执行得到相同结果
+        assert_eq!(c.run(), Ok(StoppedAt::Halted));
+        assert_eq!(c.registers.get(a0), 5);
+        assert_eq!(c.registers.get(a1), 5);
+        assert_eq!(c.retired(), 33);
+
+        let mut c = EmulatorContext::default();
+        c.set_code_segment(code).set_undo_depth(4);
+        c.run().unwrap();
+        assert_eq!(c.history().len(), 4);
+        for _ in 0..4 {
+            assert!(c.step_back());
+        }
+        assert!(!c.step_back());
+        assert_eq!(c.registers.get(a1), 0);
+        assert_eq!(c.registers.get(a0), 5);
+
+        // 撤销 sc.w 也要还原 lr.w 的保留
+        let code = riscv_asm! {
+        _start:
+            li t0, 256;
+            lr.w a0, (t0);
+            sc.w a1, a0, (t0);          // 成功, a1 = 0
+            stop;
+        };
+        let mut c = EmulatorContext::default();
+        c.set_code_segment(code).set_undo_depth(8);
+        for _ in 0..3 {
+            c.step();
+        }
+        assert_eq!(c.registers.get(a1), 0);
+        assert!(c.step_back());
+        c.registers.write(a1, 1);
+        c.step();
+        assert_eq!(c.registers.get(a1), 0);
+
+        // 撤销也还原页表遍历置上的 A 和 D 位
+        let code = riscv_asm! {
+        _start:
+            j setup;
+        s_entry:                    // 0x04
+            li t0, 1;
+            slli t0, t0, 12;        // 虚拟 0x1000 -> 物理 0x6000
+            li t1, 7;
+            lw a0, 4(t3);           // 0x10, VPN 1 的 PTE, 还没有 A 和 D
+            sw t1, 0(t0);           // 页表遍历置上 A 和 D
+            lw a1, 4(t3);           // 0x18
+            stop;
+        setup:
+            li t0, 1;
+            slli t0, t0, 14;        // 根页表在 0x4000
+            li t2, 5;
+            slli t2, t2, 12;        // 二级页表在 0x5000
+            mv t3, t2;
+            li t1, 5;
+            slli t1, t1, 10;
+            ori t1, t1, 0x01;
+            sw t1, 0(t0);
+            li t1, 0x0F;
+            sw t1, 0(t2);           // VPN 0 -> 0x0000, R|W|X
+            li t1, 6;
+            slli t1, t1, 10;
+            ori t1, t1, 0x07;
+            sw t1, 4(t2);           // VPN 1 -> 0x6000, R|W
+            li t1, 5;
+            slli t1, t1, 10;
+            ori t1, t1, 0x07;
+            sw t1, 20(t2);          // VPN 5 -> 0x5000, R|W
+            li t1, 1;
+            slli t1, t1, 31;
+            ori t1, t1, 4;
+            csrw satp, t1;
+            li t1, 1;
+            slli t1, t1, 12;
+            csrc mstatus, t1;       // MPP = S
+            li t1, 4;
+            csrw mepc, t1;
+            mret;
+        };
+        let mut c = EmulatorContext::default();
+        c.set_code_segment(code).set_undo_depth(8).set_breakpoint(0x18);
+        assert_eq!(c.run(), Ok(StoppedAt::Breakpoint(0x18)));
+        assert_eq!(c.registers.get(a0), 0x1807);
+        assert!(c.step_back());
+        assert!(c.step_back());
+        c.remove_breakpoint(0x18);
+        assert_eq!(c.run(), Ok(StoppedAt::Halted));
+        assert_eq!(c.registers.get(a0), 0x1807);
+        assert_eq!(c.registers.get(a1), 0x18C7);
+    }
+
+    #[test]
+    fn test_self_modifying_code() {
+        let mut c = EmulatorContext::default();
+        let code = riscv_asm! {
+        _start:
+            li t1, 2;
+        L_loop:
+            addi a0, a0, 1;         // 第一轮执行后被改写
+            lw t0, 0(a1);
+            sw t0, 4(zero);
+            addi t1, t1, -1;
+            bne t1, zero, L_loop;
+            stop;
+        };
+        let patch = riscv_asm! {
+            addi a0, a0, 100;
+        };
+        c.set_code_segment(code).set_data_segment(patch);
+        c.registers.write(a1, (code.len() * 4) as u64);
+        assert_eq!(c.run(), Ok(StoppedAt::Halted));
+        assert_eq!(c.registers.get(a0), 101);
+    }
+
+    #[test]
+    fn test_fence() {
+        // 像引导程序一样把代码复制到 RAM 再跳过去
+        let code = riscv_asm! {
+        _start:
+            li t0, 256;
+            lw t1, 0(a1);
+            sw t1, 0(t0);
+            lw t1, 4(a1);
+            sw t1, 4(t0);
+            fence;
+            fence.i;
+            jalr ra, t0, 0;             // a0 = 7
+            lw t1, 8(a1);
+            sw t1, 0(t0);
+            fence.i;
+            jalr ra, t0, 0;             // a0 = 107
+            fence.tso;
+            pause;
+            stop;
+        };
+        let copied = riscv_asm! {
+            addi a0, a0, 7;
+            ret;
+            addi a0, a0, 100;
+        };
+        for mode in [ExecutionMode::Interpreter, ExecutionMode::Blocks] {
+            let mut c = EmulatorContext::default();
+            c.set_execution_mode(mode)
+                .set_code_segment(code)
+                .set_data_segment(copied);
+            c.registers.write(a1, (code.len() * 4) as u64);
+            assert_eq!(c.run(), Ok(StoppedAt::Halted));
+            assert_eq!(c.registers.get(a0), 107);
+        }
+        assert_eq!(Instruction(code[5]).to_string(), "fence");
+        assert_eq!(Instruction(code[6]).to_string(), "fence.i");
+        assert_eq!(Instruction(code[12]).to_string(), "fence.tso");
+        assert_eq!(Instruction(code[13]).to_string(), "pause");
+        assert_eq!(Instruction(0x0310_000F).to_string(), "fence   rw, w");
+    }
+
+    #[test]
+    fn test_block_execution() {
+        let code = riscv_asm! {
+        _start:
+            li s0, 256;             // 数组地址
+            li s1, 10;
+            li t0, 0;
+        L_fill:                     // a[i] = i * 7
+            slli t1, t0, 2;
+            add t1, t1, s0;
+            mv a0, t0;
+            li a1, 7;
+            call mul;
+            sw a0, 0(t1);
+            addi t0, t0, 1;
+            blt t0, s1, L_fill;
+            li t0, 0;
+            li a2, 0;
+        L_sum:
+            lw t1, 0(s0);
+            add a2, a2, t1;
+            addi s0, s0, 4;
+            addi t0, t0, 1;
+            bltu t0, s1, L_sum;
+            lui t2, 0x200C;
+            lw a3, -8(t2);          // mtime, 设备要跟上已执行的指令数
+            stop;
+        mul:
+            mv t3, a0;
+            li a0, 0;
+        L_mul:
+            beq a1, zero, L_mul_end;
+            add a0, a0, t3;
+            addi a1, a1, -1;
+            j L_mul;
+        L_mul_end:
+            ret;
+        };
+        let run = |mode, limit| {
+            let mut c = EmulatorContext::default();
+            c.set_code_segment(code).set_execution_mode(mode);
+            let report = c.run_for(limit);
+            (c, report)
+        };
+        let (interpreted, _) = run(ExecutionMode::Interpreter, u64::MAX);
+        let (blocks, report) = run(ExecutionMode::Blocks, u64::MAX);
+        assert_eq!(report.stopped, Ok(StoppedAt::Halted));
+        assert_eq!(blocks.registers.get(a2), 315);
+        assert_eq!(blocks.registers.get(a3), 456);
+        assert_eq!(blocks.snapshot(), interpreted.snapshot());
+
+        // 指令数限制落在块中间也要准确
+        let (interpreted, _) = run(ExecutionMode::Interpreter, 37);
+        let (blocks, report) = run(ExecutionMode::Blocks, 37);
+        assert_eq!(report.retired, 37);
+        assert_eq!(blocks.snapshot(), interpreted.snapshot());
+
+        // 第二轮才写入新指令, 第三轮要丢掉第二轮建好的块
+        let mut c = EmulatorContext::default();
+        let code = riscv_asm! {
+        _start:
+            li t1, 3;
+        L_loop:
+            addi a0, a0, 1;
+            lw t0, 0(a1);
+            sw t0, 4(zero);
+            addi a1, a1, 4;
+            addi t1, t1, -1;
+            bne t1, zero, L_loop;
+            stop;
+        };
+        let patches = riscv_asm! {
+            addi a0, a0, 1;
+            addi a0, a0, 100;
+            addi a0, a0, 100;
+        };
+        c.set_code_segment(code).set_data_segment(patches);
+        c.set_execution_mode(ExecutionMode::Blocks);
+        c.registers.write(a1, (code.len() * 4) as u64);
+        assert_eq!(c.run(), Ok(StoppedAt::Halted));
+        assert_eq!(c.registers.get(a0), 102);
+
+        // 中断在块之间进入
+        let mut c = EmulatorContext::default();
+        let code = riscv_asm! {
+        _start:
+            li t0, 48;
+            csrw mtvec, t0;
+            lui t2, 0x2004;
+            li t3, 100;
+            sw zero, 4(t2);
+            sw t3, 0(t2);           // mtimecmp = 100
+            li t0, 128;
+            csrw mie, t0;
+            csrsi mstatus, 8;
+        L_wait:
+            addi a0, a0, 1;
+            beq s1, zero, L_wait;
+            stop;
+        handler:
+            csrr a1, mcause;
+            csrw mie, zero;
+            li s1, 1;
+            mret;
+        };
+        c.set_code_segment(code).set_execution_mode(ExecutionMode::Blocks);
+        assert_eq!(c.run(), Ok(StoppedAt::Halted));
+        assert_eq!(c.registers.get(a1), 0x8000_0007);
+        assert!(c.registers.get(a0) > 40);
+
+        // 块里直接读写 RAM, 符号扩展后也只留低 32 位
+        let code = riscv_asm! {
+        _start:
+            li s0, 256;
+            li t0, -2;
+            sw t0, 0(s0);
+            sb t0, 5(s0);
+            sh t0, 6(s0);
+            lb a0, 5(s0);
+            lbu a1, 5(s0);
+            lh a2, 6(s0);
+            lhu a3, 6(s0);
+            lw a4, 4(s0);
+            auipc a5, 0;            // 0x28
+            lui a6, 0x80000;
+            stop;
+        };
+        let mut snapshots = Vec::new();
+        for mode in [ExecutionMode::Interpreter, ExecutionMode::Blocks] {
+            let mut c = EmulatorContext::default();
+            c.set_code_segment(code).set_execution_mode(mode);
+            assert_eq!(c.run(), Ok(StoppedAt::Halted));
+            assert_eq!(c.registers.get(a0), 0xFFFF_FFFE);
+            assert_eq!(c.registers.get(a1), 0xFE);
+            assert_eq!(c.registers.get(a2), 0xFFFF_FFFE);
+            assert_eq!(c.registers.get(a3), 0xFFFE);
+            assert_eq!(c.registers.get(a4), 0xFFFE_FE00);
+            assert_eq!(c.registers.get(a5), 0x28);
+            assert_eq!(c.registers.get(a6), 0x8000_0000);
+            snapshots.push(c.snapshot());
+        }
+        assert_eq!(snapshots[0], snapshots[1]);
+    }
+
+    #[test]
+    fn test_block_interrupt_enable() {
+        // 块里的 csrsi 打开中断后, 块模式也要在下一条指令前响应
+        let code = riscv_asm! {
+        _start:
+            li t0, 64;              // handler 的地址
+            csrw mtvec, t0;
+            lui t2, 0x200C;
+            lw t3, -8(t2);          // mtime
+            addi t3, t3, 200;
+            lui t1, 0x2004;         // mtimecmp
+            sw zero, 4(t1);
+            sw t3, 0(t1);           // mtimecmp = mtime + 200
+            li t0, 128;
+            csrw mie, t0;           // MTIE
+        L_loop:
+            csrci mstatus, 8;
+            addi a0, a0, 1;
+            csrsi mstatus, 8;       // 0x30
+            beq s1, zero, L_loop;
+            stop;
+            nop;
+        handler:
+            csrr a1, mcause;
+            csrr a2, mepc;
+            li t0, -1;
+            sw t0, 4(t1);           // 关闭定时器
+            li s1, 1;
+            mret;
+        };
+        for mode in [ExecutionMode::Interpreter, ExecutionMode::Blocks] {
+            let mut c = EmulatorContext::default();
+            c.set_execution_mode(mode).set_code_segment(code);
+            assert_eq!(c.run_for(100_000).stopped, Ok(StoppedAt::Halted));
+            assert_eq!(c.registers.get(s1), 1);
+            assert_eq!(c.registers.get(a1), 0x8000_0007);
+            assert_eq!(c.registers.get(a2), 0x34);
+        }
+    }
+
+    #[test]
+    fn test_rv64() {
+        let code = riscv_asm! {
+        _start:
+            li t0, -1;                  // 64 位全 1
+            srli t1, t0, 32;            // t1 = 0xFFFF_FFFF
+            slli t2, t1, 33;            // shamt 有 6 位
+            addiw a0, t1, 1;            // 低 32 位溢出成 0
+            sext.w a1, t1;
+            li s0, 256;
+            sd t2, 0(s0);
+            lw a2, 4(s0);               // 符号扩展
+            lwu a3, 4(s0);              // 零扩展
+            ld a4, 0(s0);
+            li t3, 7;
+            li t4, -2;
+            mulw a5, t1, t3;
+            divw a6, t3, t4;
+            srli s1, t0, 33;            // s1 = 0x7FFF_FFFF
+            c.addiw s1, 1;
+            c.sd s1, 8(s0);
+            ld a7, 8(s0);
+            c.ld s1, 0(s0);
+            csrr t5, misa;
+            srli t5, t5, 62;
+            stop;
+        };
+        let mut snapshots = Vec::new();
+        for mode in [ExecutionMode::Interpreter, ExecutionMode::Blocks] {
+            let mut c = EmulatorContext::default();
+            c.set_xlen(Xlen::X64).set_execution_mode(mode).set_code_segment(code);
+            assert_eq!(c.run(), Ok(StoppedAt::Halted));
+            assert_eq!(c.xlen(), Xlen::X64);
+            assert_eq!(c.registers.get(t0), u64::MAX);
+            assert_eq!(c.registers.get(t2), 0xFFFF_FFFE_0000_0000);
+            assert_eq!(c.registers.get(a0), 0);
+            assert_eq!(c.registers.get(a1), u64::MAX);
+            assert_eq!(c.registers.get(a2), 0xFFFF_FFFF_FFFF_FFFE);
+            assert_eq!(c.registers.get(a3), 0xFFFF_FFFE);
+            assert_eq!(c.registers.get(a4), 0xFFFF_FFFE_0000_0000);
+            assert_eq!(c.registers.get(a5), -7i64 as u64);
+            assert_eq!(c.registers.get(a6), -3i64 as u64);
+            assert_eq!(c.registers.get(a7), 0xFFFF_FFFF_8000_0000);
+            assert_eq!(c.registers.get(s1), 0xFFFF_FFFE_0000_0000);
+            assert_eq!(c.registers.get(t5), 2);
+            snapshots.push(c.snapshot());
+        }
+        assert_eq!(snapshots[0], snapshots[1]);
+        assert_eq!(Instruction(code[3]).display(Xlen::X64).to_string(), "addiw   a0, t1, 1");
+        assert_eq!(Instruction(code[6]).display(Xlen::X64).to_string(), "sd      t2, 0(fp)");
+
+        // RV32 上寄存器只有低 32 位, shamt 的第 6 位和 *w 指令都是非法的
+        let mut c = EmulatorContext::default();
+        let trap = c.set_code_segment(code).run().unwrap_err();
+        assert_eq!(c.registers.get(t0), 0xFFFF_FFFF);
+        assert_eq!(trap.pc, 4);
+        assert_eq!(trap.exception, Exception::IllegalInstruction(code[1]));
+        let trap = EmulatorContext::default()
+            .set_code_segment(&code[3..])
+            .run()
+            .unwrap_err();
+        assert_eq!(trap.exception, Exception::IllegalInstruction(code[3]));
+    }
+
+    #[test]
+    fn test_rv32e() {
+        // 只认 ilp32e 的寄存器名
+        let code = rv32e_asm! {
+        _start:
+            li a0, 3;
+            li a5, 4;
+            c.add a0, a5;               // a0 = 7
+            csrr a1, misa;
+            li t0, 93;                  // ilp32e 用 t0 传系统调用号
+            ecall;                      // exit(7)
+            stop;
+        };
+        for mode in [ExecutionMode::Interpreter, ExecutionMode::Blocks] {
+            let mut c = EmulatorContext::default();
+            c.set_embedded(true)
+                .set_execution_mode(mode)
+                .set_code_segment(code)
+                .set_syscall_handler(HostSyscalls::new(
+                    std::io::empty(),
+                    std::io::sink(),
+                    std::io::sink(),
+                ));
+            assert_eq!(c.run(), Ok(StoppedAt::Halted));
+            assert!(c.embedded());
+            assert_eq!(c.exit_code(), Some(7));
+            // misa 里是 E 不是 I
+            assert_eq!(c.registers.get(a1) >> 4 & 1, 1);
+            assert_eq!(c.registers.get(a1) >> 8 & 1, 0);
+        }
+
+        assert_eq!(alias::ilp32e::t(2), t2);
+        assert_eq!(alias::ilp32e::s(1), s1);
+        assert_eq!(alias::ilp32e::a(5), a5);
+
+        // 用到 x16..x31 的指令是非法指令, 压缩指令也一样. rv32e_asm! 编不出
+        // 这些指令, 只能用 riscv_asm!
+        let code = riscv_asm! {
+        _start:
+            li a0, 1;
+            add a0, a0, a6;
+            stop;
+        };
+        let compressed = riscv_asm! {
+        _start:
+            c.mv a0, s2;
+            c.nop;
+        };
+        for mode in [ExecutionMode::Interpreter, ExecutionMode::Blocks] {
+            let mut c = EmulatorContext::default();
+            c.set_embedded(true).set_execution_mode(mode).set_code_segment(code);
+            let trap = c.run().unwrap_err();
+            assert_eq!(trap.pc, 4);
+            assert_eq!(trap.exception, Exception::IllegalInstruction(code[1]));
+            assert_eq!(c.registers.get(a0), 1);
+
+            let mut c = EmulatorContext::default();
+            c.set_embedded(true).set_execution_mode(mode).set_code_segment(compressed);
+            let trap = c.run().unwrap_err();
+            assert_eq!(trap.exception, Exception::IllegalInstruction(compressed[0] & 0xFFFF));
+        }
+        // 没有 set_embedded 时照常执行
+        let mut c = EmulatorContext::default();
+        assert_eq!(c.set_code_segment(code).run(), Ok(StoppedAt::Halted));
+        assert_eq!(c.registers.get(a0), 1);
+    }
+
+    #[test]
+    fn test_sv32() {
+        let code = riscv_asm! {
+        _start:
+            j setup;
+        m_handler:                  // 0x04, 记下缺页并跳过那条指令
+            addi s1, s1, 1;
+            csrr s2, mcause;
+            csrr s3, mtval;
+            csrr t6, mepc;
+            addi t6, t6, 4;
+            csrw mepc, t6;
+            mret;
+        s_handler:                  // 0x20, 委托给 S 态的 trap
+            csrr s4, scause;
+            csrr s5, stval;
+            csrr s6, sstatus;
+            stop;
+        u_entry:                    // 0x30, 在虚拟地址 0x6030 执行
+            lw t3, 0(t4);           // U 态不能访问 S 页
+            ecall;
+        s_entry:                    // 0x38
+            li t0, 1;
+            slli t0, t0, 12;        // 虚拟 0x1000 -> 物理 0x6000
+            li t1, 42;
+            sw t1, 0(t0);
+            lw a0, 0(t0);           // a0 = 42
+            li t2, 3;
+            slli t2, t2, 12;        // 虚拟 0x3000 -> 物理 0x6000, 只可执行
+            lw a1, 0(t2);           // load page fault
+            mv a2, s2;
+            mv a3, s3;
+            li t1, 1;
+            slli t1, t1, 19;
+            csrs sstatus, t1;       // MXR = 1
+            lw a4, 0(t2);           // a4 = 42
+            li t2, 2;
+            slli t2, t2, 12;        // 虚拟 0x2000 -> 物理 0x7000, U 页
+            li t1, 7;
+            sw t1, 0(t2);           // SUM = 0, store page fault
+            mv a5, s2;
+            li t1, 1;
+            slli t1, t1, 18;
+            csrs sstatus, t1;       // SUM = 1
+            li t1, 7;
+            sw t1, 0(t2);
+            lw a6, 0(t2);           // a6 = 7
+            li t2, 5;
+            slli t2, t2, 12;        // 虚拟 0x5000 是二级页表本身
+            lw a7, 4(t2);           // VPN 1 的 PTE, A 和 D 已置位
+            lw s8, 0(t2);           // VPN 0 的 PTE, 只取过指令, 只有 A
+            li t1, 7;
+            slli t1, t1, 10;
+            ori t1, t1, 0x07;
+            sw t1, 4(t2);           // VPN 1 改为指向 0x7000
+            sfence.vma zero, zero;
+            lw s7, 0(t0);           // s7 = 7
+            li t1, 1;
+            slli t1, t1, 8;
+            csrc sstatus, t1;       // SPP = U
+            li t1, 6;
+            slli t1, t1, 12;
+            addi t1, t1, 48;
+            csrw sepc, t1;
+            mv t4, t0;
+            sret;
+        setup:
+            li t0, 1;
+            slli t0, t0, 14;        // 根页表在 0x4000
+            li t2, 5;
+            slli t2, t2, 12;        // 二级页表在 0x5000
+            li t1, 5;
+            slli t1, t1, 10;
+            ori t1, t1, 0x01;
+            sw t1, 0(t0);           // 根页表的第 0 项指向二级页表
+            li t1, 0x0B;
+            sw t1, 0(t2);           // VPN 0 -> 0x0000, R|X
+            li t1, 6;
+            slli t1, t1, 10;
+            ori t1, t1, 0x07;
+            sw t1, 4(t2);           // VPN 1 -> 0x6000, R|W
+            li t1, 7;
+            slli t1, t1, 10;
+            ori t1, t1, 0x17;
+            sw t1, 8(t2);           // VPN 2 -> 0x7000, R|W|U
+            li t1, 6;
+            slli t1, t1, 10;
+            ori t1, t1, 0x09;
+            sw t1, 12(t2);          // VPN 3 -> 0x6000, X
+            li t1, 5;
+            slli t1, t1, 10;
+            ori t1, t1, 0x07;
+            sw t1, 20(t2);          // VPN 5 -> 0x5000, R|W
+            li t1, 0x1B;
+            sw t1, 24(t2);          // VPN 6 -> 0x0000, R|X|U
+            li t1, 4;
+            csrw mtvec, t1;
+            li t1, 32;
+            csrw stvec, t1;
+            li t1, 256;
+            csrw medeleg, t1;       // U 态的 ecall 委托给 S 态
+            li t1, 1;
+            slli t1, t1, 31;
+            ori t1, t1, 4;
+            csrw satp, t1;          // Sv32, 根页表 PPN = 4
+            li t1, 1;
+            slli t1, t1, 12;
+            csrc mstatus, t1;       // MPP = S
+            li t1, 56;
+            csrw mepc, t1;
+            mret;
+        };
+        for mode in [ExecutionMode::Interpreter, ExecutionMode::Blocks] {
+            let mut c = EmulatorContext::default();
+            c.set_execution_mode(mode).set_code_segment(code);
+            assert_eq!(c.run(), Ok(StoppedAt::Halted));
+            assert_eq!(c.registers.get(a0), 42);
+            assert_eq!(c.registers.get(a1), 0);
+            assert_eq!(c.registers.get(a2), 13);
+            assert_eq!(c.registers.get(a3), 0x3000);
+            assert_eq!(c.registers.get(a4), 42);
+            assert_eq!(c.registers.get(a5), 15);
+            assert_eq!(c.registers.get(a6), 7);
+            assert_eq!(c.registers.get(a7), 0x18C7);
+            assert_eq!(c.registers.get(s8), 0x4B);
+            assert_eq!(c.registers.get(s7), 7);
+            // 三次缺页由 M 态处理, 最后一次是 U 态读 S 页
+            assert_eq!(c.registers.get(s1), 3);
+            assert_eq!(c.registers.get(s2), 13);
+            assert_eq!(c.registers.get(s3), 0x1000);
+            assert_eq!(c.registers.get(t3), 0);
+            // U 态的 ecall 进入 S 态的 handler, SPP = U
+            assert_eq!(c.registers.get(s4), 8);
+            assert_eq!(c.registers.get(s5), 0);
+            assert_eq!(c.registers.get(s6), 0xC0000);
+            assert_eq!(c.csr.read(SEPC), Some(0x6034));
+            assert_eq!(c.csr.privilege(), Privilege::Supervisor);
+        }
+
+        // S 态不能访问 M 态的 CSR, U 态不能执行 sret
+        let code = riscv_asm! {
+        _start:
+            li t1, 1;
+            slli t1, t1, 12;
+            csrc mstatus, t1;       // MPP = S
+            li t1, 24;
+            csrw mepc, t1;
+            mret;
+            csrr a0, mscratch;      // 0x18
+        };
+        let mut c = EmulatorContext::default();
+        let trap = c.set_code_segment(code).run().unwrap_err();
+        assert_eq!(trap.pc, 24);
+        assert_eq!(trap.exception, Exception::IllegalInstruction(code[6]));
+        let code = riscv_asm! {
+        _start:
+            li t1, 3;
+            slli t1, t1, 11;
+            csrc mstatus, t1;       // MPP = U
+            li t1, 24;
+            csrw mepc, t1;
+            mret;
+            sret;                   // 0x18
+        };
+        let mut c = EmulatorContext::default();
+        let trap = c.set_code_segment(code).run().unwrap_err();
+        assert_eq!(trap.exception, Exception::IllegalInstruction(code[6]));
+        assert_eq!(c.csr.privilege(), Privilege::User);
+
+        // RV64 只有 Bare, 写 satp 无效
+        let code = riscv_asm! {
+        _start:
+            li t1, -1;
+            csrw satp, t1;
+            stop;
+        };
+        let mut c = EmulatorContext::default();
+        c.set_xlen(Xlen::X64).set_code_segment(code);
+        assert_eq!(c.run(), Ok(StoppedAt::Halted));
+        assert_eq!(c.csr.read(SATP), Some(0));
+    }
+
+    #[test]
+    fn test_sv32_host_access() {
+        // 系统调用和 GDB 看到的都是虚拟地址
+        const CODE: &[u32] = riscv_asm! {
+        _start:
+            j setup;
+        s_entry:                    // 0x04
+            li a0, 1;
+            li a1, 1;
+            slli a1, a1, 12;        // 虚拟 0x1000 -> 物理 0x6000
+            li a2, 3;
+            li a7, 64;
+            ecall;                  // write(1, 0x1000, 3)
+            mv s1, a0;
+            li a0, 1;
+            li a1, 3;
+            slli a1, a1, 12;        // 虚拟 0x3000 没有映射
+            li a2, 3;
+            li a7, 64;
+            ecall;                  // -EFAULT
+            mv s2, a0;
+            li a0, 0;
+            li a1, 1;
+            slli a1, a1, 12;
+            addi a1, a1, 4;
+            li a2, 2;
+            li a7, 63;
+            ecall;                  // read(0, 0x1004, 2)
+            mv s3, a0;
+            lw s4, 0(a1);
+            stop;                   // 0x60
+        setup:
+            li t3, 6;
+            slli t3, t3, 12;
+            li t1, 0x68;
+            sb t1, 0(t3);
+            li t1, 0x69;
+            sb t1, 1(t3);
+            li t1, 0x0A;
+            sb t1, 2(t3);           // 物理 0x6000 = "hi\n"
+            li t0, 1;
+            slli t0, t0, 14;        // 根页表在 0x4000
+            li t2, 5;
+            slli t2, t2, 12;        // 二级页表在 0x5000
+            li t1, 5;
+            slli t1, t1, 10;
+            ori t1, t1, 0x01;
+            sw t1, 0(t0);
+            li t1, 0x0F;
+            sw t1, 0(t2);           // VPN 0 -> 0x0000, R|W|X
+            li t1, 6;
+            slli t1, t1, 10;
+            ori t1, t1, 0x07;
+            sw t1, 4(t2);           // VPN 1 -> 0x6000, R|W
+            li t1, 1;
+            slli t1, t1, 31;
+            ori t1, t1, 4;
+            csrw satp, t1;
+            li t1, 1;
+            slli t1, t1, 12;
+            csrc mstatus, t1;       // MPP = S
+            li t1, 4;
+            csrw mepc, t1;
+            mret;
+        };
+        for mode in [ExecutionMode::Interpreter, ExecutionMode::Blocks] {
+            let mut c = EmulatorContext::default();
+            let stdout = SharedBuffer::default();
+            c.set_execution_mode(mode)
+                .set_code_segment(CODE)
+                .set_syscall_handler(HostSyscalls::new(
+                    &b"ok"[..],
+                    stdout.clone(),
+                    std::io::sink(),
+                ));
+            assert_eq!(c.run(), Ok(StoppedAt::Halted));
+            assert_eq!(&stdout.0.lock().unwrap()[..], b"hi\n");
+            assert_eq!(c.registers.get(s1), 3);
+            assert_eq!(c.registers.get(s2), 0xFFFF_FFF2);      // -EFAULT
+            assert_eq!(c.registers.get(s3), 2);
+            assert_eq!(c.registers.get(s4), 0x6B6F);
+        }
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let mut c = EmulatorContext::default();
+            c.set_code_segment(CODE)
+                .set_syscall_handler(HostSyscalls::new(
+                    std::io::empty(),
+                    std::io::sink(),
+                    std::io::sink(),
+                ))
+                .serve_gdb(listener)
+        });
+        let mut gdb = TcpStream::connect(address).unwrap();
+        gdb.set_nodelay(true).unwrap();
+        let mut request = |packet: &str| gdb_request(&mut gdb, packet);
+        assert_eq!(request("Z0,60,4"), "OK");
+        assert_eq!(request("c"), "S05");
+        assert_eq!(request("m1000,3"), "68690a");
+        assert_eq!(request("m3000,4"), "E01");
+        assert_eq!(request("M1000,1:48"), "OK");
+        assert_eq!(request("m1000,3"), "48690a");
+        assert_eq!(request("M3000,1:48"), "E01");
+        assert_eq!(request("z0,60,4"), "OK");
+        assert_eq!(request("c"), "W00");
+        assert_eq!(request("D"), "OK");
+        server.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_delegated_ecall() {
+        // 委托给 S 态的 ecall 不交给宿主, 没有委托的照常是系统调用
+        let code = riscv_asm! {
+        _start:
+            j setup;
+        s_handler:                  // 0x04
+            csrr s4, scause;
+            li a0, 3;
+            li a7, 93;
+            ecall;                  // S 态的 ecall 没有委托, exit(3)
+            stop;
+        u_entry:                    // 0x18
+            li a0, 5;
+            li a7, 93;
+            ecall;                  // 委托给 S 态
+            stop;
+        setup:
+            li t1, 4;
+            csrw stvec, t1;
+            li t1, 256;
+            csrw medeleg, t1;       // U 态的 ecall 委托给 S 态
+            li t1, 3;
+            slli t1, t1, 11;
+            csrc mstatus, t1;       // MPP = U
+            li t1, 24;
+            csrw mepc, t1;
+            mret;
+        };
+        for mode in [ExecutionMode::Interpreter, ExecutionMode::Blocks] {
+            let mut c = EmulatorContext::default();
+            c.set_execution_mode(mode)
+                .set_code_segment(code)
+                .set_syscall_handler(HostSyscalls::new(
+                    std::io::empty(),
+                    std::io::sink(),
+                    std::io::sink(),
+                ));
+            assert_eq!(c.run(), Ok(StoppedAt::Halted));
+            assert_eq!(c.registers.get(s4), 8);
+            assert_eq!(c.csr.read(SEPC), Some(0x20));
+            assert_eq!(c.exit_code(), Some(3));
+        }
+    }
 }